@@ -0,0 +1,39 @@
+//! Generate pages redirecting from an entry's aliases to the entry.
+
+use crate::utils::escape_xml;
+
+/// Render a page redirecting to the absolute `url`, which is also the page's canonical URL.
+pub fn render(url: &str) -> String {
+    let mut escaped = String::new();
+    escape_xml(&mut escaped, url);
+
+    format!(
+        r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>Redirecting to {escaped}</title>
+<link rel="canonical" href="{escaped}">
+<meta name="robots" content="noindex">
+<meta http-equiv="refresh" content="0; url={escaped}">
+</head>
+<body>
+<p>This page has moved to <a href="{escaped}">{escaped}</a>.</p>
+</body>
+</html>
+"#
+    )
+}
+
+#[cfg(test)]
+mod test {
+    #[test]
+    fn render() {
+        let page = super::render("https://example.com/posts/fish&chips");
+
+        assert!(page.contains(r#"<link rel="canonical" href="https://example.com/posts/fish&amp;chips">"#));
+        assert!(
+            page.contains(r#"<meta http-equiv="refresh" content="0; url=https://example.com/posts/fish&amp;chips">"#)
+        );
+    }
+}
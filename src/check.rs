@@ -0,0 +1,114 @@
+//! Check the external links of a built site for dead links. This needs network access, so it is
+//! only done when asked for with `sprokkel check`.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// The absolute `http(s)` URLs linked to by `href` attributes in `html`, in order of appearance.
+fn external_links(html: &str) -> Vec<String> {
+    let mut links = vec![];
+
+    let mut rest = html;
+    while let Some(idx) = rest.find("href=") {
+        rest = &rest[idx + "href=".len()..];
+        let Some(quote) = rest.chars().next().filter(|&c| c == '"' || c == '\'') else {
+            continue;
+        };
+        let Some(end) = rest[1..].find(quote) else {
+            break;
+        };
+        let link = &rest[1..1 + end];
+        rest = &rest[1 + end + 1..];
+
+        if link.starts_with("http://") || link.starts_with("https://") {
+            links.push(link.replace("&amp;", "&"));
+        }
+    }
+
+    links
+}
+
+/// Check `url`, returning why it is dead if it is. Servers that do not allow `HEAD` requests are
+/// asked again with a `GET` request.
+fn check_url(agent: &ureq::Agent, url: &str) -> Result<(), String> {
+    let result = match agent.head(url).call() {
+        Err(ureq::Error::Status(405 | 501, _)) => agent.get(url).call(),
+        result => result,
+    };
+
+    match result {
+        Ok(_) => Ok(()),
+        Err(ureq::Error::Status(status, response)) => Err(format!("{status} {}", response.status_text())),
+        Err(ureq::Error::Transport(err)) => Err(err.to_string()),
+    }
+}
+
+/// Check every external link in the HTML files in `out_dir`, making at most `concurrency` requests
+/// at a time. Dead links are logged with the pages linking to them. Returns the number of dead
+/// links.
+pub fn check_external_links(out_dir: &Path, concurrency: usize, timeout: Duration) -> anyhow::Result<usize> {
+    let mut pages_by_link = BTreeMap::<String, Vec<PathBuf>>::new();
+    for entry in walkdir::WalkDir::new(out_dir) {
+        let entry = entry?;
+        if !entry.file_type().is_file() || entry.path().extension().map_or(true, |extension| extension != "html") {
+            continue;
+        }
+
+        let html = std::fs::read_to_string(entry.path())?;
+        let page = entry.path().strip_prefix(out_dir)?;
+        for link in external_links(&html) {
+            let pages = pages_by_link.entry(link).or_default();
+            if pages.last().map(PathBuf::as_path) != Some(page) {
+                pages.push(page.to_owned());
+            }
+        }
+    }
+    log::info!("Checking {} external links", pages_by_link.len());
+
+    let agent = ureq::AgentBuilder::new()
+        .timeout(timeout)
+        .user_agent(concat!("sprokkel/", env!("CARGO_PKG_VERSION")))
+        .build();
+
+    let links = Mutex::new(pages_by_link.iter());
+    let dead = Mutex::new(0);
+    std::thread::scope(|s| {
+        for _ in 0..concurrency.max(1) {
+            s.spawn(|| loop {
+                let Some((link, pages)) = links.lock().unwrap().next() else {
+                    break;
+                };
+                if let Err(reason) = check_url(&agent, link) {
+                    let pages = pages
+                        .iter()
+                        .map(|page| page.display().to_string())
+                        .collect::<Vec<_>>()
+                        .join(", ");
+                    log::warn!("Dead link {link} ({reason}), linked from {pages}");
+                    *dead.lock().unwrap() += 1;
+                }
+            });
+        }
+    });
+
+    Ok(dead.into_inner().unwrap())
+}
+
+#[cfg(test)]
+mod test {
+    #[test]
+    fn external_links() {
+        use super::external_links;
+
+        let html = r##"<a href="https://example.com/?a=1&amp;b=2">a</a>
+<link rel="stylesheet" href="/style.css">
+<a href='http://example.org'>b</a> <a href="#top">c</a> <a href="mailto:me@example.com">d</a>"##;
+
+        assert_eq!(
+            external_links(html),
+            vec!["https://example.com/?a=1&b=2", "http://example.org"]
+        );
+    }
+}
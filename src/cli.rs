@@ -24,6 +24,74 @@ pub struct BuildCommand {
     pub watch: bool,
     #[arg(short, long, default_value = "false")]
     pub develop: bool,
+    /// Build for an environment configured in sprokkel.toml, using its base URL
+    #[arg(long)]
+    pub env: Option<String>,
+    /// Include draft and non-released entries, even in production builds
+    #[arg(long, default_value = "false")]
+    pub drafts: bool,
+    /// Remove everything in the output directory before building
+    #[arg(long, default_value = "false")]
+    pub clean: bool,
+    /// Directory to write the built site to, overriding `out-dir` in sprokkel.toml
+    #[arg(long)]
+    pub out_dir: Option<PathBuf>,
+    #[arg(default_value = "./")]
+    pub path: PathBuf,
+}
+
+#[derive(Debug, Parser)]
+pub struct ServeCommand {
+    /// The port to serve the site on
+    #[arg(short, long, default_value = "8080")]
+    pub port: u16,
+    #[arg(short, long, default_value = "false")]
+    pub develop: bool,
+    /// Build for an environment configured in sprokkel.toml, using its base URL
+    #[arg(long)]
+    pub env: Option<String>,
+    /// Include draft and non-released entries, even in production builds
+    #[arg(long, default_value = "false")]
+    pub drafts: bool,
+    /// Remove everything in the output directory before building
+    #[arg(long, default_value = "false")]
+    pub clean: bool,
+    /// Directory to write the built site to, overriding `out-dir` in sprokkel.toml
+    #[arg(long)]
+    pub out_dir: Option<PathBuf>,
+    #[arg(default_value = "./")]
+    pub path: PathBuf,
+}
+
+#[derive(Debug, Parser)]
+pub struct InitCommand {
+    /// Create the site even if the directory is not empty, overwriting existing files
+    #[arg(long, default_value = "false")]
+    pub force: bool,
+    #[arg(default_value = "./")]
+    pub path: PathBuf,
+}
+
+#[derive(Debug, Parser)]
+pub struct CleanCommand {
+    /// Directory to remove, overriding `out-dir` in sprokkel.toml
+    #[arg(long)]
+    pub out_dir: Option<PathBuf>,
+    #[arg(default_value = "./")]
+    pub path: PathBuf,
+}
+
+#[derive(Debug, Parser)]
+pub struct CheckCommand {
+    /// The number of links to check at the same time
+    #[arg(long, default_value = "8")]
+    pub concurrency: usize,
+    /// Seconds to wait for a response before considering a link dead
+    #[arg(long, default_value = "10")]
+    pub timeout: u64,
+    /// Directory of the built site to check, overriding `out-dir` in sprokkel.toml
+    #[arg(long)]
+    pub out_dir: Option<PathBuf>,
     #[arg(default_value = "./")]
     pub path: PathBuf,
 }
@@ -31,7 +99,14 @@ pub struct BuildCommand {
 #[derive(Debug, Subcommand)]
 pub enum Commands {
     Build(BuildCommand),
-    // Init,
+    /// Build the site every time it changes, and serve it over HTTP
+    Serve(ServeCommand),
+    /// Create a minimal site to start from
+    Init(InitCommand),
+    /// Remove the output directory
+    Clean(CleanCommand),
+    /// Check the external links of the built site for dead links
+    Check(CheckCommand),
 }
 
 #[derive(Debug, Parser)]
@@ -1,14 +1,350 @@
+use std::{collections::HashMap, path::PathBuf};
+
+#[derive(serde::Deserialize, PartialEq, Eq, Clone, Copy, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub enum LinkStyle {
+    /// e.g., `https://example.com/posts/foo`
+    Absolute,
+    /// e.g., `/posts/foo`
+    RelativeRoot,
+}
+
 #[derive(serde::Deserialize, PartialEq, Eq, Default)]
 #[serde(rename_all = "kebab-case", deny_unknown_fields)]
 pub struct Links {
     pub trim_index_html: Option<bool>,
+    /// The style of the URLs of pages, such as permalinks. Feeds and the sitemap always use
+    /// absolute URLs. Defaults to absolute.
+    pub style: Option<LinkStyle>,
+    /// Output path of the pages after the first of a paginated template, relative to the template's
+    /// directory. `{name}` is the template's file stem, `{extension}` its extension including the
+    /// dot and `{page}` the page number. Defaults to `{name}-{page}{extension}`.
+    pub pagination_path: Option<String>,
+}
+
+/// Site-level metadata, available to all templates.
+#[derive(serde::Deserialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Site {
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub author: Option<String>,
+    /// Whether to generate a `robots.txt`. Defaults to false.
+    pub robots: Option<bool>,
+    /// The rules of the `robots.txt`. Defaults to allowing all crawlers everywhere.
+    pub robots_rules: Option<String>,
+}
+
+#[derive(serde::Deserialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Highlight {
+    /// Number the lines of all code blocks. Defaults to false, in which case only code blocks
+    /// with a `numberLines` class or attribute are numbered.
+    pub line_numbers: Option<bool>,
+    /// Prefix of the classes of syntax highlighting spans, e.g., `hl-` results in classes such as
+    /// `hl-keyword`. Defaults to no prefix.
+    pub class_prefix: Option<String>,
 }
 
-#[derive(serde::Deserialize, PartialEq, Eq)]
+#[derive(serde::Deserialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Images {
+    /// The widths to generate resized image variants at, for use in `srcset`. Defaults to
+    /// `[1536, 768]`.
+    pub widths: Option<Vec<u32>>,
+    /// Alternative formats to additionally encode images in, in order of preference. Defaults to
+    /// `["webp"]`.
+    pub formats: Option<Vec<ImageFormat>>,
+    /// Remove metadata, such as EXIF, from images. Defaults to true.
+    pub strip_metadata: Option<bool>,
+    /// Rotate and flip the pixels of images according to their EXIF orientation, as the orientation
+    /// is lost when stripping metadata or re-encoding. Defaults to true.
+    pub apply_orientation: Option<bool>,
+    /// Encode quality of JPEG and AVIF images, from 1 to 100. It does not apply to WebP images,
+    /// which are encoded losslessly. Defaults to 75 for JPEG and 80 for AVIF.
+    #[serde(default, deserialize_with = "quality")]
+    pub quality: Option<u8>,
+    /// Encode quality per lossy format, overriding `quality`.
+    #[serde(default)]
+    pub format_quality: FormatQuality,
+}
+
+#[derive(serde::Deserialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct FormatQuality {
+    #[serde(default, deserialize_with = "quality")]
+    pub jpeg: Option<u8>,
+    #[serde(default, deserialize_with = "quality")]
+    pub avif: Option<u8>,
+    /// WebP images are encoded losslessly, so setting their quality is an error.
+    #[serde(rename = "webp", default, deserialize_with = "lossless_quality")]
+    _webp: (),
+}
+
+fn quality<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<Option<u8>, D::Error> {
+    let quality = <u8 as serde::Deserialize>::deserialize(deserializer)?;
+    if !(1..=100).contains(&quality) {
+        return Err(serde::de::Error::custom(format!(
+            "image quality must be from 1 to 100, got {quality}"
+        )));
+    }
+    Ok(Some(quality))
+}
+
+fn lossless_quality<'de, D: serde::Deserializer<'de>>(_: D) -> Result<(), D::Error> {
+    Err(serde::de::Error::custom(
+        "WebP images are encoded losslessly, their quality cannot be set",
+    ))
+}
+
+/// How headings with an ID link to themselves.
+#[derive(serde::Deserialize, PartialEq, Eq, Clone, Copy, Debug, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum HeadingAnchors {
+    /// e.g., `<h2><a href="#id">Heading</a></h2>`
+    #[default]
+    Wrap,
+    /// e.g., `<h2><a class="anchor" href="#id" aria-hidden="true">#</a>Heading</h2>`
+    Prepend,
+    /// e.g., `<h2>Heading</h2>`
+    None,
+}
+
+#[derive(serde::Deserialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Markup {
+    /// Fail entries with footnote references without definitions, instead of only warning.
+    /// Defaults to false.
+    pub strict_footnotes: Option<bool>,
+    /// Classes of divs to render as callouts. Defaults to `["note", "tip", "important",
+    /// "warning", "caution"]`.
+    pub callouts: Option<Vec<String>>,
+    /// Formats of Djot raw blocks and inlines to pass through into the HTML as-is, in addition to
+    /// `html`, e.g., `["svg"]`. Defaults to none.
+    pub raw_formats: Option<Vec<String>>,
+    /// How headings link to themselves. Defaults to wrapping the heading's text in a link.
+    pub heading_anchors: Option<HeadingAnchors>,
+}
+
+/// Names of the site's source directories, relative to the site.
+#[derive(serde::Deserialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Dirs {
+    /// Defaults to `entries`.
+    pub entries: Option<PathBuf>,
+    /// Defaults to `templates`.
+    pub templates: Option<PathBuf>,
+    /// Defaults to `assets`.
+    pub assets: Option<PathBuf>,
+    /// Defaults to `cat`.
+    pub cat: Option<PathBuf>,
+}
+
+#[derive(serde::Deserialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Assets {
+    /// Additionally write static assets under a name including a hash of their content, for
+    /// cache-busting. Defaults to false.
+    pub fingerprint: Option<bool>,
+}
+
+#[derive(serde::Deserialize, PartialEq, Eq, Clone, Copy, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub enum ImageFormat {
+    Webp,
+    /// Only available when built with the `avif` feature.
+    Avif,
+}
+
+#[derive(serde::Deserialize, PartialEq, Eq, Clone, Copy, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub enum Compression {
+    Gzip,
+    Brotli,
+}
+
+#[derive(serde::Deserialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Build {
+    /// Write compressed copies of text-like output files, for servers serving precompressed
+    /// files. Defaults to none.
+    pub precompress: Option<Vec<Compression>>,
+}
+
+/// How LaTeX math is rendered. Renderers are only available if sprokkel is built with their
+/// feature.
+#[derive(serde::Deserialize, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub enum MathRenderer {
+    /// MathML rendered with KaTeX, requires the `katex` feature.
+    Katex,
+    /// MathML rendered with latex2mathml, requires the `latex2mathml` feature.
+    Mathml,
+    /// The LaTeX is written as text.
+    None,
+}
+
+impl Default for MathRenderer {
+    /// The first renderer sprokkel is built with, if any.
+    fn default() -> Self {
+        if cfg!(feature = "katex") {
+            MathRenderer::Katex
+        } else if cfg!(feature = "latex2mathml") {
+            MathRenderer::Mathml
+        } else {
+            MathRenderer::None
+        }
+    }
+}
+
+#[derive(serde::Deserialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Math {
+    /// Defaults to `katex` if sprokkel is built with it, otherwise to `mathml` if sprokkel is built
+    /// with it, and otherwise to `none`.
+    pub renderer: Option<MathRenderer>,
+}
+
+#[derive(serde::Deserialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Security {
+    /// Remove scripts, event handlers, `javascript:` URLs and other unsafe markup from raw HTML in
+    /// entries. Defaults to false.
+    pub sanitize_html: Option<bool>,
+}
+
+#[derive(serde::Deserialize, PartialEq, Eq, Clone, Copy, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub enum SortOrder {
+    Ascending,
+    Descending,
+}
+
+#[derive(serde::Deserialize, PartialEq, Eq, Clone, Copy, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub enum FeedFormat {
+    Rss,
+    Atom,
+    Json,
+}
+
+fn default_feed_formats() -> Vec<FeedFormat> {
+    vec![FeedFormat::Rss]
+}
+
+/// Feed configuration of an entry group.
+#[derive(serde::Deserialize, PartialEq, Eq, Clone, Debug)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Feed {
+    /// Defaults to the group name.
+    pub title: Option<String>,
+    pub description: Option<String>,
+    /// The feed formats to generate. Defaults to only RSS.
+    #[serde(default = "default_feed_formats")]
+    pub formats: Vec<FeedFormat>,
+    /// Path of the RSS feed relative to the output directory. Defaults to `<group>/rss.xml`.
+    pub rss_path: Option<PathBuf>,
+    /// Path of the Atom feed relative to the output directory. Defaults to `<group>/atom.xml`.
+    pub atom_path: Option<PathBuf>,
+    /// Path of the JSON feed relative to the output directory. Defaults to `<group>/feed.json`.
+    pub json_path: Option<PathBuf>,
+}
+
+/// A named environment, selected with `--env`.
+#[derive(serde::Deserialize, PartialEq, Eq, Clone, Debug)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Env {
+    /// Used instead of `base-url` and `base-url-develop`.
+    pub base_url: String,
+}
+
+/// Configuration of how the files of a `cat` directory are concatenated, read from the directory's
+/// `cat.toml`.
+#[derive(serde::Deserialize, PartialEq, Eq, Default, Debug)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Cat {
+    /// Globs of the file names to concatenate. Files are concatenated in the order of the first
+    /// glob they match, and by name for files matching the same glob. Defaults to `["*"]`.
+    pub include: Option<Vec<String>>,
+    /// Globs of file names not to concatenate, even if included. Defaults to none.
+    pub exclude: Option<Vec<String>>,
+    /// Extension of the output file, replacing the extension of the directory's name.
+    pub extension: Option<String>,
+}
+
+#[derive(serde::Deserialize, PartialEq)]
 #[serde(rename_all = "kebab-case", deny_unknown_fields)]
 pub struct SiteConfig {
     pub base_url: String,
     pub base_url_develop: String,
     #[serde(default)]
+    pub site: Site,
+    #[serde(default)]
     pub links: Links,
+    #[serde(default)]
+    pub highlight: Highlight,
+    #[serde(default)]
+    pub markup: Markup,
+    #[serde(default)]
+    pub images: Images,
+    #[serde(default)]
+    pub assets: Assets,
+    #[serde(default)]
+    pub build: Build,
+    #[serde(default)]
+    pub math: Math,
+    #[serde(default)]
+    pub security: Security,
+    /// Feeds to generate, keyed by entry group name.
+    #[serde(default)]
+    pub feeds: HashMap<String, Feed>,
+    /// Whether to generate a `sitemap.xml`. Defaults to true.
+    pub sitemap: Option<bool>,
+    /// Order of the entries within a group by date. Undated entries come after dated entries, and
+    /// entries with the same date are ordered by slug. Defaults to descending, i.e., newest first.
+    pub entry_order: Option<SortOrder>,
+    /// Reading speed used to estimate entries' reading time. Defaults to 200.
+    pub words_per_minute: Option<u32>,
+    /// UTC offset of entry dates and times without one, e.g., `+02:00`. Defaults to UTC.
+    pub timezone: Option<crate::types::UtcOffset>,
+    #[serde(default)]
+    pub dirs: Dirs,
+    /// Directory to write the built site to, relative to the site directory. Defaults to `out` in
+    /// the working directory.
+    pub out_dir: Option<PathBuf>,
+    /// Named environments, keyed by name.
+    #[serde(default)]
+    pub env: HashMap<String, Env>,
+    /// The name of the environment selected with [`SiteConfig::select_env`].
+    #[serde(skip)]
+    pub selected_env: Option<String>,
+    /// Arbitrary site-wide variables, available in templates as `site.extra`.
+    #[serde(default)]
+    pub extra: HashMap<String, minijinja::Value>,
+}
+
+impl SiteConfig {
+    /// Build for the environment named `name`, using its base URL for both production and develop
+    /// builds.
+    pub fn select_env(&mut self, name: &str) -> anyhow::Result<()> {
+        let Some(env) = self.env.get(name) else {
+            anyhow::bail!("Environment \"{name}\" is not configured in sprokkel.toml");
+        };
+        self.base_url = env.base_url.clone();
+        self.base_url_develop = env.base_url.clone();
+        self.selected_env = Some(name.to_owned());
+        Ok(())
+    }
+
+    /// Parse the site configuration `config` in tests. The base URLs default to
+    /// `http://localhost:8080` and `..`.
+    #[cfg(test)]
+    pub fn for_test(config: &str) -> Result<Self, toml::de::Error> {
+        let mut config: toml::Table = toml::from_str(config)?;
+        config
+            .entry("base-url")
+            .or_insert_with(|| "http://localhost:8080".into());
+        config.entry("base-url-develop").or_insert_with(|| "..".into());
+        toml::Value::Table(config).try_into()
+    }
 }
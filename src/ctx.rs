@@ -1,11 +1,49 @@
-use std::{path::Path, sync::Arc};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 
-use crate::{cli::BuildKind, config::SiteConfig, utils};
+use crate::{
+    cli::BuildKind,
+    config::{Compression, Feed, ImageFormat, LinkStyle, MathRenderer, SiteConfig, SortOrder},
+    images::Quality,
+    ir_markup::HtmlOptions,
+    robots,
+    types::{SiteMeta, UtcOffset},
+    utils,
+};
 
 struct InnerCtx {
     build_kind: BuildKind,
     base_url: String,
+    base_path: String,
+    env: String,
+    site: SiteMeta,
     trim_index_html: bool,
+    link_style: LinkStyle,
+    pagination_path: String,
+    html_options: HtmlOptions,
+    image_widths: Vec<u32>,
+    image_formats: Vec<ImageFormat>,
+    strip_image_metadata: bool,
+    apply_image_orientation: bool,
+    image_quality: Quality,
+    feeds: HashMap<String, Feed>,
+    sitemap: bool,
+    robots_rules: Option<String>,
+    entry_order: SortOrder,
+    words_per_minute: u32,
+    utc_offset: UtcOffset,
+    strict_footnotes: bool,
+    callouts: Vec<String>,
+    raw_formats: Vec<String>,
+    fingerprint_assets: bool,
+    precompress: Vec<Compression>,
+    entries_dir: PathBuf,
+    templates_dir: PathBuf,
+    assets_dir: PathBuf,
+    cat_dir: PathBuf,
 }
 
 /// Site build context. The context is cheap to clone.
@@ -15,17 +53,116 @@ pub struct Ctx {
 }
 
 impl Ctx {
+    /// A production build context in tests, see [`SiteConfig::for_test`].
+    #[cfg(test)]
+    pub fn for_test(config: &str) -> Self {
+        Ctx::from_site_config(BuildKind::Production, &SiteConfig::for_test(config).unwrap())
+    }
+
     pub fn from_site_config(build_kind: BuildKind, site_config: &SiteConfig) -> Self {
         let base_url = if build_kind.is_production() {
             &site_config.base_url
         } else {
             &site_config.base_url_develop
         };
+        let base_path = utils::url_path(base_url).unwrap_or("").trim_end_matches('/').to_owned();
+        let image_widths = {
+            let mut widths = site_config.images.widths.clone().unwrap_or(vec![1536, 768]);
+            widths.sort_unstable_by(|a, b| b.cmp(a));
+            widths.dedup();
+            widths
+        };
+        let image_formats = {
+            let mut formats = site_config.images.formats.clone().unwrap_or(vec![ImageFormat::Webp]);
+            if cfg!(not(feature = "avif")) && formats.contains(&ImageFormat::Avif) {
+                log::warn!("AVIF images are configured, but sprokkel was built without the `avif` feature");
+                formats.retain(|format| *format != ImageFormat::Avif);
+            }
+            formats
+        };
+        let image_quality = {
+            let images = &site_config.images;
+            let quality =
+                |format_quality: Option<u8>, default: u8| format_quality.or(images.quality).unwrap_or(default);
+            Quality {
+                jpeg: quality(images.format_quality.jpeg, 75),
+                avif: quality(images.format_quality.avif, 80),
+            }
+        };
+        let math_renderer = match site_config.math.renderer {
+            Some(MathRenderer::Katex) if cfg!(not(feature = "katex")) => {
+                log::warn!("The katex math renderer is configured, but sprokkel was built without the `katex` feature");
+                MathRenderer::None
+            }
+            Some(MathRenderer::Mathml) if cfg!(not(feature = "latex2mathml")) => {
+                log::warn!(
+                    "The mathml math renderer is configured, but sprokkel was built without the `latex2mathml` feature"
+                );
+                MathRenderer::None
+            }
+            renderer => renderer.unwrap_or_default(),
+        };
         Ctx {
             inner: Arc::new(InnerCtx {
                 build_kind,
                 base_url: base_url.clone(),
+                base_path: base_path.clone(),
+                env: site_config.selected_env.clone().unwrap_or_else(|| match build_kind {
+                    BuildKind::Production => "production".to_owned(),
+                    BuildKind::Develop => "develop".to_owned(),
+                }),
+                site: SiteMeta {
+                    title: site_config.site.title.clone().unwrap_or_default(),
+                    description: site_config.site.description.clone().unwrap_or_default(),
+                    author: site_config.site.author.clone().unwrap_or_default(),
+                    extra: site_config.extra.clone(),
+                },
                 trim_index_html: site_config.links.trim_index_html.unwrap_or(true),
+                link_style: site_config.links.style.unwrap_or(LinkStyle::Absolute),
+                pagination_path: site_config
+                    .links
+                    .pagination_path
+                    .clone()
+                    .unwrap_or_else(|| "{name}-{page}{extension}".to_owned()),
+                html_options: HtmlOptions {
+                    line_numbers: site_config.highlight.line_numbers.unwrap_or(false),
+                    highlight_class_prefix: site_config.highlight.class_prefix.clone().unwrap_or_default(),
+                    site_host: utils::url_host(&site_config.base_url).map(str::to_owned),
+                    base_path,
+                    sanitize_html: site_config.security.sanitize_html.unwrap_or(false),
+                    heading_anchors: site_config.markup.heading_anchors.unwrap_or_default(),
+                    math_renderer,
+                },
+                image_widths,
+                image_formats,
+                strip_image_metadata: site_config.images.strip_metadata.unwrap_or(true),
+                apply_image_orientation: site_config.images.apply_orientation.unwrap_or(true),
+                image_quality,
+                feeds: site_config.feeds.clone(),
+                sitemap: site_config.sitemap.unwrap_or(true),
+                robots_rules: site_config.site.robots.unwrap_or(false).then(|| {
+                    site_config
+                        .site
+                        .robots_rules
+                        .clone()
+                        .unwrap_or_else(|| robots::DEFAULT_RULES.to_owned())
+                }),
+                entry_order: site_config.entry_order.unwrap_or(SortOrder::Descending),
+                words_per_minute: site_config.words_per_minute.unwrap_or(200).max(1),
+                utc_offset: site_config.timezone.unwrap_or_default(),
+                strict_footnotes: site_config.markup.strict_footnotes.unwrap_or(false),
+                callouts: site_config.markup.callouts.clone().unwrap_or_else(|| {
+                    ["note", "tip", "important", "warning", "caution"]
+                        .map(str::to_owned)
+                        .to_vec()
+                }),
+                raw_formats: site_config.markup.raw_formats.clone().unwrap_or_default(),
+                fingerprint_assets: site_config.assets.fingerprint.unwrap_or(false),
+                precompress: site_config.build.precompress.clone().unwrap_or_default(),
+                entries_dir: site_config.dirs.entries.clone().unwrap_or("entries".into()),
+                templates_dir: site_config.dirs.templates.clone().unwrap_or("templates".into()),
+                assets_dir: site_config.dirs.assets.clone().unwrap_or("assets".into()),
+                cat_dir: site_config.dirs.cat.clone().unwrap_or("cat".into()),
             }),
         }
     }
@@ -38,6 +175,143 @@ impl Ctx {
         &self.inner.base_url
     }
 
+    /// The name of the environment built for: the environment selected with `--env`, otherwise
+    /// `production` or `develop`.
+    pub fn env(&self) -> &str {
+        &self.inner.env
+    }
+
+    /// The path the site is hosted under, e.g., `/blog` for base URL `https://example.com/blog`.
+    /// Empty if the site is hosted at the root.
+    pub fn base_path(&self) -> &str {
+        &self.inner.base_path
+    }
+
+    /// Prefix a root-relative path with the base path, e.g., `/style.css` becomes `/blog/style.css`.
+    /// Other paths are returned as-is.
+    pub fn prepend_base_path(&self, path: &str) -> String {
+        if path.starts_with('/') && !path.starts_with("//") {
+            format!("{}{path}", self.base_path())
+        } else {
+            path.to_owned()
+        }
+    }
+
+    /// Site-level metadata.
+    pub fn site(&self) -> &SiteMeta {
+        &self.inner.site
+    }
+
+    /// Options for rendering entry markup to HTML.
+    pub fn html_options(&self) -> &HtmlOptions {
+        &self.inner.html_options
+    }
+
+    /// Output path pattern of the pages after the first of a paginated template.
+    pub fn pagination_path(&self) -> &str {
+        &self.inner.pagination_path
+    }
+
+    /// The widths to generate resized image variants at, from largest to smallest.
+    pub fn image_widths(&self) -> &[u32] {
+        &self.inner.image_widths
+    }
+
+    /// Alternative formats to encode images in, in order of preference.
+    pub fn image_formats(&self) -> &[ImageFormat] {
+        &self.inner.image_formats
+    }
+
+    /// Whether metadata, such as EXIF, is removed from images.
+    pub fn strip_image_metadata(&self) -> bool {
+        self.inner.strip_image_metadata
+    }
+
+    /// Whether the EXIF orientation of images is applied to their pixels.
+    pub fn apply_image_orientation(&self) -> bool {
+        self.inner.apply_image_orientation
+    }
+
+    /// Encode quality of lossy image formats.
+    pub fn image_quality(&self) -> Quality {
+        self.inner.image_quality
+    }
+
+    /// Feed configurations, keyed by entry group name.
+    pub fn feeds(&self) -> &HashMap<String, Feed> {
+        &self.inner.feeds
+    }
+
+    /// Whether a sitemap should be generated.
+    pub fn sitemap(&self) -> bool {
+        self.inner.sitemap
+    }
+
+    /// The rules of the `robots.txt` to generate, if one should be generated.
+    pub fn robots_rules(&self) -> Option<&str> {
+        self.inner.robots_rules.as_deref()
+    }
+
+    /// Order of the entries within a group by date.
+    pub fn entry_order(&self) -> SortOrder {
+        self.inner.entry_order
+    }
+
+    /// Reading speed used to estimate entries' reading time.
+    pub fn words_per_minute(&self) -> u32 {
+        self.inner.words_per_minute
+    }
+
+    /// UTC offset of entry dates and times without one.
+    pub fn utc_offset(&self) -> UtcOffset {
+        self.inner.utc_offset
+    }
+
+    /// Whether footnote references without definitions fail an entry.
+    pub fn strict_footnotes(&self) -> bool {
+        self.inner.strict_footnotes
+    }
+
+    /// Classes of divs to render as callouts.
+    pub fn callouts(&self) -> &[String] {
+        &self.inner.callouts
+    }
+
+    /// Formats of Djot raw blocks and inlines passed through into the HTML, in addition to HTML.
+    pub fn raw_formats(&self) -> &[String] {
+        &self.inner.raw_formats
+    }
+
+    /// Whether static assets are additionally written under fingerprinted names.
+    pub fn fingerprint_assets(&self) -> bool {
+        self.inner.fingerprint_assets
+    }
+
+    /// The encodings to write compressed copies of text-like output files in.
+    pub fn precompress(&self) -> &[Compression] {
+        &self.inner.precompress
+    }
+
+    /// The directory of entries, relative to the site.
+    pub fn entries_dir(&self) -> &Path {
+        &self.inner.entries_dir
+    }
+
+    /// The directory of templates, relative to the site.
+    pub fn templates_dir(&self) -> &Path {
+        &self.inner.templates_dir
+    }
+
+    /// The directory of static assets, relative to the site.
+    pub fn assets_dir(&self) -> &Path {
+        &self.inner.assets_dir
+    }
+
+    /// The directory of assets to concatenate, relative to the site.
+    pub fn cat_dir(&self) -> &Path {
+        &self.inner.cat_dir
+    }
+
     /// Turn a path relative to the output directory into an absolute URL.
     pub fn path_to_absolute_url(&self, path: impl AsRef<Path>) -> anyhow::Result<String> {
         let mut url = utils::path_to_url(Some(self.base_url()), path)?;
@@ -46,26 +320,114 @@ impl Ctx {
         }
         Ok(url)
     }
+
+    /// Turn a path relative to the output directory into a URL in the configured link style.
+    pub fn path_to_url(&self, path: impl AsRef<Path>) -> anyhow::Result<String> {
+        match self.inner.link_style {
+            LinkStyle::Absolute => self.path_to_absolute_url(path),
+            LinkStyle::RelativeRoot => {
+                let mut url = utils::path_to_url(Some(self.base_path()), path)?;
+                if self.inner.trim_index_html && url.ends_with("/index.html") {
+                    url.truncate(url.len() - "/index.html".len());
+                }
+                if url.is_empty() {
+                    url.push('/');
+                }
+                Ok(url)
+            }
+        }
+    }
 }
 
 #[cfg(test)]
 mod test {
+    #[test]
+    fn image_quality() {
+        use super::{Ctx, SiteConfig};
+        use crate::images::Quality;
+
+        let quality = |config: &str| Ctx::for_test(config).image_quality();
+
+        assert_eq!(quality(""), Quality { jpeg: 75, avif: 80 });
+        assert_eq!(quality("[images]\nquality = 82"), Quality { jpeg: 82, avif: 82 });
+        assert_eq!(
+            quality("[images]\nquality = 100\n[images.format-quality]\navif = 1"),
+            Quality { jpeg: 100, avif: 1 }
+        );
+        assert!(SiteConfig::for_test("[images]\nquality = 0").is_err());
+        assert!(SiteConfig::for_test("[images.format-quality]\navif = 200").is_err());
+        assert!(SiteConfig::for_test("[images.format-quality]\nwebp = 90").is_err());
+    }
+
+    #[test]
+    fn math_renderer() {
+        use super::{Ctx, MathRenderer};
+
+        let renderer = |config: &str| Ctx::for_test(config).html_options().math_renderer;
+
+        assert_eq!(renderer(""), MathRenderer::default());
+        assert_eq!(renderer("[math]\nrenderer = \"none\""), MathRenderer::None);
+        let katex = if cfg!(feature = "katex") {
+            MathRenderer::Katex
+        } else {
+            MathRenderer::None
+        };
+        assert_eq!(renderer("[math]\nrenderer = \"katex\""), katex);
+    }
+
+    #[test]
+    fn site() {
+        use super::Ctx;
+
+        let ctx = Ctx::for_test(
+            r#"
+                [site]
+                title = "A site"
+
+                [extra]
+                analytics-id = "abc"
+                social = { mastodon = "https://example.com/@someone" }
+            "#,
+        );
+
+        assert_eq!(ctx.site().title, "A site");
+        assert_eq!(ctx.site().author, "");
+        assert_eq!(ctx.site().extra["analytics-id"].as_str(), Some("abc"));
+        assert_eq!(
+            ctx.site().extra["social"].get_attr("mastodon").unwrap().as_str(),
+            Some("https://example.com/@someone")
+        );
+    }
+
+    #[test]
+    fn dirs() {
+        use super::Ctx;
+        use std::path::Path;
+
+        let ctx = Ctx::for_test(
+            r#"
+                [dirs]
+                entries = "content"
+            "#,
+        );
+
+        assert_eq!(ctx.entries_dir(), Path::new("content"));
+        assert_eq!(ctx.templates_dir(), Path::new("templates"));
+        assert_eq!(ctx.assets_dir(), Path::new("assets"));
+        assert_eq!(ctx.cat_dir(), Path::new("cat"));
+    }
+
     #[test]
     fn path_to_absolute_url() {
-        use super::{BuildKind, Ctx, SiteConfig};
+        use super::Ctx;
         use std::path::PathBuf;
 
-        let site_config: SiteConfig = toml::from_str(
+        let ctx = Ctx::for_test(
             r#"
-                base-url = "http://localhost:8080"
-                base-url-develop = ".."
-
                 [links]
                 trim-index-html = true
             "#,
-        )
-        .unwrap();
-        let ctx = Ctx::from_site_config(BuildKind::Production, &site_config);
+        );
 
         assert_eq!(
             ctx.path_to_absolute_url("").unwrap(),
@@ -90,4 +452,94 @@ mod test {
             "http://localhost:8080/no-extension"
         );
     }
+
+    #[test]
+    fn env() {
+        use super::{BuildKind, Ctx, SiteConfig};
+
+        let mut site_config = SiteConfig::for_test(
+            r#"
+                base-url = "https://example.com"
+
+                [env.staging]
+                base-url = "https://staging.example.com"
+            "#,
+        )
+        .unwrap();
+
+        let ctx = Ctx::from_site_config(BuildKind::Production, &site_config);
+        assert_eq!(ctx.env(), "production");
+        assert_eq!(ctx.base_url(), "https://example.com");
+        let ctx = Ctx::from_site_config(BuildKind::Develop, &site_config);
+        assert_eq!(ctx.env(), "develop");
+        assert_eq!(ctx.base_url(), "..");
+
+        assert!(site_config.select_env("testing").is_err());
+        site_config.select_env("staging").unwrap();
+        let ctx = Ctx::from_site_config(BuildKind::Develop, &site_config);
+        assert_eq!(ctx.env(), "staging");
+        assert_eq!(ctx.base_url(), "https://staging.example.com");
+    }
+
+    #[test]
+    fn base_path() {
+        use super::{BuildKind, Ctx, SiteConfig};
+
+        let site_config = SiteConfig::for_test(
+            r#"
+                base-url = "https://example.com/blog/"
+
+                [links]
+                style = "relative-root"
+            "#,
+        )
+        .unwrap();
+
+        let ctx = Ctx::from_site_config(BuildKind::Production, &site_config);
+        assert_eq!(ctx.base_path(), "/blog");
+        assert_eq!(ctx.prepend_base_path("/style.css"), "/blog/style.css");
+        assert_eq!(ctx.prepend_base_path("style.css"), "style.css");
+        assert_eq!(
+            ctx.prepend_base_path("//example.org/style.css"),
+            "//example.org/style.css"
+        );
+        assert_eq!(ctx.path_to_url("posts/foo/index.html").unwrap(), "/blog/posts/foo");
+        assert_eq!(ctx.path_to_url("index.html").unwrap(), "/blog");
+
+        let ctx = Ctx::from_site_config(BuildKind::Develop, &site_config);
+        assert_eq!(ctx.base_path(), "");
+        assert_eq!(ctx.prepend_base_path("/style.css"), "/style.css");
+    }
+
+    #[test]
+    fn path_to_url() {
+        use super::Ctx;
+        use std::path::PathBuf;
+
+        let ctx = Ctx::for_test(
+            r#"
+                [links]
+                style = "relative-root"
+            "#,
+        );
+
+        assert_eq!(ctx.path_to_url("").unwrap(), "/");
+        assert_eq!(ctx.path_to_url("index.html").unwrap(), "/");
+        assert_eq!(
+            ctx.path_to_url(PathBuf::from("a").join("nested").join("index.html"))
+                .unwrap(),
+            "/a/nested"
+        );
+        assert_eq!(
+            ctx.path_to_url(PathBuf::from("a").join("file.xml")).unwrap(),
+            "/a/file.xml"
+        );
+        assert_eq!(
+            ctx.path_to_absolute_url(PathBuf::from("a").join("file.xml")).unwrap(),
+            "http://localhost:8080/a/file.xml"
+        );
+
+        let ctx = Ctx::for_test("");
+        assert_eq!(ctx.path_to_url("a/index.html").unwrap(), "http://localhost:8080/a");
+    }
 }
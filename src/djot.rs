@@ -7,6 +7,7 @@ use crate::ir_markup::{
     Alignment as IrAlignment, Attributes as IrAttributes, Container as IrContainer, ContainerEnd as IrContainerEnd,
     Event as IrEvent, ListKind as IrListKind, MathKind as IrMathKind, OrderedListNumbering as IrOrderedListNumbering,
 };
+use crate::{emoji, utils};
 
 /// Iterates from an Event::Start to a matching Event::End. The resulting iterator yields all
 /// events in between the start and end, skipping over the start and end itself. If the next item
@@ -53,11 +54,14 @@ fn render_to_raw_string<'a>(events: impl Iterator<Item = Event<'a>>) -> Cow<'a,
             Event::EnDash => string.to_mut().push('–'),
             Event::EmDash => string.to_mut().push('—'),
             Event::NonBreakingSpace => string.to_mut().push(' '),
-            Event::Symbol(val) => {
-                string.to_mut().push(':');
-                string.to_mut().push_str(val.as_ref());
-                string.to_mut().push(':')
-            }
+            Event::Symbol(val) => match emoji::emoji(&val) {
+                Some(emoji) => string.to_mut().push_str(emoji),
+                None => {
+                    string.to_mut().push(':');
+                    string.to_mut().push_str(val.as_ref());
+                    string.to_mut().push(':')
+                }
+            },
             _ => {}
         }
     }
@@ -109,8 +113,21 @@ impl Context {
     }
 }
 
-fn djot_to_ir<'s>(mut djot: impl Iterator<Item = Event<'s>>) -> impl Iterator<Item = IrEvent<'s>> {
+/// Whether raw blocks and inlines of `format` are passed through into the HTML. HTML always is,
+/// other formats only if they are in `raw_formats`.
+fn is_passed_through(format: &str, raw_formats: &[String]) -> bool {
+    matches!(format, "html" | "HTML")
+        || raw_formats
+            .iter()
+            .any(|raw_format| raw_format.eq_ignore_ascii_case(format))
+}
+
+fn djot_to_ir<'s>(
+    djot: impl Iterator<Item = Event<'s>>,
+    raw_formats: &'s [String],
+) -> impl Iterator<Item = IrEvent<'s>> {
     let mut ctx = Context::new();
+    let mut djot = utils::PutBack::new(djot);
 
     // to be replaced by `gen`-blocks
     genawaiter::rc::Gen::new(|co| async move {
@@ -215,10 +232,20 @@ fn djot_to_ir<'s>(mut djot: impl Iterator<Item = Event<'s>>) -> impl Iterator<It
                     })
                     .await
                 }
-                Event::Start(Container::Div { class: _ }, attributes) => {
+                Event::Start(Container::Div { class }, attributes) => {
+                    // The class of `::: class` comes before classes set as attributes
+                    let mut attributes: IrAttributes = attributes.into();
+                    if !class.is_empty() {
+                        let classes = attributes.remove("class");
+                        attributes.insert("class", class);
+                        if let Some(classes) = classes {
+                            attributes.insert("class", classes);
+                        }
+                    }
+
                     co.yield_(IrEvent::Start {
                         container: IrContainer::Div,
-                        attributes: attributes.into(),
+                        attributes,
                     })
                     .await
                 }
@@ -292,23 +319,27 @@ fn djot_to_ir<'s>(mut djot: impl Iterator<Item = Event<'s>>) -> impl Iterator<It
 
                 Event::Start(Container::RawInline { format }, attributes) => {
                     let content = render_to_raw_string(iter_container_from_inside(&mut djot));
-                    if matches!(format, "html" | "HTML") {
+                    if is_passed_through(format, raw_formats) {
                         co.yield_(IrEvent::HtmlInline {
                             content,
                             attributes: attributes.into(),
                         })
                         .await
+                    } else {
+                        log::debug!("Dropped raw inline of format {format}");
                     }
                 }
                 Event::End(Container::RawInline { .. }) => unreachable!(),
                 Event::Start(Container::RawBlock { format }, attributes) => {
                     let content = render_to_raw_string(iter_container_from_inside(&mut djot));
-                    if matches!(format, "html" | "HTML") {
+                    if is_passed_through(format, raw_formats) {
                         co.yield_(IrEvent::HtmlBlock {
                             content,
                             attributes: attributes.into(),
                         })
                         .await
+                    } else {
+                        log::debug!("Dropped raw block of format {format}");
                     }
                 }
                 Event::End(Container::RawBlock { .. }) => unreachable!(),
@@ -364,6 +395,22 @@ fn djot_to_ir<'s>(mut djot: impl Iterator<Item = Event<'s>>) -> impl Iterator<It
                         attributes: attributes.into(),
                     })
                     .await;
+
+                    // A caption must be the first child of the table in HTML, move it to the front
+                    let mut table: Vec<_> = iter_container_from_inside(&mut djot).collect();
+                    table.push(Event::End(Container::Table));
+                    if let Some(start) = table
+                        .iter()
+                        .position(|event| matches!(event, Event::Start(Container::Caption, _)))
+                    {
+                        let end = start
+                            + table[start..]
+                                .iter()
+                                .position(|event| matches!(event, Event::End(Container::Caption)))
+                                .expect("caption is closed");
+                        table[..=end].rotate_right(end + 1 - start);
+                    }
+                    djot.put_back(table);
                 }
                 Event::End(Container::Table) => {
                     co.yield_(IrEvent::End {
@@ -582,7 +629,14 @@ fn djot_to_ir<'s>(mut djot: impl Iterator<Item = Event<'s>>) -> impl Iterator<It
                     .await
                 }
 
-                Event::Str(str) | Event::Symbol(str) => co.yield_(IrEvent::Str(str)).await,
+                Event::Str(str) => co.yield_(IrEvent::Str(str)).await,
+                Event::Symbol(symbol) => {
+                    let str = match emoji::emoji(&symbol) {
+                        Some(emoji) => emoji.into(),
+                        None => format!(":{symbol}:").into(),
+                    };
+                    co.yield_(IrEvent::Str(str)).await
+                }
 
                 Event::Softbreak => co.yield_(IrEvent::Str("\n".into())).await,
                 Event::Hardbreak => {
@@ -630,7 +684,13 @@ fn djot_to_ir<'s>(mut djot: impl Iterator<Item = Event<'s>>) -> impl Iterator<It
 }
 
 pub fn parse<'s>(input: &'s str) -> impl Iterator<Item = IrEvent<'s>> {
-    djot_to_ir(jotdown::Parser::new(input))
+    parse_with_raw_formats(input, &[])
+}
+
+/// Parse, additionally passing raw blocks and inlines of the formats in `raw_formats` through into
+/// the HTML as-is.
+pub fn parse_with_raw_formats<'s>(input: &'s str, raw_formats: &'s [String]) -> impl Iterator<Item = IrEvent<'s>> {
+    djot_to_ir(jotdown::Parser::new(input), raw_formats)
 }
 
 #[cfg(test)]
@@ -645,8 +705,8 @@ mod test {
     fn test(input: &str, output: &str) {
         let mut s = String::new();
         let p = Parser::new(input);
-        let ir = djot_to_ir(p);
-        ir_markup::push_html(&mut s, ir, &HashMap::new()).unwrap();
+        let ir = djot_to_ir(p, &[]);
+        ir_markup::push_html(&mut s, ir, &HashMap::new(), &Default::default()).unwrap();
         assert_eq!(s, output);
     }
 
@@ -814,6 +874,51 @@ checked</li>
         )
     }
 
+    #[test]
+    fn div_and_span_classes() {
+        test(
+            "::: note\ncontent\n:::\n",
+            r##"<div class="note">
+<p>content</p>
+</div>
+"##,
+        );
+        test(
+            "{.wide}\n::: note\ncontent\n:::\n",
+            r##"<div class="note wide">
+<p>content</p>
+</div>
+"##,
+        );
+        test("[content]{.note}\n", "<p>\n<span class=\"note\">content</span></p>\n");
+    }
+
+    #[test]
+    fn task_list_class() {
+        test(
+            "{.todo}\n- [ ] unchecked\n",
+            r##"<ul class="todo task-list">
+<li>
+<input type="checkbox" disabled="">
+unchecked</li>
+</ul>
+"##,
+        )
+    }
+
+    #[test]
+    fn link_destination_escaped() {
+        test(
+            r##"[a](foo"><script>.html)"##,
+            "<p>\n<a href=\"foo&quot;&gt;&lt;script&gt;.html\">a</a></p>\n",
+        );
+    }
+
+    #[test]
+    fn symbols() {
+        test(":tada: :not_an_emoji:\n", "<p>🎉 :not_an_emoji:</p>\n");
+    }
+
     #[test]
     fn characters() {
         test(
@@ -828,6 +933,89 @@ checked</li>
         )
     }
 
+    #[test]
+    fn image_alt() {
+        use crate::types::Images;
+
+        fn render_image(input: &str) -> String {
+            let images = HashMap::from([(
+                "img.png".to_owned(),
+                Images {
+                    original: "img.png".into(),
+                    original_width: None,
+                    original_height: None,
+                    variants: vec![],
+                    sources: vec![],
+                },
+            )]);
+
+            let mut s = String::new();
+            ir_markup::push_html(
+                &mut s,
+                djot_to_ir(Parser::new(input), &[]),
+                &images,
+                &Default::default(),
+            )
+            .unwrap();
+            s
+        }
+
+        assert!(render_image("![some alt](img.png)").contains(r#"alt="some alt""#));
+        assert!(render_image("![](img.png)").contains(r#"alt="""#));
+        // Without known dimensions (e.g., SVGs), no size attributes are set
+        assert!(!render_image("![](img.png)").contains("width="));
+    }
+
+    #[test]
+    fn image_picture() {
+        use crate::types::{ImageSource, Images};
+
+        let images = HashMap::from([(
+            "img.png".to_owned(),
+            Images {
+                original: "img.png".into(),
+                original_width: Some(1000),
+                original_height: Some(500),
+                variants: vec![(768, "img-768.png".into())],
+                sources: vec![ImageSource {
+                    mime_type: "image/webp",
+                    original: "img.webp".into(),
+                    variants: vec![(768, "img-768.webp".into())],
+                }],
+            },
+        )]);
+
+        let mut s = String::new();
+        ir_markup::push_html(
+            &mut s,
+            djot_to_ir(Parser::new("![alt](img.png)"), &[]),
+            &images,
+            &Default::default(),
+        )
+        .unwrap();
+
+        assert!(s.contains(r#"<picture><source type="image/webp" srcset="/img.webp 1000w,/img-768.webp 768w">"#));
+        assert!(s.contains(r#"srcset="/img.png 1000w,/img-768.png 768w""#));
+        assert!(s.contains(r#"width="1000" height="500""#));
+        assert!(s.contains(r#"alt="alt"></picture>"#));
+
+        let options = ir_markup::HtmlOptions {
+            base_path: "/blog".to_owned(),
+            ..Default::default()
+        };
+        let mut s = String::new();
+        ir_markup::push_html(
+            &mut s,
+            djot_to_ir(Parser::new("![alt](img.png)"), &[]),
+            &images,
+            &options,
+        )
+        .unwrap();
+
+        assert!(s.contains(r#"srcset="/blog/img.webp 1000w,/blog/img-768.webp 768w""#));
+        assert!(s.contains(r#"srcset="/blog/img.png 1000w,/blog/img-768.png 768w""#));
+    }
+
     #[test]
     fn table_with_head() {
         test(
@@ -854,6 +1042,34 @@ checked</li>
         )
     }
 
+    #[test]
+    fn table_with_caption() {
+        test(
+            r##"
+| head 1 | head 2 |
+|--|--|
+| cell 1 | cell 2 |
+^ The _caption_
+"##,
+            r##"<table>
+<caption>The <em>caption</em></caption>
+<thead>
+<tr>
+<th>head 1</th>
+<th>head 2</th>
+</tr>
+</thead>
+<tbody>
+<tr>
+<td>cell 1</td>
+<td>cell 2</td>
+</tr>
+</tbody>
+</table>
+"##,
+        )
+    }
+
     #[test]
     fn table_without_head() {
         test(
@@ -898,4 +1114,31 @@ checked</li>
 "##,
         );
     }
+
+    #[test]
+    fn raw_formats() {
+        use super::parse_with_raw_formats;
+
+        let render = |raw_formats: &[String]| {
+            let mut s = String::new();
+            let input = "```=svg\n<svg></svg>\n```\n\n```=latex\n\\LaTeX\n```\n\n`<b>`{=html}\n";
+            ir_markup::push_html(
+                &mut s,
+                parse_with_raw_formats(input, raw_formats),
+                &HashMap::new(),
+                &Default::default(),
+            )
+            .unwrap();
+            s
+        };
+
+        let html = render(&[]);
+        assert!(!html.contains("<svg>"));
+        assert!(html.contains("<b>"));
+
+        let html = render(&["svg".to_owned()]);
+        assert!(html.contains("<svg></svg>"));
+        assert!(!html.contains("LaTeX"));
+        assert!(html.contains("<b>"));
+    }
 }
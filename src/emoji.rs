@@ -0,0 +1,202 @@
+//! Emoji shortcodes, such as `:tada:` in Djot.
+
+/// Shortcodes and their emoji, sorted by shortcode. The shortcodes are a subset of those used by
+/// GitHub.
+static EMOJI: &[(&str, &str)] = &[
+    ("+1", "👍"),
+    ("-1", "👎"),
+    ("100", "💯"),
+    ("alarm_clock", "⏰"),
+    ("angry", "😠"),
+    ("apple", "🍎"),
+    ("arrow_down", "⬇️"),
+    ("arrow_left", "⬅️"),
+    ("arrow_right", "➡️"),
+    ("arrow_up", "⬆️"),
+    ("art", "🎨"),
+    ("astonished", "😲"),
+    ("baby", "👶"),
+    ("balloon", "🎈"),
+    ("bangbang", "‼️"),
+    ("beer", "🍺"),
+    ("beers", "🍻"),
+    ("bell", "🔔"),
+    ("bike", "🚲"),
+    ("bird", "🐦"),
+    ("birthday", "🎂"),
+    ("blush", "😊"),
+    ("book", "📖"),
+    ("books", "📚"),
+    ("boom", "💥"),
+    ("bowtie", "🤵"),
+    ("brain", "🧠"),
+    ("broken_heart", "💔"),
+    ("bug", "🐛"),
+    ("bulb", "💡"),
+    ("calendar", "📆"),
+    ("camera", "📷"),
+    ("cat", "🐱"),
+    ("chart_with_upwards_trend", "📈"),
+    ("checkered_flag", "🏁"),
+    ("cherries", "🍒"),
+    ("clap", "👏"),
+    ("clipboard", "📋"),
+    ("cloud", "☁️"),
+    ("coffee", "☕"),
+    ("computer", "💻"),
+    ("confused", "😕"),
+    ("construction", "🚧"),
+    ("cookie", "🍪"),
+    ("cool", "🆒"),
+    ("crab", "🦀"),
+    ("crossed_fingers", "🤞"),
+    ("cry", "😢"),
+    ("crystal_ball", "🔮"),
+    ("dart", "🎯"),
+    ("disappointed", "😞"),
+    ("dog", "🐶"),
+    ("dragon", "🐉"),
+    ("earth_africa", "🌍"),
+    ("earth_americas", "🌎"),
+    ("earth_asia", "🌏"),
+    ("email", "📧"),
+    ("exclamation", "❗"),
+    ("eyes", "👀"),
+    ("facepalm", "🤦"),
+    ("fire", "🔥"),
+    ("fish", "🐟"),
+    ("flushed", "😳"),
+    ("gear", "⚙️"),
+    ("ghost", "👻"),
+    ("gift", "🎁"),
+    ("globe_with_meridians", "🌐"),
+    ("grimacing", "😬"),
+    ("grin", "😁"),
+    ("grinning", "😀"),
+    ("hammer", "🔨"),
+    ("hammer_and_wrench", "🛠️"),
+    ("hand", "✋"),
+    ("heart", "❤️"),
+    ("heart_eyes", "😍"),
+    ("heavy_check_mark", "✔️"),
+    ("heavy_multiplication_x", "✖️"),
+    ("hourglass", "⌛"),
+    ("house", "🏠"),
+    ("hugs", "🤗"),
+    ("hushed", "😯"),
+    ("information_source", "ℹ️"),
+    ("innocent", "😇"),
+    ("joy", "😂"),
+    ("key", "🔑"),
+    ("keyboard", "⌨️"),
+    ("kiss", "💋"),
+    ("laughing", "😆"),
+    ("leaves", "🍃"),
+    ("link", "🔗"),
+    ("lock", "🔒"),
+    ("mag", "🔍"),
+    ("mailbox", "📫"),
+    ("memo", "📝"),
+    ("microscope", "🔬"),
+    ("money_with_wings", "💸"),
+    ("moon", "🌔"),
+    ("mortar_board", "🎓"),
+    ("mountain", "⛰️"),
+    ("muscle", "💪"),
+    ("musical_note", "🎵"),
+    ("nerd_face", "🤓"),
+    ("neutral_face", "😐"),
+    ("no_entry", "⛔"),
+    ("ok", "🆗"),
+    ("ok_hand", "👌"),
+    ("package", "📦"),
+    ("pencil", "📝"),
+    ("pencil2", "✏️"),
+    ("penguin", "🐧"),
+    ("point_down", "👇"),
+    ("point_left", "👈"),
+    ("point_right", "👉"),
+    ("point_up", "☝️"),
+    ("pray", "🙏"),
+    ("pushpin", "📌"),
+    ("question", "❓"),
+    ("rainbow", "🌈"),
+    ("raised_hands", "🙌"),
+    ("recycle", "♻️"),
+    ("relaxed", "☺️"),
+    ("relieved", "😌"),
+    ("robot", "🤖"),
+    ("rocket", "🚀"),
+    ("rofl", "🤣"),
+    ("rose", "🌹"),
+    ("rotating_light", "🚨"),
+    ("scream", "😱"),
+    ("see_no_evil", "🙈"),
+    ("seedling", "🌱"),
+    ("shrug", "🤷"),
+    ("skull", "💀"),
+    ("sleeping", "😴"),
+    ("slightly_smiling_face", "🙂"),
+    ("smile", "😄"),
+    ("smiley", "😃"),
+    ("smirk", "😏"),
+    ("snake", "🐍"),
+    ("snowflake", "❄️"),
+    ("sob", "😭"),
+    ("sparkles", "✨"),
+    ("speech_balloon", "💬"),
+    ("star", "⭐"),
+    ("star2", "🌟"),
+    ("stopwatch", "⏱️"),
+    ("sunglasses", "😎"),
+    ("sunny", "☀️"),
+    ("sweat_smile", "😅"),
+    ("tada", "🎉"),
+    ("thinking", "🤔"),
+    ("thumbsdown", "👎"),
+    ("thumbsup", "👍"),
+    ("tired_face", "😫"),
+    ("trophy", "🏆"),
+    ("tulip", "🌷"),
+    ("turtle", "🐢"),
+    ("umbrella", "☔"),
+    ("unamused", "😒"),
+    ("unicorn", "🦄"),
+    ("upside_down_face", "🙃"),
+    ("v", "✌️"),
+    ("warning", "⚠️"),
+    ("wave", "👋"),
+    ("white_check_mark", "✅"),
+    ("wink", "😉"),
+    ("wrench", "🔧"),
+    ("x", "❌"),
+    ("yum", "😋"),
+    ("zap", "⚡"),
+    ("zzz", "💤"),
+];
+
+/// Get the emoji of a shortcode, without the surrounding colons.
+pub fn emoji(shortcode: &str) -> Option<&'static str> {
+    EMOJI
+        .binary_search_by(|(shortcode_, _)| (*shortcode_).cmp(shortcode))
+        .ok()
+        .map(|idx| EMOJI[idx].1)
+}
+
+#[cfg(test)]
+mod test {
+    #[test]
+    fn sorted() {
+        assert!(super::EMOJI.windows(2).all(|pair| pair[0].0 < pair[1].0));
+    }
+
+    #[test]
+    fn emoji() {
+        use super::emoji;
+
+        assert_eq!(emoji("tada"), Some("🎉"));
+        assert_eq!(emoji("+1"), Some("👍"));
+        assert_eq!(emoji("zzz"), Some("💤"));
+        assert_eq!(emoji("not_an_emoji"), None);
+    }
+}
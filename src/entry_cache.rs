@@ -0,0 +1,136 @@
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap, HashSet},
+    hash::{Hash, Hasher},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use crate::types;
+
+/// An entry's markup rendered to HTML.
+#[derive(Clone)]
+pub struct Markup {
+    pub summary: String,
+    pub rest: String,
+    pub toc: Vec<types::TocEntry>,
+    pub word_count: usize,
+    pub has_math: bool,
+    pub has_code: bool,
+}
+
+/// Rendered entries kept between builds, keyed by source path. Every rendered item is stored along
+/// with a hash of what it was rendered from, and is only rendered again if that hash changes.
+/// Entries that are no longer built are dropped from the cache after a build.
+///
+/// Entry pages can show other entries than the entry itself and the entries referring to it, e.g.,
+/// a list of recent entries. Those are not tracked, so the cache should be cleared when templates
+/// change.
+pub struct EntryCache {
+    markup: Mutex<HashMap<PathBuf, (u64, Markup)>>,
+    pages: Mutex<HashMap<PathBuf, (u64, Vec<u8>)>>,
+}
+
+impl EntryCache {
+    pub fn new() -> Self {
+        EntryCache {
+            markup: Mutex::new(HashMap::new()),
+            pages: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn clear(&self) {
+        self.markup.lock().unwrap().clear();
+        self.pages.lock().unwrap().clear();
+    }
+
+    /// Drop all cached pages, keeping the cached markup.
+    pub fn clear_pages(&self) {
+        self.pages.lock().unwrap().clear();
+    }
+
+    /// Get the markup of the entry at `path` rendered from input with hash `key`, rendering it if
+    /// it is not cached.
+    pub fn markup(
+        &self,
+        path: &Path,
+        key: u64,
+        render: impl FnOnce() -> anyhow::Result<Markup>,
+    ) -> anyhow::Result<Markup> {
+        get_or_render(&self.markup, path, key, render)
+    }
+
+    /// Get the page of the entry at `path` rendered from input with hash `key`, rendering it if it
+    /// is not cached.
+    pub fn page(
+        &self,
+        path: &Path,
+        key: u64,
+        render: impl FnOnce() -> anyhow::Result<Vec<u8>>,
+    ) -> anyhow::Result<Vec<u8>> {
+        get_or_render(&self.pages, path, key, render)
+    }
+
+    /// Drop all entries from the cache that are not at one of `paths`.
+    pub fn retain(&self, paths: &HashSet<&Path>) {
+        self.markup
+            .lock()
+            .unwrap()
+            .retain(|path, _| paths.contains(path.as_path()));
+        self.pages
+            .lock()
+            .unwrap()
+            .retain(|path, _| paths.contains(path.as_path()));
+    }
+}
+
+fn get_or_render<T: Clone>(
+    cache: &Mutex<HashMap<PathBuf, (u64, T)>>,
+    path: &Path,
+    key: u64,
+    render: impl FnOnce() -> anyhow::Result<T>,
+) -> anyhow::Result<T> {
+    if let Some((cached_key, cached)) = cache.lock().unwrap().get(path) {
+        if *cached_key == key {
+            return Ok(cached.clone());
+        }
+    }
+
+    // the lock is not held while rendering, so other entries can be rendered concurrently
+    let rendered = render()?;
+    cache.lock().unwrap().insert(path.to_owned(), (key, rendered.clone()));
+    Ok(rendered)
+}
+
+/// Hash the input of a render step.
+pub fn hash(value: impl Hash) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    value.hash(&mut hasher);
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod test {
+    #[test]
+    fn get_or_render() {
+        use super::{hash, EntryCache};
+        use std::{collections::HashSet, path::Path};
+
+        let cache = EntryCache::new();
+        let path = Path::new("posts/foo.dj");
+
+        let page = cache.page(path, hash("a"), || Ok(b"a".to_vec())).unwrap();
+        assert_eq!(page, b"a");
+        let page = cache.page(path, hash("a"), || panic!("page is cached")).unwrap();
+        assert_eq!(page, b"a");
+        let page = cache.page(path, hash("b"), || Ok(b"b".to_vec())).unwrap();
+        assert_eq!(page, b"b");
+
+        assert!(cache.page(path, hash("c"), || Err(anyhow::anyhow!("failed"))).is_err());
+        let page = cache.page(path, hash("b"), || Ok(b"rendered".to_vec())).unwrap();
+        assert_eq!(page, b"b");
+
+        cache.retain(&HashSet::new());
+        let page = cache.page(path, hash("b"), || Ok(b"rendered".to_vec())).unwrap();
+        assert_eq!(page, b"rendered");
+    }
+}
@@ -0,0 +1,503 @@
+//! Generate syndication feeds of entry groups.
+
+use std::path::PathBuf;
+
+use crate::{config, types, utils::escape_xml as escape, Ctx};
+
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+const WEEKDAYS: [&str; 7] = ["Sun", "Mon", "Tue", "Wed", "Thu", "Fri", "Sat"];
+
+/// Format a date and optional time as an RFC 822 date-time, e.g. `Tue, 16 Apr 2024 09:40:32 +0000`.
+/// Times without a UTC offset are taken to be at `default_offset`.
+fn rfc822(date: types::Date, time: Option<types::Time>, default_offset: types::UtcOffset) -> String {
+    let time = time.unwrap_or(types::Time::new(0, 0, 0));
+    format!(
+        "{}, {:02} {} {:04} {:02}:{:02}:{:02} {}",
+        WEEKDAYS[date.weekday() as usize],
+        date.day,
+        MONTHS[(date.month.clamp(1, 12) - 1) as usize],
+        date.year,
+        time.hour,
+        time.minute,
+        time.second,
+        time.offset.unwrap_or(default_offset).rfc822(),
+    )
+}
+
+/// Push an element with escaped text content.
+fn push_element(buf: &mut String, tag: &str, text: &str) {
+    buf.push('<');
+    buf.push_str(tag);
+    buf.push('>');
+    escape(buf, text);
+    buf.push_str("</");
+    buf.push_str(tag);
+    buf.push_str(">\n");
+}
+
+/// Push a JSON string literal, with quotes.
+fn push_json_string(buf: &mut String, value: &str) {
+    buf.push('"');
+    for char in value.chars() {
+        match char {
+            '"' => buf.push_str("\\\""),
+            '\\' => buf.push_str("\\\\"),
+            '\n' => buf.push_str("\\n"),
+            '\r' => buf.push_str("\\r"),
+            '\t' => buf.push_str("\\t"),
+            '\u{0}'..='\u{1f}' => buf.push_str(&format!("\\u{:04x}", char as u32)),
+            _ => buf.push(char),
+        }
+    }
+    buf.push('"');
+}
+
+/// The entries to include in a feed: entries without a date are skipped, the rest are ordered
+/// newest first.
+fn feed_entries<'e, 'm>(entries: &'e [types::Entry<'m>]) -> Vec<(&'e types::Entry<'m>, types::Date)> {
+    let mut entries: Vec<_> = entries
+        .iter()
+        .filter_map(|entry| Some((entry, entry.meta.date?)))
+        .collect();
+    entries.sort_by(|(a, a_date), (b, b_date)| (b_date, b.meta.time).cmp(&(a_date, a.meta.time)));
+    entries
+}
+
+/// Channel-level information of a feed.
+struct FeedMeta<'a> {
+    title: &'a str,
+    description: &'a str,
+    site_url: &'a str,
+    feed_url: &'a str,
+    author: &'a str,
+    /// Date and time of the newest entry, or of the build if there are no entries
+    updated: (types::Date, Option<types::Time>),
+    /// UTC offset of entry times without one
+    default_offset: types::UtcOffset,
+}
+
+/// A syndication feed format.
+trait Format {
+    fn write_head(&self, buf: &mut String, meta: &FeedMeta);
+    /// Write an entry. `permalink` is the entry's absolute URL, regardless of the link style.
+    fn write_entry(&self, buf: &mut String, meta: &FeedMeta, entry: &types::Entry, permalink: &str, date: types::Date);
+    fn write_tail(&self, buf: &mut String);
+}
+
+/// RSS 2.0
+struct Rss;
+
+impl Format for Rss {
+    fn write_head(&self, buf: &mut String, meta: &FeedMeta) {
+        buf.push_str("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n");
+        buf.push_str("<rss version=\"2.0\" xmlns:atom=\"http://www.w3.org/2005/Atom\">\n<channel>\n");
+        push_element(buf, "title", meta.title);
+        push_element(buf, "link", meta.site_url);
+        push_element(buf, "description", meta.description);
+        buf.push_str("<atom:link href=\"");
+        escape(buf, meta.feed_url);
+        buf.push_str("\" rel=\"self\" type=\"application/rss+xml\"/>\n");
+    }
+
+    fn write_entry(&self, buf: &mut String, meta: &FeedMeta, entry: &types::Entry, permalink: &str, date: types::Date) {
+        buf.push_str("<item>\n");
+        push_element(buf, "title", &entry.front_matter.title);
+        push_element(buf, "link", permalink);
+        buf.push_str("<guid isPermaLink=\"true\">");
+        escape(buf, permalink);
+        buf.push_str("</guid>\n");
+        push_element(buf, "pubDate", &rfc822(date, entry.meta.time, meta.default_offset));
+        match &entry.front_matter.description {
+            Some(description) => {
+                let mut description_html = "<p>".to_owned();
+                escape(&mut description_html, description);
+                description_html.push_str("</p>");
+                push_element(buf, "description", &description_html);
+            }
+            None => push_element(buf, "description", &entry.summary),
+        }
+        buf.push_str("</item>\n");
+    }
+
+    fn write_tail(&self, buf: &mut String) {
+        buf.push_str("</channel>\n</rss>\n");
+    }
+}
+
+/// Atom 1.0
+struct Atom;
+
+impl Format for Atom {
+    fn write_head(&self, buf: &mut String, meta: &FeedMeta) {
+        buf.push_str("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n");
+        buf.push_str("<feed xmlns=\"http://www.w3.org/2005/Atom\">\n");
+        push_element(buf, "title", meta.title);
+        if !meta.description.is_empty() {
+            push_element(buf, "subtitle", meta.description);
+        }
+        buf.push_str("<link href=\"");
+        escape(buf, meta.site_url);
+        buf.push_str("\"/>\n<link href=\"");
+        escape(buf, meta.feed_url);
+        buf.push_str("\" rel=\"self\" type=\"application/atom+xml\"/>\n");
+        push_element(buf, "id", meta.feed_url);
+        let (date, time) = meta.updated;
+        push_element(buf, "updated", &date.rfc3339(time, meta.default_offset));
+        buf.push_str("<author>\n");
+        push_element(buf, "name", meta.author);
+        buf.push_str("</author>\n");
+    }
+
+    fn write_entry(&self, buf: &mut String, meta: &FeedMeta, entry: &types::Entry, permalink: &str, date: types::Date) {
+        buf.push_str("<entry>\n");
+        push_element(buf, "title", &entry.front_matter.title);
+        buf.push_str("<link href=\"");
+        escape(buf, permalink);
+        buf.push_str("\"/>\n");
+        push_element(buf, "id", permalink);
+        push_element(buf, "updated", &date.rfc3339(entry.meta.time, meta.default_offset));
+        // The HTML content is escaped once, such that feed readers get the HTML after unescaping
+        buf.push_str("<content type=\"html\">");
+        escape(buf, &entry.summary);
+        escape(buf, &entry.rest);
+        buf.push_str("</content>\n</entry>\n");
+    }
+
+    fn write_tail(&self, buf: &mut String) {
+        buf.push_str("</feed>\n");
+    }
+}
+
+/// JSON Feed 1.1
+struct JsonFeed;
+
+impl Format for JsonFeed {
+    fn write_head(&self, buf: &mut String, meta: &FeedMeta) {
+        buf.push_str("{\n  \"version\": \"https://jsonfeed.org/version/1.1\",\n  \"title\": ");
+        push_json_string(buf, meta.title);
+        if !meta.description.is_empty() {
+            buf.push_str(",\n  \"description\": ");
+            push_json_string(buf, meta.description);
+        }
+        buf.push_str(",\n  \"home_page_url\": ");
+        push_json_string(buf, meta.site_url);
+        buf.push_str(",\n  \"feed_url\": ");
+        push_json_string(buf, meta.feed_url);
+        buf.push_str(",\n  \"items\": [");
+    }
+
+    fn write_entry(&self, buf: &mut String, meta: &FeedMeta, entry: &types::Entry, permalink: &str, date: types::Date) {
+        // Every item but the first is preceded by a comma
+        if !buf.ends_with('[') {
+            buf.push(',');
+        }
+        buf.push_str("\n    {\n      \"id\": ");
+        push_json_string(buf, permalink);
+        buf.push_str(",\n      \"url\": ");
+        push_json_string(buf, permalink);
+        buf.push_str(",\n      \"title\": ");
+        push_json_string(buf, &entry.front_matter.title);
+        buf.push_str(",\n      \"content_html\": ");
+        push_json_string(buf, &format!("{}{}", entry.summary, entry.rest));
+        buf.push_str(",\n      \"date_published\": ");
+        push_json_string(buf, &date.rfc3339(entry.meta.time, meta.default_offset));
+        buf.push_str("\n    }");
+    }
+
+    fn write_tail(&self, buf: &mut String) {
+        buf.push_str("\n  ]\n}\n");
+    }
+}
+
+/// Output path of a feed relative to the output directory.
+pub fn feed_path(group: &str, feed: &config::Feed, format: config::FeedFormat) -> PathBuf {
+    let (path, default_file_name) = match format {
+        config::FeedFormat::Rss => (&feed.rss_path, "rss.xml"),
+        config::FeedFormat::Atom => (&feed.atom_path, "atom.xml"),
+        config::FeedFormat::Json => (&feed.json_path, "feed.json"),
+    };
+    path.clone()
+        .unwrap_or_else(|| PathBuf::from(group).join(default_file_name))
+}
+
+/// Render a feed of the given entries. `group` is the name of the entries' group and `feed_url` is
+/// the absolute URL the feed is served at.
+pub fn render(
+    ctx: &Ctx,
+    format: config::FeedFormat,
+    group: &str,
+    feed: &config::Feed,
+    feed_url: &str,
+    entries: &[types::Entry],
+) -> String {
+    let format: &dyn Format = match format {
+        config::FeedFormat::Rss => &Rss,
+        config::FeedFormat::Atom => &Atom,
+        config::FeedFormat::Json => &JsonFeed,
+    };
+    let entries = feed_entries(entries);
+    let title = feed.title.as_deref().unwrap_or(group);
+
+    let meta = FeedMeta {
+        title,
+        description: feed.description.as_deref().unwrap_or(""),
+        site_url: ctx.base_url(),
+        feed_url,
+        // Atom requires a feed author; fall back to the feed's title if the site has no author
+        author: match ctx.site().author.as_str() {
+            "" => title,
+            author => author,
+        },
+        updated: match entries.first() {
+            Some((entry, date)) => (*date, entry.meta.time),
+            None => {
+                let now = std::time::SystemTime::now()
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .unwrap_or_default();
+                let (date, time) = types::Date::from_unix_time(now);
+                (date, Some(time))
+            }
+        },
+        default_offset: ctx.utc_offset(),
+    };
+
+    let mut buf = String::new();
+    format.write_head(&mut buf, &meta);
+    for (entry, date) in entries {
+        let permalink = ctx.path_to_absolute_url(&entry.meta.out_file).expect("valid path");
+        format.write_entry(&mut buf, &meta, entry, &permalink, date);
+    }
+    format.write_tail(&mut buf);
+
+    buf
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{config, front_matter, types, Ctx};
+
+    #[test]
+    fn rfc822() {
+        use super::rfc822;
+        use crate::types::{Date, Time, UtcOffset};
+
+        assert_eq!(
+            rfc822(Date::new(2024, 4, 16), None, UtcOffset(0)),
+            "Tue, 16 Apr 2024 00:00:00 +0000"
+        );
+        assert_eq!(
+            rfc822(Date::new(2000, 1, 1), Some(Time::new(9, 40, 32)), UtcOffset(0)),
+            "Sat, 01 Jan 2000 09:40:32 +0000"
+        );
+        assert_eq!(
+            rfc822(Date::new(2024, 2, 29), None, UtcOffset(-300)),
+            "Thu, 29 Feb 2024 00:00:00 -0500"
+        );
+        assert_eq!(
+            rfc822(
+                Date::new(2024, 2, 29),
+                Some(Time::new(12, 0, 0).with_offset(UtcOffset(120))),
+                UtcOffset(-300)
+            ),
+            "Thu, 29 Feb 2024 12:00:00 +0200"
+        );
+    }
+
+    fn with_entries(f: impl FnOnce(&Ctx, &[types::Entry])) {
+        let ctx = Ctx::for_test("");
+
+        let metas = ["2024-04-16_older.dj", "2024-05-01_newer.dj", "undated.dj"]
+            .map(|file_name| types::EntryMeta::for_test(&ctx, &format!("posts/{file_name}")));
+        let mut front_matter = front_matter::parse_front_matter("").unwrap().0;
+        front_matter.title = "Fish & <chips>".to_owned();
+        let entries: Vec<_> = metas
+            .iter()
+            .map(|meta| types::Entry {
+                summary: "<p>A \"summary\"</p>".to_owned(),
+                rest: "<p>The rest</p>".to_owned(),
+                ..types::Entry::for_test(meta, &front_matter)
+            })
+            .collect();
+
+        f(&ctx, &entries)
+    }
+
+    fn feed() -> config::Feed {
+        config::Feed {
+            title: None,
+            description: Some("All posts".to_owned()),
+            formats: vec![config::FeedFormat::Rss, config::FeedFormat::Atom],
+            rss_path: None,
+            atom_path: None,
+            json_path: None,
+        }
+    }
+
+    #[test]
+    fn rss() {
+        with_entries(|ctx, entries| {
+            let rss = super::render(
+                ctx,
+                config::FeedFormat::Rss,
+                "posts",
+                &feed(),
+                "http://localhost:8080/posts/rss.xml",
+                entries,
+            );
+
+            assert_eq!(
+                rss,
+                r#"<?xml version="1.0" encoding="utf-8"?>
+<rss version="2.0" xmlns:atom="http://www.w3.org/2005/Atom">
+<channel>
+<title>posts</title>
+<link>http://localhost:8080</link>
+<description>All posts</description>
+<atom:link href="http://localhost:8080/posts/rss.xml" rel="self" type="application/rss+xml"/>
+<item>
+<title>Fish &amp; &lt;chips&gt;</title>
+<link>http://localhost:8080/2024/newer</link>
+<guid isPermaLink="true">http://localhost:8080/2024/newer</guid>
+<pubDate>Wed, 01 May 2024 00:00:00 +0000</pubDate>
+<description>&lt;p&gt;A &quot;summary&quot;&lt;/p&gt;</description>
+</item>
+<item>
+<title>Fish &amp; &lt;chips&gt;</title>
+<link>http://localhost:8080/2024/older</link>
+<guid isPermaLink="true">http://localhost:8080/2024/older</guid>
+<pubDate>Tue, 16 Apr 2024 00:00:00 +0000</pubDate>
+<description>&lt;p&gt;A &quot;summary&quot;&lt;/p&gt;</description>
+</item>
+</channel>
+</rss>
+"#
+            );
+        });
+    }
+
+    #[test]
+    fn atom() {
+        with_entries(|ctx, entries| {
+            let atom = super::render(
+                ctx,
+                config::FeedFormat::Atom,
+                "posts",
+                &feed(),
+                "http://localhost:8080/posts/atom.xml",
+                entries,
+            );
+
+            assert_eq!(
+                atom,
+                r#"<?xml version="1.0" encoding="utf-8"?>
+<feed xmlns="http://www.w3.org/2005/Atom">
+<title>posts</title>
+<subtitle>All posts</subtitle>
+<link href="http://localhost:8080"/>
+<link href="http://localhost:8080/posts/atom.xml" rel="self" type="application/atom+xml"/>
+<id>http://localhost:8080/posts/atom.xml</id>
+<updated>2024-05-01T00:00:00Z</updated>
+<author>
+<name>posts</name>
+</author>
+<entry>
+<title>Fish &amp; &lt;chips&gt;</title>
+<link href="http://localhost:8080/2024/newer"/>
+<id>http://localhost:8080/2024/newer</id>
+<updated>2024-05-01T00:00:00Z</updated>
+<content type="html">&lt;p&gt;A &quot;summary&quot;&lt;/p&gt;&lt;p&gt;The rest&lt;/p&gt;</content>
+</entry>
+<entry>
+<title>Fish &amp; &lt;chips&gt;</title>
+<link href="http://localhost:8080/2024/older"/>
+<id>http://localhost:8080/2024/older</id>
+<updated>2024-04-16T00:00:00Z</updated>
+<content type="html">&lt;p&gt;A &quot;summary&quot;&lt;/p&gt;&lt;p&gt;The rest&lt;/p&gt;</content>
+</entry>
+</feed>
+"#
+            );
+        });
+    }
+
+    #[test]
+    fn json() {
+        with_entries(|ctx, entries| {
+            let json = super::render(
+                ctx,
+                config::FeedFormat::Json,
+                "posts",
+                &feed(),
+                "http://localhost:8080/posts/feed.json",
+                &entries[..1],
+            );
+
+            assert_eq!(
+                json,
+                r#"{
+  "version": "https://jsonfeed.org/version/1.1",
+  "title": "posts",
+  "description": "All posts",
+  "home_page_url": "http://localhost:8080",
+  "feed_url": "http://localhost:8080/posts/feed.json",
+  "items": [
+    {
+      "id": "http://localhost:8080/2024/older",
+      "url": "http://localhost:8080/2024/older",
+      "title": "Fish & <chips>",
+      "content_html": "<p>A \"summary\"</p><p>The rest</p>",
+      "date_published": "2024-04-16T00:00:00Z"
+    }
+  ]
+}
+"#
+            );
+
+            let json = super::render(
+                ctx,
+                config::FeedFormat::Json,
+                "posts",
+                &feed(),
+                "http://localhost:8080/posts/feed.json",
+                entries,
+            );
+            assert!(json.contains("2024/newer\",\n      \"title\""));
+            assert!(json.contains("\n    },\n    {\n"));
+            assert!(!json.contains("undated"));
+        });
+    }
+
+    #[test]
+    fn push_json_string() {
+        let mut buf = String::new();
+        super::push_json_string(&mut buf, "\"a\\b\"\n\u{1}");
+        assert_eq!(buf, r#""\"a\\b\"\n\u0001""#);
+    }
+
+    #[test]
+    fn feed_path() {
+        use super::feed_path;
+        use std::path::PathBuf;
+
+        let mut feed = feed();
+        assert_eq!(
+            feed_path("posts", &feed, config::FeedFormat::Rss),
+            PathBuf::from("posts").join("rss.xml")
+        );
+        assert_eq!(
+            feed_path("posts", &feed, config::FeedFormat::Atom),
+            PathBuf::from("posts").join("atom.xml")
+        );
+        assert_eq!(
+            feed_path("posts", &feed, config::FeedFormat::Json),
+            PathBuf::from("posts").join("feed.json")
+        );
+        feed.atom_path = Some("feed.xml".into());
+        assert_eq!(
+            feed_path("posts", &feed, config::FeedFormat::Atom),
+            PathBuf::from("feed.xml")
+        );
+    }
+}
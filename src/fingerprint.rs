@@ -0,0 +1,73 @@
+//! Fingerprint static assets for cache-busting, by writing them under a name that includes a hash
+//! of their content.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use crate::{entry_cache, out::Out, utils};
+
+/// Maps the URL paths of assets relative to the output directory to the URL paths of their
+/// fingerprinted copies, e.g., `css/style.css` to `css/style.0123abcd.css`.
+pub type AssetManifest = HashMap<String, String>;
+
+/// Write a fingerprinted copy of every file in `in_dir` to the output directory, preserving the
+/// directory structure.
+pub fn fingerprint_dir(out: &Out, in_dir: impl AsRef<Path>) -> anyhow::Result<AssetManifest> {
+    let in_dir = in_dir.as_ref();
+    let mut manifest = AssetManifest::new();
+
+    for entry in walkdir::WalkDir::new(in_dir).follow_links(true) {
+        let entry = entry?;
+        if !entry.file_type().is_file() {
+            continue;
+        }
+
+        let path = entry.path().strip_prefix(in_dir)?;
+        let content = std::fs::read(entry.path())?;
+        let fingerprinted = fingerprinted_path(path, &content);
+        out.update_file(&mut &*content, &fingerprinted)?;
+
+        manifest.insert(
+            utils::path_to_url(None, path)?,
+            utils::path_to_url(None, fingerprinted)?,
+        );
+    }
+
+    Ok(manifest)
+}
+
+/// Insert a hash of `content` before the extension of `path`.
+fn fingerprinted_path(path: &Path, content: &[u8]) -> PathBuf {
+    let hash = format!("{:016x}", entry_cache::hash(content));
+    let hash = &hash[..8];
+
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    match path.extension() {
+        Some(extension) => path.with_file_name(format!("{stem}.{hash}.{}", extension.to_string_lossy())),
+        None => path.with_file_name(format!("{stem}.{hash}")),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    #[test]
+    fn fingerprinted_path() {
+        use super::fingerprinted_path;
+        use std::path::Path;
+
+        let path = fingerprinted_path(Path::new("css/style.css"), b"body {}");
+        let name = path.file_name().unwrap().to_str().unwrap();
+        assert_eq!(path.parent(), Some(Path::new("css")));
+        assert!(name.starts_with("style.") && name.ends_with(".css"));
+        assert_eq!(name.len(), "style.0123abcd.css".len());
+
+        assert_eq!(path, fingerprinted_path(Path::new("css/style.css"), b"body {}"));
+        assert_ne!(
+            path,
+            fingerprinted_path(Path::new("css/style.css"), b"body { margin: 0 }")
+        );
+
+        let path = fingerprinted_path(Path::new("LICENSE"), b"");
+        assert_eq!(path.file_name().unwrap().len(), "LICENSE.0123abcd".len());
+    }
+}
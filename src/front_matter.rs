@@ -1,4 +1,5 @@
-use std::collections::HashMap;
+use anyhow::Context;
+use std::{collections::HashMap, path::PathBuf};
 
 use crate::types;
 
@@ -10,12 +11,12 @@ pub fn parse_front_matter(content: &str) -> anyhow::Result<(types::FrontMatter,
 
     if content.starts_with("+++") {
         if let Some(end) = content[3..].find("\n+++").map(|idx| idx + 3) {
-            parsed = Some(toml::from_str(&content[3..end + 1])?);
+            parsed = Some(toml::from_str(&content[3..end + 1]).context("Parsing TOML front matter")?);
             rest = &content[end + 4..];
         }
     } else if content.starts_with("---") {
         if let Some(end) = content[3..].find("\n---").map(|idx| idx + 3) {
-            parsed = Some(serde_yaml::from_str(&content[3..end + 1])?);
+            parsed = Some(serde_yaml::from_str(&content[3..end + 1]).context("Parsing YAML front matter")?);
             rest = &content[end + 4..];
         }
     }
@@ -23,6 +24,14 @@ pub fn parse_front_matter(content: &str) -> anyhow::Result<(types::FrontMatter,
     let mut front_matter = types::FrontMatter {
         title: String::new(),
         released: None,
+        draft: false,
+        description: None,
+        date: None,
+        slug: None,
+        out_file: None,
+        template: None,
+        aliases: vec![],
+        tags: vec![],
         extra: parsed.unwrap_or_else(|| HashMap::new()),
     };
 
@@ -32,5 +41,210 @@ pub fn parse_front_matter(content: &str) -> anyhow::Result<(types::FrontMatter,
             Some(release.is_true() || matches!(release.as_str(), Some("true" | "yes")));
     }
 
+    if let Some(draft) = extra.get("draft") {
+        front_matter.draft = draft.is_true() || matches!(draft.as_str(), Some("true" | "yes"));
+    }
+
+    if let Some(description) = extra.get("description") {
+        let description = description
+            .as_str()
+            .ok_or(anyhow::anyhow!("front matter `description` must be a string"))?;
+        front_matter.description = Some(description.to_owned());
+    }
+
+    if let Some(date) = extra.get("date") {
+        let date = date.as_str().ok_or(anyhow::anyhow!(
+            "front matter `date` must be a string such as \"2024-04-26\" or \"2024-04-26T094032\""
+        ))?;
+        front_matter.date = Some(
+            types::parse_date_time(date)
+                .map_err(|_| anyhow::anyhow!("front matter `date` is not a valid date: {date}"))?,
+        );
+    }
+
+    if let Some(slug) = extra.get("slug") {
+        let slug = slug
+            .as_str()
+            .ok_or(anyhow::anyhow!("front matter `slug` must be a string"))?;
+        if slug.is_empty() || slug.contains('/') || slug == "." || slug == ".." {
+            anyhow::bail!("front matter `slug` must be a single, non-empty path segment: {slug}");
+        }
+        front_matter.slug = Some(slug.to_owned());
+    }
+
+    if let Some(permalink) = extra.get("permalink") {
+        let permalink = permalink.as_str().ok_or(anyhow::anyhow!(
+            "front matter `permalink` must be a string such as \"/about/\""
+        ))?;
+        front_matter.out_file = Some(types::permalink_to_out_file(permalink).context("in front matter `permalink`")?);
+    }
+
+    if let Some(template) = extra.get("template") {
+        let template = template.as_str().ok_or(anyhow::anyhow!(
+            "front matter `template` must be a string such as \"special\" for `_special.html`"
+        ))?;
+        front_matter.template = Some(template.to_owned());
+    }
+
+    if let Some(aliases) = extra.get("aliases") {
+        front_matter.aliases = parse_aliases(aliases)?;
+    }
+
+    if let Some(tags) = extra.get("tags") {
+        front_matter.tags = parse_tags(tags)?;
+    }
+
     Ok((front_matter, rest))
 }
+
+/// Aliases are given as a sequence of root-relative paths, e.g., `["/old/path/"]`.
+fn parse_aliases(aliases: &minijinja::Value) -> anyhow::Result<Vec<PathBuf>> {
+    let aliases = aliases.as_seq().ok_or(anyhow::anyhow!(
+        "front matter `aliases` must be a list of strings such as \"/old/path/\""
+    ))?;
+    aliases
+        .iter()
+        .map(|alias| {
+            let alias = alias
+                .as_str()
+                .ok_or(anyhow::anyhow!("front matter alias is not a string: {alias}"))?;
+            types::permalink_to_out_file(alias).context("in front matter `aliases`")
+        })
+        .collect()
+}
+
+/// Tags are given either as a sequence of strings, or as a single string for a single tag.
+fn parse_tags(tags: &minijinja::Value) -> anyhow::Result<Vec<String>> {
+    if let Some(tag) = tags.as_str() {
+        return Ok(vec![tag.to_owned()]);
+    }
+
+    let tags = tags.as_seq().ok_or(anyhow::anyhow!(
+        "front matter `tags` must be a string or a list of strings"
+    ))?;
+    tags.iter()
+        .map(|tag| {
+            tag.as_str()
+                .map(str::to_owned)
+                .ok_or(anyhow::anyhow!("front matter tag is not a string: {tag}"))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    #[test]
+    fn draft_and_release() {
+        use super::parse_front_matter;
+
+        let (front_matter, _) = parse_front_matter("+++\ndraft = true\n+++\n").unwrap();
+        assert!(front_matter.draft);
+        assert_eq!(front_matter.released, None);
+        assert!(front_matter.is_draft());
+
+        let (front_matter, _) = parse_front_matter("---\nrelease: yes\ndraft: no\n---\n").unwrap();
+        assert!(!front_matter.draft);
+        assert_eq!(front_matter.released, Some(true));
+        assert!(!front_matter.is_draft());
+
+        let (front_matter, _) = parse_front_matter("+++\nrelease = false\n+++\n").unwrap();
+        assert!(!front_matter.draft);
+        assert!(front_matter.is_draft());
+
+        let (front_matter, _) = parse_front_matter("Hi").unwrap();
+        assert!(!front_matter.draft);
+        assert!(!front_matter.is_draft());
+    }
+
+    #[test]
+    fn date() {
+        use super::parse_front_matter;
+        use crate::types::{Date, Time};
+
+        let (front_matter, _) = parse_front_matter("+++\ndate = \"2024-04-26T094032\"\n+++\n").unwrap();
+        assert_eq!(
+            front_matter.date,
+            Some((Date::new(2024, 4, 26), Some(Time::new(9, 40, 32))))
+        );
+
+        let (front_matter, _) = parse_front_matter("---\ndate: 2024-04-26\n---\n").unwrap();
+        assert_eq!(front_matter.date, Some((Date::new(2024, 4, 26), None)));
+
+        assert!(parse_front_matter("+++\ndate = \"yesterday\"\n+++\n").is_err());
+    }
+
+    #[test]
+    fn slug_and_permalink() {
+        use super::parse_front_matter;
+        use std::path::Path;
+
+        let (front_matter, _) = parse_front_matter("+++\nslug = \"foo\"\npermalink = \"/about/\"\n+++\n").unwrap();
+        assert_eq!(front_matter.slug.as_deref(), Some("foo"));
+        assert_eq!(front_matter.out_file.as_deref(), Some(Path::new("about/index.html")));
+
+        let (front_matter, _) = parse_front_matter("---\npermalink: /feeds/index.html\n---\n").unwrap();
+        assert_eq!(front_matter.out_file.as_deref(), Some(Path::new("feeds/index.html")));
+
+        assert!(parse_front_matter("+++\nslug = \"a/b\"\n+++\n").is_err());
+        assert!(parse_front_matter("+++\npermalink = \"about\"\n+++\n").is_err());
+        assert!(parse_front_matter("+++\npermalink = \"/../about\"\n+++\n").is_err());
+    }
+
+    #[test]
+    fn template() {
+        use super::parse_front_matter;
+
+        let (front_matter, _) = parse_front_matter("+++\ntemplate = \"special\"\n+++\n").unwrap();
+        assert_eq!(front_matter.template.as_deref(), Some("special"));
+
+        let (front_matter, _) = parse_front_matter("Hi").unwrap();
+        assert_eq!(front_matter.template, None);
+
+        assert!(parse_front_matter("+++\ntemplate = 1\n+++\n").is_err());
+    }
+
+    #[test]
+    fn aliases() {
+        use super::parse_front_matter;
+        use std::path::Path;
+
+        let (front_matter, _) = parse_front_matter("+++\naliases = [\"/old/path/\", \"/older.html\"]\n+++\n").unwrap();
+        assert_eq!(
+            front_matter.aliases,
+            [Path::new("old/path/index.html"), Path::new("older.html")]
+        );
+
+        assert!(parse_front_matter("+++\naliases = \"/old/\"\n+++\n").is_err());
+        assert!(parse_front_matter("+++\naliases = [\"old\"]\n+++\n").is_err());
+    }
+
+    #[test]
+    fn tags() {
+        use super::parse_front_matter;
+
+        let (front_matter, rest) = parse_front_matter("+++\ntags = [\"Rust\", \"web dev\"]\n+++\nHi").unwrap();
+        assert_eq!(front_matter.tags, ["Rust", "web dev"]);
+        assert_eq!(rest, "\nHi");
+
+        let (front_matter, _) = parse_front_matter("---\ntags: rust\n---\n").unwrap();
+        assert_eq!(front_matter.tags, ["rust"]);
+
+        let (front_matter, _) = parse_front_matter("Hi").unwrap();
+        assert!(front_matter.tags.is_empty());
+
+        assert!(parse_front_matter("+++\ntags = [1]\n+++\n").is_err());
+    }
+
+    #[test]
+    fn description() {
+        use super::parse_front_matter;
+
+        let (front_matter, _) = parse_front_matter("+++\ndescription = \"A <short> post\"\n+++\n").unwrap();
+        assert_eq!(front_matter.description.as_deref(), Some("A <short> post"));
+
+        let (front_matter, _) = parse_front_matter("Hi").unwrap();
+        assert_eq!(front_matter.description, None);
+
+        assert!(parse_front_matter("+++\ndescription = 1\n+++\n").is_err());
+    }
+}
@@ -1,10 +1,43 @@
-use std::{cell::RefCell, sync::OnceLock};
+use std::{
+    cell::RefCell,
+    collections::BTreeSet,
+    sync::{Mutex, OnceLock},
+};
 use tree_sitter_highlight::{HighlightConfiguration, HighlightEvent, Highlighter as Highlighter_};
 
 /// takes a language name, returns canonical name and highlight configuration
 type Configurations = dyn (Fn(&str) -> Option<(&'static str, &'static HighlightConfiguration)>) + Send + Sync;
 static CONFIGURATIONS: OnceLock<Box<Configurations>> = OnceLock::new();
 
+/// Unknown languages that have been warned about during the current build.
+static WARNED_LANGUAGES: Mutex<BTreeSet<String>> = Mutex::new(BTreeSet::new());
+
+/// Language names accepted for highlighting, including aliases.
+static SUPPORTED_LANGUAGES: &[&str] = &[
+    "bash",
+    "sh",
+    "shell",
+    "c",
+    "cpp",
+    "c++",
+    "css",
+    "djot",
+    "go",
+    "golang",
+    "html",
+    "json",
+    "nix",
+    "python",
+    "rust",
+    "toml",
+    "typescript",
+    "ts",
+    "javascript",
+    "js",
+    "yaml",
+    "yml",
+];
+
 thread_local!(static HIGHLIGHTER: RefCell<Highlighter> = RefCell::new(Highlighter::new()));
 
 /// Tuple of (treesitter higlight, neovim highlight group)
@@ -67,6 +100,12 @@ fn init_configurations() -> Box<Configurations> {
     }));
     cpp_config.configure(HIGHLIGHT_NAMES);
 
+    let css_config = Box::leak::<'static>(Box::new({
+        let highlights = tree_sitter_css::HIGHLIGHTS_QUERY;
+        HighlightConfiguration::new(tree_sitter_css::language(), &highlights, "", "").unwrap()
+    }));
+    css_config.configure(HIGHLIGHT_NAMES);
+
     let djot_config = Box::leak::<'static>(Box::new({
         let highlights = tree_sitter_djot::HIGHLIGHTS_QUERY;
         HighlightConfiguration::new(
@@ -79,6 +118,30 @@ fn init_configurations() -> Box<Configurations> {
     }));
     djot_config.configure(HIGHLIGHT_NAMES);
 
+    let go_config = Box::leak::<'static>(Box::new({
+        let highlights = tree_sitter_go::HIGHLIGHT_QUERY;
+        HighlightConfiguration::new(tree_sitter_go::language(), &highlights, "", "").unwrap()
+    }));
+    go_config.configure(HIGHLIGHT_NAMES);
+
+    let html_config = Box::leak::<'static>(Box::new({
+        let highlights = tree_sitter_html::HIGHLIGHT_QUERY;
+        HighlightConfiguration::new(
+            tree_sitter_html::language(),
+            &highlights,
+            tree_sitter_html::INJECTION_QUERY,
+            "",
+        )
+        .unwrap()
+    }));
+    html_config.configure(HIGHLIGHT_NAMES);
+
+    let json_config = Box::leak::<'static>(Box::new({
+        let highlights = tree_sitter_json::HIGHLIGHT_QUERY;
+        HighlightConfiguration::new(tree_sitter_json::language(), &highlights, "", "").unwrap()
+    }));
+    json_config.configure(HIGHLIGHT_NAMES);
+
     let nix_config = Box::leak::<'static>(Box::new({
         let highlights = tree_sitter_nix::HIGHLIGHTS_QUERY;
         HighlightConfiguration::new(tree_sitter_nix::language(), &highlights, "", "").unwrap()
@@ -89,7 +152,7 @@ fn init_configurations() -> Box<Configurations> {
         let highlights = tree_sitter_python::HIGHLIGHT_QUERY;
         HighlightConfiguration::new(tree_sitter_python::language(), &highlights, "", "").unwrap()
     }));
-    nix_config.configure(HIGHLIGHT_NAMES);
+    python_config.configure(HIGHLIGHT_NAMES);
 
     let rust_config = Box::leak::<'static>(Box::new({
         let highlights = tree_sitter_rust::HIGHLIGHT_QUERY;
@@ -126,16 +189,27 @@ fn init_configurations() -> Box<Configurations> {
     }));
     typescript_config.configure(HIGHLIGHT_NAMES);
 
+    let yaml_config = Box::leak::<'static>(Box::new({
+        let highlights = include_str!("highlight/yaml.scm");
+        HighlightConfiguration::new(tree_sitter_yaml::language(), &highlights, "", "").unwrap()
+    }));
+    yaml_config.configure(HIGHLIGHT_NAMES);
+
     let highlight_configurations = |language: &'_ str| match language {
         "bash" | "sh" | "shell" => Some(("bash", bash_config as &'static _)),
         "c" => Some(("c", c_config as &'static _)),
         "cpp" | "c++" => Some(("cpp", cpp_config as &'static _)),
+        "css" => Some(("css", css_config as &'static _)),
         "djot" => Some(("djot", djot_config as &'static _)),
+        "go" | "golang" => Some(("go", go_config as &'static _)),
+        "html" => Some(("html", html_config as &'static _)),
+        "json" => Some(("json", json_config as &'static _)),
         "nix" => Some(("nix", nix_config as &'static _)),
         "python" => Some(("python", python_config as &'static _)),
         "rust" => Some(("rust", rust_config as &'static _)),
         "toml" => Some(("toml", toml_config as &'static _)),
         "typescript" | "ts" | "javascript" | "js" => Some(("typescript", typescript_config as &'static _)),
+        "yaml" | "yml" => Some(("yaml", yaml_config as &'static _)),
         _ => None,
     };
 
@@ -153,7 +227,7 @@ impl Highlighter {
     }
 }
 
-fn highlight_inner(code: &str, language: &str) -> Result<(&'static str, String), Error> {
+fn highlight_inner(code: &str, language: &str, class_prefix: &str) -> Result<(&'static str, String), Error> {
     let code = code.as_bytes();
 
     HIGHLIGHTER.with_borrow_mut(|this| {
@@ -176,11 +250,15 @@ fn highlight_inner(code: &str, language: &str) -> Result<(&'static str, String),
                     }
                 }
                 HighlightEvent::HighlightStart(s) => {
-                    let mut class = std::borrow::Cow::Borrowed(HIGHLIGHT_NAMES[s.0]);
-                    if class.contains('.') {
-                        class = std::borrow::Cow::Owned(class.replace('.', " "));
+                    buf.extend_from_slice(br#"<span class=""#);
+                    for (idx, class) in HIGHLIGHT_NAMES[s.0].split('.').enumerate() {
+                        if idx > 0 {
+                            buf.push(b' ');
+                        }
+                        buf.extend_from_slice(class_prefix.as_bytes());
+                        buf.extend_from_slice(class.as_bytes());
                     }
-                    buf.extend_from_slice(format!(r#"<span class="{class}">"#).as_bytes());
+                    buf.extend_from_slice(br#"">"#);
                 }
                 HighlightEvent::HighlightEnd => {
                     buf.extend_from_slice("</span>".as_bytes());
@@ -197,16 +275,35 @@ pub enum Highlighted<'s> {
     Highlighted { language: &'s str, highlighted: String },
 }
 
-pub fn highlight<'s>(code: &str, language: &'s str) -> anyhow::Result<Highlighted<'s>> {
+/// The language names that can be highlighted, including aliases.
+pub fn supported_languages() -> &'static [&'static str] {
+    SUPPORTED_LANGUAGES
+}
+
+/// Forget which unknown languages have been warned about, such that they are warned about again
+/// in the next build.
+pub fn reset_warnings() {
+    WARNED_LANGUAGES.lock().unwrap().clear();
+}
+
+/// Highlight `code` as `language`. The classes of highlight spans are prefixed by `class_prefix`,
+/// e.g., `hl-keyword` for the prefix `hl-`.
+pub fn highlight<'s>(code: &str, language: &'s str, class_prefix: &str) -> anyhow::Result<Highlighted<'s>> {
     if matches!(language, "" | "plain" | "text" | "plaintext") {
         let mut res = String::new();
         pulldown_cmark_escape::escape_html_body_text(&mut res, code)?;
         Ok(Highlighted::Plain(res))
     } else {
-        match highlight_inner(&code, language) {
+        match highlight_inner(&code, language, class_prefix) {
             Ok((language, highlighted)) => Ok(Highlighted::Highlighted { language, highlighted }),
             Err(Error::InvalidLanguage) => {
-                log::warn!("an invalid highlight language was requested: {language}");
+                if WARNED_LANGUAGES.lock().unwrap().insert(language.to_owned()) {
+                    log::warn!(
+                        "Code block language \"{language}\" is not supported, it is rendered as plain text. \
+                        Supported languages: {}",
+                        SUPPORTED_LANGUAGES.join(", ")
+                    );
+                }
                 let mut res = String::new();
                 pulldown_cmark_escape::escape_html_body_text(&mut res, code)?;
                 Ok(Highlighted::Highlighted {
@@ -218,3 +315,39 @@ pub fn highlight<'s>(code: &str, language: &'s str) -> anyhow::Result<Highlighte
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::{highlight, init_configurations, supported_languages, Highlighted, CONFIGURATIONS};
+
+    #[test]
+    fn supported() {
+        let configurations = CONFIGURATIONS.get_or_init(init_configurations);
+        for language in supported_languages() {
+            assert!(configurations(language).is_some(), "{language}");
+        }
+        assert!(configurations("rs").is_none());
+    }
+
+    #[test]
+    fn languages() {
+        for (language, canonical, code) in [
+            ("css", "css", "a { color: red; }"),
+            ("golang", "go", "package main\n\nfunc main() {}\n"),
+            ("html", "html", "<p class=\"foo\">bar</p>\n"),
+            ("json", "json", r#"{"foo": [1, true, null]}"#),
+            ("yml", "yaml", "foo: bar # baz\n"),
+        ] {
+            match highlight(code, language, "").unwrap() {
+                Highlighted::Highlighted {
+                    language: highlighted_language,
+                    highlighted,
+                } => {
+                    assert_eq!(highlighted_language, canonical);
+                    assert!(highlighted.contains("<span class="), "{language}: {highlighted}");
+                }
+                Highlighted::Plain(_) => panic!("{language} was not highlighted"),
+            }
+        }
+    }
+}
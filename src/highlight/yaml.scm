@@ -0,0 +1,59 @@
+; The tree-sitter-yaml crate does not ship highlight queries. Capture names are limited to those
+; in `HIGHLIGHT_NAMES`.
+
+(comment) @comment
+
+(block_mapping_pair
+  key: (flow_node [(double_quote_scalar) (single_quote_scalar)] @property))
+(block_mapping_pair
+  key: (flow_node (plain_scalar (string_scalar) @property)))
+(flow_mapping
+  (_ key: (flow_node [(double_quote_scalar) (single_quote_scalar)] @property)))
+(flow_mapping
+  (_ key: (flow_node (plain_scalar (string_scalar) @property))))
+
+[
+  (boolean_scalar)
+  (null_scalar)
+  (integer_scalar)
+  (float_scalar)
+] @constant
+
+[
+  (double_quote_scalar)
+  (single_quote_scalar)
+  (block_scalar)
+  (string_scalar)
+] @string
+
+(escape_sequence) @string.special
+
+[
+  (anchor_name)
+  (alias_name)
+] @variable
+
+(tag) @type
+
+[
+  (yaml_directive)
+  (tag_directive)
+  (reserved_directive)
+] @attribute
+
+[
+  ","
+  "-"
+  ":"
+  ">"
+  "?"
+  "|"
+  "["
+  "]"
+  "{"
+  "}"
+  "*"
+  "&"
+  "---"
+  "..."
+] @punctuation
@@ -1,13 +1,20 @@
+use anyhow::Context;
 use image::GenericImageView;
 use std::{
-    collections::{HashMap, HashSet},
+    collections::HashMap,
     fs,
     io::Cursor,
     path::{Path, PathBuf},
     sync::{Arc, Mutex},
+    time::SystemTime,
 };
 
-use crate::{ir_markup, out::Out, types};
+use crate::{
+    config, ir_markup,
+    out::{FileStamp, Out},
+    report::BuildReport,
+    types, Ctx,
+};
 
 #[derive(Debug)]
 struct Response {
@@ -15,22 +22,83 @@ struct Response {
     write_files: Vec<(PathBuf, Vec<u8>)>,
 }
 
+/// An image to be processed.
+struct Work<'a> {
+    idx: usize,
+    image_link: &'a str,
+    in_file: PathBuf,
+    out_file: PathBuf,
+    modified: SystemTime,
+    image_data: Vec<u8>,
+}
+
+/// How image metadata is handled.
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct MetadataOptions {
+    strip: bool,
+    apply_orientation: bool,
+}
+
+/// Encode quality of lossy image formats, from 1 to 100.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Quality {
+    pub jpeg: u8,
+    pub avif: u8,
+}
+
+/// A processed image, along with what it was processed from. The encoded images are not kept in
+/// memory, only the stamps of the files they were written to.
+struct CachedImage {
+    modified: SystemTime,
+    widths: Vec<u32>,
+    formats: Vec<config::ImageFormat>,
+    metadata: MetadataOptions,
+    quality: Quality,
+    out_file: PathBuf,
+    images: types::Images,
+    outputs: Vec<(PathBuf, FileStamp)>,
+}
+
+/// Processed images kept between builds, keyed by source path. An image is only processed again
+/// if its source file was modified, the image configuration changed, or one of its output files
+/// changed. Images that are no longer linked to are dropped from the cache after a build.
+pub struct ImageCache {
+    images: Mutex<HashMap<PathBuf, CachedImage>>,
+}
+
+impl ImageCache {
+    pub fn new() -> Self {
+        ImageCache {
+            images: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
 #[inline]
-fn make_image_path_for_width<const W: u32>(path: &Path) -> PathBuf {
+fn make_image_path_for_width(path: &Path, width: u32) -> PathBuf {
     let mut image_path = path.to_owned();
     let file_stem = image_path.file_stem().unwrap();
     let extension = image_path.extension().unwrap();
     let mut file_name = file_stem.to_owned();
-    file_name.push(format!("-{}.", W));
+    file_name.push(format!("-{}.", width));
     file_name.push(extension);
     image_path.set_file_name(file_name);
     image_path
 }
 
-fn encode_image(image: &image::DynamicImage, format: image::ImageFormat) -> anyhow::Result<Vec<u8>> {
+fn encode_image(image: &image::DynamicImage, format: image::ImageFormat, quality: Quality) -> anyhow::Result<Vec<u8>> {
     let mut buf = Cursor::new(Vec::new());
 
     match format {
+        image::ImageFormat::Jpeg => {
+            let encoder = image::codecs::jpeg::JpegEncoder::new_with_quality(&mut buf, quality.jpeg);
+            image.write_with_encoder(encoder)?;
+        }
+        #[cfg(feature = "avif")]
+        image::ImageFormat::Avif => {
+            let encoder = image::codecs::avif::AvifEncoder::new_with_speed_quality(&mut buf, 4, quality.avif);
+            image.write_with_encoder(encoder)?;
+        }
         image::ImageFormat::Png => {
             let encoder = image::codecs::png::PngEncoder::new_with_quality(
                 &mut buf,
@@ -47,14 +115,302 @@ fn encode_image(image: &image::DynamicImage, format: image::ImageFormat) -> anyh
     Ok(buf.into_inner())
 }
 
-fn extract_image(out_file: PathBuf, image_data: Vec<u8>) -> anyhow::Result<Response> {
+/// Remove EXIF, XMP and IPTC metadata from a JPEG without re-encoding it. Other segments, such as
+/// ICC color profiles, are kept.
+fn strip_jpeg_metadata(jpeg: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let Some(mut rest) = jpeg.strip_prefix(&[0xff, 0xd8]) else {
+        anyhow::bail!("not a JPEG");
+    };
+
+    let mut stripped = vec![0xff, 0xd8];
+    loop {
+        match *rest {
+            // Entropy-coded data follows the start of scan, which is kept as-is
+            [0xff, 0xda, ..] | [0xff, 0xd9, ..] => {
+                stripped.extend_from_slice(rest);
+                return Ok(stripped);
+            }
+            // Fill bytes
+            [0xff, 0xff, ..] => rest = &rest[1..],
+            [0xff, marker, len_hi, len_lo, ..] => {
+                let len = 2 + u16::from_be_bytes([len_hi, len_lo]) as usize;
+                let Some(segment) = rest.get(..len) else {
+                    anyhow::bail!("truncated JPEG segment");
+                };
+                // APP1 holds EXIF and XMP, APP13 holds IPTC
+                if !matches!(marker, 0xe1 | 0xed) {
+                    stripped.extend_from_slice(segment);
+                }
+                rest = &rest[len..];
+            }
+            _ => anyhow::bail!("malformed JPEG"),
+        }
+    }
+}
+
+/// The EXIF orientation of an image, from 1 to 8, if set. EXIF is read from JPEG, PNG and WebP
+/// images.
+fn orientation(format: image::ImageFormat, image_data: &[u8]) -> Option<u16> {
+    let tiff = match format {
+        image::ImageFormat::Jpeg => jpeg_exif(image_data)?,
+        image::ImageFormat::Png => png_exif(image_data)?,
+        image::ImageFormat::WebP => webp_exif(image_data)?,
+        _ => return None,
+    };
+    exif_orientation(tiff)
+}
+
+/// The EXIF data of a JPEG, from its APP1 segment.
+fn jpeg_exif(jpeg: &[u8]) -> Option<&[u8]> {
+    let mut rest = jpeg.strip_prefix(&[0xff, 0xd8])?;
+    while let [0xff, marker, len_hi, len_lo, ..] = *rest {
+        if marker == 0xda {
+            break;
+        }
+        let len = 2 + u16::from_be_bytes([len_hi, len_lo]) as usize;
+        let segment = rest.get(4..len)?;
+        if let Some(tiff) = segment.strip_prefix(b"Exif\0\0").filter(|_| marker == 0xe1) {
+            return Some(tiff);
+        }
+        rest = &rest[len..];
+    }
+    None
+}
+
+/// The EXIF data of a PNG, from its `eXIf` chunk.
+fn png_exif(png: &[u8]) -> Option<&[u8]> {
+    let mut rest = png.strip_prefix(b"\x89PNG\r\n\x1a\n")?;
+    while let Some(header) = rest.get(..8) {
+        let len = u32::from_be_bytes(header[..4].try_into().ok()?) as usize;
+        let data = rest.get(8..8 + len)?;
+        match &header[4..] {
+            b"eXIf" => return Some(data),
+            b"IEND" => break,
+            _ => {}
+        }
+        // Skip the chunk's CRC
+        rest = rest.get(8 + len + 4..)?;
+    }
+    None
+}
+
+/// The EXIF data of a WebP, from its `EXIF` chunk.
+fn webp_exif(webp: &[u8]) -> Option<&[u8]> {
+    if webp.get(..4)? != b"RIFF" || webp.get(8..12)? != b"WEBP" {
+        return None;
+    }
+    let mut rest = &webp[12..];
+    while let Some(header) = rest.get(..8) {
+        let len = u32::from_le_bytes(header[4..].try_into().ok()?) as usize;
+        let data = rest.get(8..8 + len)?;
+        if &header[..4] == b"EXIF" {
+            // Some encoders prefix the data like in JPEG
+            return Some(data.strip_prefix(b"Exif\0\0").unwrap_or(data));
+        }
+        // Chunks are padded to an even length
+        rest = rest.get(8 + len + len % 2..)?;
+    }
+    None
+}
+
+/// The orientation tag in the first IFD of EXIF data, which is laid out like a TIFF file.
+fn exif_orientation(tiff: &[u8]) -> Option<u16> {
+    let little_endian = match tiff.get(..2)? {
+        b"II" => true,
+        b"MM" => false,
+        _ => return None,
+    };
+    let read_u16 = |offset: usize| -> Option<u16> {
+        let bytes = tiff.get(offset..offset + 2)?.try_into().ok()?;
+        Some(if little_endian {
+            u16::from_le_bytes(bytes)
+        } else {
+            u16::from_be_bytes(bytes)
+        })
+    };
+    let read_u32 = |offset: usize| -> Option<u32> {
+        let bytes = tiff.get(offset..offset + 4)?.try_into().ok()?;
+        Some(if little_endian {
+            u32::from_le_bytes(bytes)
+        } else {
+            u32::from_be_bytes(bytes)
+        })
+    };
+
+    let ifd = read_u32(4)? as usize;
+    for entry in 0..read_u16(ifd)? as usize {
+        let entry = ifd + 2 + entry * 12;
+        if read_u16(entry)? == 0x0112 {
+            return read_u16(entry + 8).filter(|orientation| (1..=8).contains(orientation));
+        }
+    }
+    None
+}
+
+/// Rotate and flip an image such that it is displayed upright without its EXIF orientation.
+fn apply_orientation(image: image::DynamicImage, orientation: u16) -> image::DynamicImage {
+    match orientation {
+        2 => image.fliph(),
+        3 => image.rotate180(),
+        4 => image.flipv(),
+        5 => image.rotate90().fliph(),
+        6 => image.rotate90(),
+        7 => image.rotate270().fliph(),
+        8 => image.rotate270(),
+        _ => image,
+    }
+}
+
+/// The attributes of the first tag named `name` in `markup`, as name and value pairs. Parsing stops
+/// at the first malformed attribute.
+fn tag_attributes<'a>(markup: &'a str, name: &str) -> Vec<(&'a str, &'a str)> {
+    let mut attributes = vec![];
+
+    let Some(start) = markup.match_indices('<').map(|(idx, _)| idx + 1).find(|&idx| {
+        markup[idx..].starts_with(name)
+            && markup[idx + name.len()..].starts_with(|c: char| c.is_ascii_whitespace() || c == '>' || c == '/')
+    }) else {
+        return attributes;
+    };
+
+    let mut rest = &markup[start + name.len()..];
+    loop {
+        rest = rest.trim_start();
+        let Some(eq) = rest.find('=').filter(|&eq| !rest[..eq].contains(['>', '/'])) else {
+            break;
+        };
+        let attribute = rest[..eq].trim_end();
+        let value = rest[eq + 1..].trim_start();
+        let Some(quote) = value.chars().next().filter(|&c| c == '"' || c == '\'') else {
+            break;
+        };
+        let Some(end) = value[1..].find(quote) else {
+            break;
+        };
+        attributes.push((attribute, &value[1..1 + end]));
+        rest = &value[1 + end + 1..];
+    }
+
+    attributes
+}
+
+/// The dimensions of an SVG in pixels, from the `width` and `height` attributes of its root element
+/// and otherwise from its `viewBox`. Lengths in units other than pixels are not supported.
+fn svg_dimensions(svg: &str) -> Option<(u32, u32)> {
+    let attributes = tag_attributes(svg, "svg");
+    let attribute = |name: &str| {
+        attributes
+            .iter()
+            .find(|(attribute, _)| *attribute == name)
+            .map(|(_, value)| *value)
+    };
+    let length = |name: &str| -> Option<f64> {
+        let value = attribute(name)?.trim();
+        value
+            .strip_suffix("px")
+            .unwrap_or(value)
+            .parse()
+            .ok()
+            .filter(|&length: &f64| length > 0.0)
+    };
+    let view_box = attribute("viewBox").and_then(|view_box| {
+        let values: Vec<f64> = view_box
+            .split(|c: char| c.is_ascii_whitespace() || c == ',')
+            .filter(|value| !value.is_empty())
+            .map(|value| value.parse().ok())
+            .collect::<Option<_>>()?;
+        match values[..] {
+            [_, _, width, height] if width > 0.0 && height > 0.0 => Some((width, height)),
+            _ => None,
+        }
+    });
+
+    let (width, height) = match (length("width"), length("height"), view_box) {
+        (Some(width), Some(height), _) => (width, height),
+        // A missing length follows the aspect ratio of the view box
+        (Some(width), None, Some((view_width, view_height))) => (width, width * view_height / view_width),
+        (None, Some(height), Some((view_width, view_height))) => (height * view_width / view_height, height),
+        (None, None, Some(view_box)) => view_box,
+        _ => return None,
+    };
+
+    Some((width.round() as u32, height.round() as u32))
+}
+
+/// Get the `image` crate format, file extension and MIME type of an alternative image format.
+fn format_info(format: config::ImageFormat) -> (image::ImageFormat, &'static str, &'static str) {
+    match format {
+        config::ImageFormat::Webp => (image::ImageFormat::WebP, "webp", "image/webp"),
+        config::ImageFormat::Avif => (image::ImageFormat::Avif, "avif", "image/avif"),
+    }
+}
+
+/// Encode the full image and its resized variants in an alternative format. Variants are only kept
+/// if they are smaller than the full image in that format.
+fn extract_source(
+    format: config::ImageFormat,
+    quality: Quality,
+    out_file: &Path,
+    image: &image::DynamicImage,
+    resized: &[(u32, image::DynamicImage)],
+) -> anyhow::Result<(types::ImageSource, Vec<(PathBuf, Vec<u8>)>)> {
+    let (format, extension, mime_type) = format_info(format);
+
+    let mut source = types::ImageSource {
+        mime_type,
+        original: out_file.with_extension(extension),
+        variants: vec![],
+    };
+
+    let full = encode_image(image, format, quality)?;
+    let mut write_files = vec![];
+    for (target_width, image) in resized {
+        let out_file = make_image_path_for_width(&source.original, *target_width);
+        let result = encode_image(image, format, quality)?;
+        if result.len() < full.len() {
+            source.variants.push((*target_width, out_file.clone()));
+            write_files.push((out_file, result));
+        }
+    }
+    write_files.push((source.original.clone(), full));
+
+    Ok((source, write_files))
+}
+
+/// Process an image, generating a resized variant for every width in `widths` that is smaller than
+/// the original. `widths` is ordered from largest to smallest. Additionally, the image and its
+/// variants are encoded in every format in `formats`.
+fn extract_image(
+    widths: &[u32],
+    formats: &[config::ImageFormat],
+    metadata: MetadataOptions,
+    quality: Quality,
+    out_file: PathBuf,
+    image_data: Vec<u8>,
+) -> anyhow::Result<Response> {
     let mut images = types::Images {
         original: out_file.clone(),
         original_width: None,
-        x_1536: None,
-        x_768: None,
+        original_height: None,
+        variants: vec![],
+        sources: vec![],
     };
 
+    // SVGs are copied as-is, but their dimensions are known for styling
+    if out_file
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("svg"))
+    {
+        if let Some((width, height)) = std::str::from_utf8(&image_data).ok().and_then(svg_dimensions) {
+            images.original_width = Some(width);
+            images.original_height = Some(height);
+        }
+        return anyhow::Ok(Response {
+            images,
+            write_files: vec![(out_file, image_data)],
+        });
+    }
+
     let format = match image::ImageFormat::from_path(&out_file) {
         Ok(format) => format,
         Err(_) => {
@@ -70,9 +426,19 @@ fn extract_image(out_file: PathBuf, image_data: Vec<u8>) -> anyhow::Result<Respo
         reader.set_format(format);
         reader.decode()?
     };
+    let orientation = if metadata.apply_orientation {
+        orientation(format, &image_data).filter(|&orientation| orientation != 1)
+    } else {
+        None
+    };
+    let image = match orientation {
+        Some(orientation) => apply_orientation(image, orientation),
+        None => image,
+    };
     let (width, height) = image.dimensions();
 
     images.original_width = Some(width);
+    images.original_height = Some(height);
 
     let (try_reencode, target_format) = match format {
         image::ImageFormat::Jpeg => (false, image::ImageFormat::Jpeg),
@@ -81,52 +447,85 @@ fn extract_image(out_file: PathBuf, image_data: Vec<u8>) -> anyhow::Result<Respo
         _ => (true, image::ImageFormat::Png),
     };
 
-    let full = if format != target_format || try_reencode {
-        let reencoded = encode_image(&image, target_format)?;
-        if format != target_format || reencoded.len() < image_data.len() {
+    // Re-encoded images carry no metadata. JPEGs are not re-encoded unless their pixels changed,
+    // as that loses quality, so their metadata is removed separately.
+    let full = if format != target_format || orientation.is_some() {
+        encode_image(&image, target_format, quality)?
+    } else if try_reencode {
+        let reencoded = encode_image(&image, target_format, quality)?;
+        if metadata.strip || reencoded.len() < image_data.len() {
             reencoded
         } else {
             image_data
         }
+    } else if metadata.strip {
+        strip_jpeg_metadata(&image_data)?
     } else {
         image_data
     };
 
+    let resized: Vec<(u32, image::DynamicImage)> = widths
+        .iter()
+        .filter(|&&target_width| target_width < width)
+        .map(|&target_width| {
+            let image = image.resize(target_width, height, image::imageops::FilterType::Lanczos3);
+            (target_width, image)
+        })
+        .collect();
+
     let mut write_files = vec![];
-    if width > 1536 {
-        let out_file = make_image_path_for_width::<1536>(&out_file);
+    for (target_width, image) in &resized {
+        let out_file = make_image_path_for_width(&out_file, *target_width);
 
-        let image = image.resize(1536, height, image::imageops::FilterType::Lanczos3);
-        let result = encode_image(&image, format)?;
+        let result = encode_image(image, format, quality)?;
         if result.len() < full.len() {
-            images.x_1536 = Some(out_file.clone());
+            images.variants.push((*target_width, out_file.clone()));
             write_files.push((out_file, result));
         }
     }
 
-    if width > 768 {
-        let out_file = make_image_path_for_width::<768>(&out_file);
+    // Alternative formats are only offered when they are smaller than the fallback. Not all
+    // encoders support all images (e.g., due to color types), in that case the format is skipped.
+    for &source_format in formats {
+        if format_info(source_format).0 == target_format {
+            continue;
+        }
 
-        let image = image.resize(768, height, image::imageops::FilterType::Lanczos3);
-        let result = encode_image(&image, format)?;
-        if result.len() < full.len() {
-            images.x_768 = Some(out_file.clone());
-            write_files.push((out_file, result));
+        match extract_source(source_format, quality, &out_file, &image, &resized) {
+            Ok((source, files)) => {
+                let source_len = files.last().map(|(_, content)| content.len()).unwrap_or(0);
+                if source_len < full.len() {
+                    images.sources.push(source);
+                    write_files.extend(files);
+                }
+            }
+            Err(err) => {
+                log::debug!("Could not encode {out_file:?} as {source_format:?}: {err}");
+            }
         }
     }
+
     write_files.push((out_file, full));
 
     anyhow::Ok(Response { images, write_files })
 }
 
+/// Process the images linked to by entries, given the distinct image links of every entry. The
+/// number of processed and cached images is recorded in `report`.
 pub fn extract_images<'a>(
+    ctx: &Ctx,
     out: &Out,
+    cache: &ImageCache,
+    report: &mut BuildReport,
     entries: &[types::EntryMeta],
-    parsed_entries: &[Vec<ir_markup::Event<'a>>],
+    image_links: &'a [Vec<String>],
 ) -> anyhow::Result<Vec<HashMap<String, types::Images>>> {
-    let (tx, rx) = std::sync::mpsc::channel::<(usize, String, anyhow::Result<Response>)>();
+    // Processed images come with the files to write, cached images were already kept
+    type Processed = anyhow::Result<(CachedImage, Vec<(PathBuf, Vec<u8>)>)>;
+    let (tx, rx) = std::sync::mpsc::channel::<(usize, String, PathBuf, Processed)>();
 
     let mut images = (0..entries.len()).map(|_| HashMap::new()).collect::<Vec<_>>();
+    let mut previous = std::mem::take(&mut *cache.images.lock().unwrap());
 
     // Read and write image data serially, process concurrently. Uses quite some memory but keeps
     // i/o fast
@@ -137,56 +536,261 @@ pub fn extract_images<'a>(
         let t = {
             let mutex = mutex.clone();
             std_s.spawn(move || {
-                for (idx, link, response) in rx {
-                    let response = response?;
-                    images[idx].insert(link, response.images);
-                    for (path, content) in response.write_files {
+                let mut current = HashMap::new();
+                for (idx, link, in_file, processed) in rx {
+                    let (mut cached, write_files) =
+                        processed.with_context(|| format!("in entry {}", entries[idx].file_path.display()))?;
+                    images[idx].insert(link, cached.images.clone());
+                    for (path, content) in write_files {
                         let m = mutex.lock().unwrap();
-                        out.update_file(&mut &*content, path)?;
+                        out.update_file(&mut &*content, &path)?;
+                        let stamp = out.stamp(&path)?;
                         drop(m);
+                        cached.outputs.push((path, stamp));
                     }
+                    current.insert(in_file, cached);
                 }
 
-                return anyhow::Ok(images);
+                return anyhow::Ok((images, current));
             })
         };
 
-        rayon::scope(move |s| {
-            let mut links = HashSet::<&'a str>::new();
-            for (idx, (entry, parsed_entry)) in entries.iter().zip(parsed_entries).enumerate() {
-                links.clear();
-
-                for event in parsed_entry {
-                    if let ir_markup::Event::Image {
-                        destination,
-                        alt: _,
-                        attributes: _,
-                    } = event
-                    {
-                        links.insert(destination.as_ref());
+        let widths = ctx.image_widths();
+        let formats = ctx.image_formats();
+        let metadata = MetadataOptions {
+            strip: ctx.strip_image_metadata(),
+            apply_orientation: ctx.apply_image_orientation(),
+        };
+        let quality = ctx.image_quality();
+
+        // Images are processed by a fixed number of workers, taking work from a bounded queue. When
+        // the queue is full, reading blocks, such that only a bounded number of images are in
+        // memory at any one time.
+        //
+        // If writing fails, the writer stops receiving. Workers then stop as well, and the queue is
+        // dropped along with the last worker, such that reading stops instead of blocking on the
+        // full queue. The writer's error is returned when joining it.
+        let workers = rayon::current_num_threads();
+        let (work_tx, work_rx) = std::sync::mpsc::sync_channel::<Work<'a>>(workers);
+        let work_rx = Arc::new(Mutex::new(work_rx));
+
+        // Reading happens on this thread rather than on the thread pool, as it blocks while the
+        // queue is full.
+        rayon::in_place_scope(move |s| {
+            for _ in 0..workers {
+                let tx = tx.clone();
+                let work_rx = work_rx.clone();
+                s.spawn(move |_| loop {
+                    let work = match work_rx.lock().unwrap().recv() {
+                        Ok(work) => work,
+                        Err(_) => break,
+                    };
+
+                    let Work {
+                        idx,
+                        image_link,
+                        in_file,
+                        out_file,
+                        modified,
+                        image_data,
+                    } = work;
+                    let processed = extract_image(widths, formats, metadata, quality, out_file.clone(), image_data)
+                        .map(|response| {
+                            let cached = CachedImage {
+                                modified,
+                                widths: widths.to_owned(),
+                                formats: formats.to_owned(),
+                                metadata,
+                                quality,
+                                out_file,
+                                images: response.images,
+                                outputs: vec![],
+                            };
+                            (cached, response.write_files)
+                        });
+                    if tx.send((idx, image_link.to_owned(), in_file, processed)).is_err() {
+                        break;
                     }
-                }
+                });
+            }
+            drop(work_rx);
 
-                for image_link in links.drain() {
-                    let in_file = entry.asset_dir.join(image_link);
-                    let out_file = entry.out_asset_dir.join(image_link);
+            let entries_by_name: HashMap<&str, usize> = entries
+                .iter()
+                .enumerate()
+                .map(|(idx, entry)| (entry.canonical_name.as_str(), idx))
+                .collect();
+
+            'entries: for (idx, (entry, links)) in entries.iter().zip(image_links).enumerate() {
+                for image_link in links.iter().map(String::as_str) {
+                    // Internal image links refer to the assets of another entry, and are written to
+                    // that entry's output asset directory
+                    let (asset_dir, out_asset_dir, path) = match image_link.strip_prefix("~/") {
+                        Some(link) => match ir_markup::resolve_internal_asset(link, &entries_by_name) {
+                            Some((target, path)) => (&entries[target].asset_dir, &entries[target].out_asset_dir, path),
+                            // Unknown entries are reported when rewriting internal links
+                            None => continue,
+                        },
+                        None => (&entry.asset_dir, &entry.out_asset_dir, image_link),
+                    };
+                    let in_file = asset_dir.join(path);
+                    let out_file = out_asset_dir.join(path);
                     let m = mutex.lock().unwrap();
+                    let modified = fs::metadata(&in_file)?.modified()?;
+
+                    if let Some(cached) = previous.remove(&in_file).filter(|cached| {
+                        cached.modified == modified
+                            && cached.widths == widths
+                            && cached.formats == formats
+                            && cached.metadata == metadata
+                            && cached.quality == quality
+                            && cached.out_file == out_file
+                            && cached
+                                .outputs
+                                .iter()
+                                .all(|(path, stamp)| out.keep_file_if_unchanged(path, *stamp))
+                    }) {
+                        drop(m);
+                        report.images_cached += 1;
+                        if tx
+                            .send((idx, image_link.to_owned(), in_file, Ok((cached, vec![]))))
+                            .is_err()
+                        {
+                            break 'entries;
+                        }
+                        continue;
+                    }
+
                     let image_data = fs::read(&in_file)?;
                     drop(m);
+                    report.images_processed += 1;
 
-                    let tx = tx.clone();
-                    // this provides no backpresure. if processing is much slower than reading from
-                    // disk, we can easily exhaust memory
-                    s.spawn(move |_| {
-                        tx.send((idx, image_link.to_owned(), extract_image(out_file, image_data)))
-                            .unwrap();
-                    });
+                    let work = Work {
+                        idx,
+                        image_link,
+                        in_file,
+                        out_file,
+                        modified,
+                        image_data,
+                    };
+                    if work_tx.send(work).is_err() {
+                        break 'entries;
+                    }
                 }
             }
 
             anyhow::Ok(())
         })?;
 
-        anyhow::Ok(t.join().unwrap()?)
+        let (images, current) = t.join().unwrap()?;
+        *cache.images.lock().unwrap() = current;
+        anyhow::Ok(images)
     })
 }
+
+#[cfg(test)]
+mod test {
+    /// A JPEG header with an EXIF segment setting the given orientation, followed by an ICC
+    /// profile segment and the start of scan.
+    fn jpeg_with_orientation(orientation: u16) -> Vec<u8> {
+        let mut tiff = b"MM\0\x2a\0\0\0\x08".to_vec();
+        tiff.extend_from_slice(&1u16.to_be_bytes());
+        tiff.extend_from_slice(&[0x01, 0x12, 0x00, 0x03, 0x00, 0x00, 0x00, 0x01]);
+        tiff.extend_from_slice(&orientation.to_be_bytes());
+        tiff.extend_from_slice(&[0, 0, 0, 0, 0, 0]);
+
+        let mut jpeg = vec![0xff, 0xd8, 0xff, 0xe1];
+        jpeg.extend_from_slice(&(2 + 6 + tiff.len() as u16).to_be_bytes());
+        jpeg.extend_from_slice(b"Exif\0\0");
+        jpeg.extend_from_slice(&tiff);
+        jpeg.extend_from_slice(&[0xff, 0xe2, 0x00, 0x04, 0xab, 0xcd]);
+        jpeg.extend_from_slice(&[0xff, 0xda, 0x01, 0x02, 0xff, 0xd9]);
+        jpeg
+    }
+
+    #[test]
+    fn strip_jpeg_metadata() {
+        use super::strip_jpeg_metadata;
+
+        assert_eq!(
+            strip_jpeg_metadata(&jpeg_with_orientation(6)).unwrap(),
+            [0xff, 0xd8, 0xff, 0xe2, 0x00, 0x04, 0xab, 0xcd, 0xff, 0xda, 0x01, 0x02, 0xff, 0xd9]
+        );
+        assert!(strip_jpeg_metadata(b"\x89PNG").is_err());
+        assert!(strip_jpeg_metadata(&[0xff, 0xd8, 0xff, 0xe1, 0x00, 0x10]).is_err());
+    }
+
+    #[test]
+    fn orientation() {
+        use super::orientation;
+        use image::ImageFormat;
+
+        assert_eq!(orientation(ImageFormat::Jpeg, &jpeg_with_orientation(6)), Some(6));
+        assert_eq!(orientation(ImageFormat::Jpeg, &jpeg_with_orientation(1)), Some(1));
+        assert_eq!(orientation(ImageFormat::Jpeg, &jpeg_with_orientation(9)), None);
+        assert_eq!(orientation(ImageFormat::Jpeg, &[0xff, 0xd8, 0xff, 0xda, 0x00]), None);
+        assert_eq!(orientation(ImageFormat::Png, &jpeg_with_orientation(6)), None);
+
+        // The same EXIF data in a PNG `eXIf` chunk, after an `IHDR` chunk
+        let tiff = &jpeg_with_orientation(8)[12..12 + 26];
+        let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+        png.extend_from_slice(&[0, 0, 0, 1]);
+        png.extend_from_slice(b"IHDR\0\0\0\0\0");
+        png.extend_from_slice(&(tiff.len() as u32).to_be_bytes());
+        png.extend_from_slice(b"eXIf");
+        png.extend_from_slice(tiff);
+        png.extend_from_slice(&[0, 0, 0, 0]);
+        assert_eq!(orientation(ImageFormat::Png, &png), Some(8));
+
+        // And in a WebP `EXIF` chunk, after an odd-length chunk
+        let mut webp = b"RIFF\0\0\0\0WEBP".to_vec();
+        webp.extend_from_slice(b"VP8X\x01\0\0\0\0\0");
+        webp.extend_from_slice(b"EXIF");
+        webp.extend_from_slice(&(tiff.len() as u32).to_le_bytes());
+        webp.extend_from_slice(tiff);
+        assert_eq!(orientation(ImageFormat::WebP, &webp), Some(8));
+    }
+
+    #[test]
+    fn svg_dimensions() {
+        use super::svg_dimensions;
+
+        assert_eq!(
+            svg_dimensions(
+                r#"<?xml version="1.0"?><svg xmlns="http://www.w3.org/2000/svg" width="120" height="80px"></svg>"#
+            ),
+            Some((120, 80))
+        );
+        assert_eq!(
+            svg_dimensions("<svg viewBox='0 0 300.5 150'>\n<rect width=\"10\" height=\"10\"/></svg>"),
+            Some((301, 150))
+        );
+        assert_eq!(
+            svg_dimensions(r#"<svg width="600" viewBox="0,0,300,150"/>"#),
+            Some((600, 300))
+        );
+        assert_eq!(svg_dimensions(r#"<svg width="100%" height="100%"/>"#), None);
+        assert_eq!(svg_dimensions(r#"<svgfoo width="1" height="1"/>"#), None);
+    }
+
+    #[test]
+    fn apply_orientation() {
+        use super::apply_orientation;
+        use image::{DynamicImage, GenericImageView, Rgb, RgbImage};
+
+        // Two pixels side by side, red then blue
+        let mut pixels = RgbImage::new(2, 1);
+        pixels.put_pixel(0, 0, Rgb([255, 0, 0]));
+        pixels.put_pixel(1, 0, Rgb([0, 0, 255]));
+        let image = DynamicImage::ImageRgb8(pixels);
+
+        let rotated = apply_orientation(image.clone(), 6);
+        assert_eq!(rotated.dimensions(), (1, 2));
+        assert_eq!(rotated.get_pixel(0, 0).0, [255, 0, 0, 255]);
+
+        let flipped = apply_orientation(image.clone(), 2);
+        assert_eq!(flipped.get_pixel(0, 0).0, [0, 0, 255, 255]);
+
+        assert_eq!(apply_orientation(image.clone(), 1), image);
+    }
+}
@@ -0,0 +1,59 @@
+use std::path::Path;
+
+/// The files of a new site, as `(path, content)`.
+const FILES: &[(&str, &str)] = &[
+    ("sprokkel.toml", include_str!("init/sprokkel.toml")),
+    ("templates/_entry.html", include_str!("init/templates/_entry.html")),
+    ("templates/index.html", include_str!("init/templates/index.html")),
+    (
+        "entries/posts/2024-01-01_hello-world.dj",
+        include_str!("init/entries/posts/2024-01-01_hello-world.dj"),
+    ),
+    ("assets/style.css", include_str!("init/assets/style.css")),
+];
+
+/// Create a minimal site at `path`. Unless `force` is set, this refuses to write into a non-empty
+/// directory. When forced, existing files of the skeleton are overwritten.
+pub fn init(path: &Path, force: bool) -> anyhow::Result<()> {
+    if !force && path.exists() && std::fs::read_dir(path)?.next().is_some() {
+        anyhow::bail!(
+            "{} is not empty, pass --force to create a site there anyway",
+            path.display()
+        );
+    }
+
+    for (file, content) in FILES {
+        let file = path.join(file);
+        if let Some(parent) = file.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(&file, content)?;
+        log::info!("Created {}", file.display());
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    #[test]
+    fn init() {
+        use super::init;
+
+        let root = std::env::temp_dir().join(format!("sprokkel-init-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+
+        init(&root, false).unwrap();
+        assert!(root.join("sprokkel.toml").is_file());
+        assert!(root.join("templates").join("_entry.html").is_file());
+        assert!(root.join("entries").join("posts").is_dir());
+
+        assert!(init(&root, false).is_err());
+        init(&root, true).unwrap();
+
+        let _: crate::config::SiteConfig =
+            toml::from_str(&std::fs::read_to_string(root.join("sprokkel.toml")).unwrap()).unwrap();
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
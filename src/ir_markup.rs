@@ -2,11 +2,20 @@
 //! rendered to HTML.
 
 use bumpalo::Bump;
-use std::{borrow::Cow, collections::HashMap, fmt::Write};
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    fmt::Write,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
 
 use bitvec::vec::BitVec;
 
-use crate::{highlight, types};
+use crate::{
+    config::{HeadingAnchors, MathRenderer},
+    highlight, sanitize, types, utils,
+};
 
 #[derive(thiserror::Error, Debug)]
 pub enum Error {
@@ -56,6 +65,8 @@ impl<'s> Attributes<'s> {
             .find_map(|(attribute_, value)| (*attribute_ == attribute).then(|| value))
     }
 
+    /// Insert an attribute, replacing its previous value. Classes are the exception: inserting a
+    /// `class` attribute appends to the existing classes.
     pub fn insert(&mut self, attribute: impl Into<Cow<'s, str>>, value: impl Into<AttributeValue<'s>>) {
         let attribute = attribute.into();
 
@@ -64,7 +75,12 @@ impl<'s> Attributes<'s> {
             .iter_mut()
             .find_map(|(attribute_, value)| (*attribute_ == attribute).then(|| value))
         {
-            *entry = value.into();
+            let value = value.into();
+            if attribute != "class" || entry.to_string().is_empty() {
+                *entry = value;
+            } else if !value.to_string().is_empty() {
+                *entry = AttributeValue::Raw(format!("{entry} {value}").into());
+            }
         } else {
             // insertion to keep attributes sorted
             let mut idx = 0;
@@ -78,6 +94,21 @@ impl<'s> Attributes<'s> {
         }
     }
 
+    pub fn remove(&mut self, attribute: impl Into<Cow<'s, str>>) -> Option<AttributeValue<'s>> {
+        let attribute = attribute.into();
+        let idx = self
+            .attributes
+            .iter()
+            .position(|(attribute_, _)| *attribute_ == attribute)?;
+        Some(self.attributes.remove(idx).1)
+    }
+
+    /// Whether the `class` attribute contains `class`.
+    pub fn has_class(&self, class: &str) -> bool {
+        self.get("class")
+            .is_some_and(|classes| classes.to_string().split_whitespace().any(|class_| class_ == class))
+    }
+
     fn into_iter(self) -> impl Iterator<Item = (Cow<'s, str>, AttributeValuePlusFmt<'s>)> {
         self.attributes.into_iter().map(|(attr, val)| (attr, val.into()))
     }
@@ -110,6 +141,15 @@ impl<'s> std::fmt::Debug for AttributeValue<'s> {
     }
 }
 
+impl<'s> std::fmt::Display for AttributeValue<'s> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AttributeValue::Jotdown(val) => write!(f, "{val}"),
+            AttributeValue::Raw(val) => write!(f, "{val}"),
+        }
+    }
+}
+
 impl<'s> From<jotdown::AttributeValue<'s>> for AttributeValue<'s> {
     fn from(value: jotdown::AttributeValue<'s>) -> Self {
         AttributeValue::Jotdown(value)
@@ -232,6 +272,18 @@ impl HeadingLevel {
             HeadingLevel::H6 => "h6",
         }
     }
+
+    /// The heading level as a number, from 1 to 6.
+    pub fn number(self) -> u8 {
+        match self {
+            HeadingLevel::H1 => 1,
+            HeadingLevel::H2 => 2,
+            HeadingLevel::H3 => 3,
+            HeadingLevel::H4 => 4,
+            HeadingLevel::H5 => 5,
+            HeadingLevel::H6 => 6,
+        }
+    }
 }
 
 impl std::fmt::Display for HeadingLevel {
@@ -283,7 +335,7 @@ pub enum ListKind {
     Task,
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
 pub enum MathKind {
     Display,
     Inline,
@@ -422,6 +474,8 @@ struct Footnote {
     buf: String,
     number: std::num::NonZeroUsize,
     state: FootnoteState,
+    /// The number of references to this footnote, each of which gets a back-reference.
+    references: usize,
 }
 
 enum WriteTarget<'w> {
@@ -439,10 +493,12 @@ struct Writer<'w> {
 
     /// Footnote reference numbering and rendered footnote buffers
     footnotes: HashMap<Cow<'w, str>, Footnote>,
+
+    heading_anchors: HeadingAnchors,
 }
 
 impl<'w> Writer<'w> {
-    fn new(buf: &'w mut String) -> Self {
+    fn new(buf: &'w mut String, heading_anchors: HeadingAnchors) -> Self {
         Writer {
             buf,
 
@@ -451,6 +507,8 @@ impl<'w> Writer<'w> {
             list_tightness: BitVec::new(),
 
             footnotes: HashMap::new(),
+
+            heading_anchors,
         }
     }
 
@@ -532,20 +590,20 @@ impl<'w> Writer<'w> {
         Ok(())
     }
 
-    /// Register a footnote reference and get its number.
-    fn register_footnote_reference(&mut self, label: &Cow<'w, str>) -> usize {
+    /// Register a footnote reference and get the footnote's number and the ID of the reference.
+    fn register_footnote_reference(&mut self, label: &Cow<'w, str>) -> (usize, String) {
         let number = std::num::NonZeroUsize::new(self.footnotes.len() + 1).unwrap();
         // if https://github.com/rust-lang/rust/issues/56167 is stabilized, clone can be done only
         // when needed
-        self.footnotes
-            .entry(label.clone())
-            .or_insert(Footnote {
-                number,
-                state: FootnoteState::Missing,
-                buf: String::new(),
-            })
-            .number
-            .into()
+        let footnote = self.footnotes.entry(label.clone()).or_insert(Footnote {
+            number,
+            state: FootnoteState::Missing,
+            buf: String::new(),
+            references: 0,
+        });
+        footnote.references += 1;
+        let number = footnote.number.into();
+        (number, footnote_reference_id(number, footnote.references))
     }
 
     /// Register a footnote definition and get its number.
@@ -557,6 +615,7 @@ impl<'w> Writer<'w> {
             number,
             state: FootnoteState::Missing,
             buf: String::new(),
+            references: 0,
         });
         if matches!(entry.state, FootnoteState::Defined) {
             log::warn!("Footnote defined multiple times: {label}");
@@ -585,7 +644,24 @@ impl<'w> Writer<'w> {
             Container::Heading { level, id } => {
                 self.write_tag_with_attributes_on_new_line(level.tag(), attributes.into_iter())?;
                 if let Some(id) = id {
-                    self.write_tag_with_attributes("a", [("href".into(), (&format_args!("#{id}")).into())])?;
+                    let href = format!("#{id}");
+                    match self.heading_anchors {
+                        HeadingAnchors::Wrap => {
+                            self.write_tag_with_attributes("a", [("href".into(), href.as_str().into())])?
+                        }
+                        HeadingAnchors::Prepend => {
+                            self.write_tag_with_attributes(
+                                "a",
+                                [
+                                    ("class".into(), "anchor".into()),
+                                    ("href".into(), href.as_str().into()),
+                                    ("aria-hidden".into(), "true".into()),
+                                ],
+                            )?;
+                            self.write("#</a>")?;
+                        }
+                        HeadingAnchors::None => {}
+                    }
                 }
             }
             Container::Div => {
@@ -598,7 +674,7 @@ impl<'w> Writer<'w> {
             }
 
             Container::Link { destination } => {
-                // TODO: escape
+                // The destination is escaped as a raw attribute value
                 self.write_tag_with_attributes_on_new_line(
                     "a",
                     attributes.into_iter().chain([("href".into(), destination.into())]),
@@ -636,10 +712,11 @@ impl<'w> Writer<'w> {
                                 .chain(start.map(|start| ("start".into(), start.into()))),
                         )?;
                     }
-                    ListKind::Task => self.write_tag_with_attributes_on_new_line(
-                        "ul",
-                        attributes.into_iter().chain([("class".into(), "task-list".into())]),
-                    )?,
+                    ListKind::Task => {
+                        let mut attributes = attributes;
+                        attributes.insert("class", "task-list");
+                        self.write_tag_with_attributes_on_new_line("ul", attributes.into_iter())?
+                    }
                 }
             }
             Container::ListItem => self.write_tag_with_attributes_on_new_line("li", attributes.into_iter())?,
@@ -668,10 +745,11 @@ impl<'w> Writer<'w> {
             Container::Footnote { label } => {
                 let num = self.register_footnote_definition(&label);
                 self.write_target = WriteTarget::FootnotesBuf { label: label.clone() };
+                let mut attributes = attributes;
+                attributes.insert("class", "footnote-definition");
                 self.write_tag_with_attributes_on_new_line(
                     "li",
                     attributes.into_iter().chain([
-                        ("class".into(), "footnote-definition".into()),
                         ("id".into(), (&format_args!("fn-{num}")).into()),
                         ("role".into(), "doc-footnote".into()),
                     ]),
@@ -695,8 +773,9 @@ impl<'w> Writer<'w> {
             ContainerEnd::DescriptionDetails => self.write("</dd>")?,
 
             ContainerEnd::Heading { level, has_id } => {
+                let wrapped = has_id && self.heading_anchors == HeadingAnchors::Wrap;
                 self.with_buf(|buf| {
-                    if has_id {
+                    if wrapped {
                         write!(buf, "</a>")?;
                     }
                     write!(buf, "</{level}>\n")
@@ -732,7 +811,9 @@ impl<'w> Writer<'w> {
             }
 
             ContainerEnd::Footnote => {
-                self.write_on_new_line("</li>\n")?;
+                // The definition is closed once its back-references are known, after all
+                // references are rendered
+                self.ensure_newline()?;
                 self.write_target = WriteTarget::Buf;
             }
 
@@ -747,17 +828,204 @@ impl<'w> Writer<'w> {
     }
 }
 
+/// Options for rendering markup to HTML.
+#[derive(Clone, Debug, Default)]
+pub struct HtmlOptions {
+    /// Number the lines of all code blocks, rather than only those with a `numberLines` class or
+    /// attribute.
+    pub line_numbers: bool,
+    /// Prefix of the classes of syntax highlighting spans.
+    pub highlight_class_prefix: String,
+    /// Host of the site. Links to absolute URLs with another host open in a new tab. If `None`,
+    /// all links to absolute URLs open in a new tab.
+    pub site_host: Option<String>,
+    /// Path the site is hosted under, e.g., `/blog`, or empty if it is hosted at the root. Prefixes
+    /// the URLs of image variants.
+    pub base_path: String,
+    /// Sanitize raw HTML, for sites with untrusted content.
+    pub sanitize_html: bool,
+    pub heading_anchors: HeadingAnchors,
+    pub math_renderer: MathRenderer,
+}
+
+/// Whether a link destination is an absolute URL to another host than the site's host.
+fn is_external_link(destination: &str, site_host: Option<&str>) -> bool {
+    match utils::url_host(destination) {
+        Some(host) => site_host.map_or(true, |site_host| !host.eq_ignore_ascii_case(site_host)),
+        None => false,
+    }
+}
+
+/// Parse a line range specification such as `2,4-6` into line ranges. Parts that are not a line
+/// number or a range of line numbers are ignored.
+fn parse_line_ranges(spec: &str) -> Vec<std::ops::RangeInclusive<usize>> {
+    spec.split(',')
+        .filter_map(|part| {
+            let part = part.trim();
+            match part.split_once('-') {
+                Some((start, end)) => Some(start.trim().parse().ok()?..=end.trim().parse().ok()?),
+                None => {
+                    let line = part.parse().ok()?;
+                    Some(line..=line)
+                }
+            }
+        })
+        .collect()
+}
+
+/// Wrap every line of (highlighted) code in a `<span class="line" data-line="N">`. Lines within
+/// `highlighted_lines` additionally get the `highlighted-line` class. Highlight spans crossing line
+/// boundaries are closed at the end of the line and reopened on the next line, such that every line
+/// is self-contained. Trailing lines without content are dropped.
+fn number_lines(code: &str, highlighted_lines: &[std::ops::RangeInclusive<usize>]) -> String {
+    let mut lines: Vec<String> = vec![];
+    let mut open_tags: Vec<&str> = vec![];
+
+    for line in code.split('\n') {
+        let mut wrapped = String::new();
+        let mut has_content = false;
+
+        for tag in &open_tags {
+            wrapped.push_str(tag);
+        }
+
+        let mut rest = line;
+        while let Some(idx) = rest.find('<') {
+            has_content |= idx > 0;
+            wrapped.push_str(&rest[..idx]);
+
+            let end = rest[idx..].find('>').map(|end| idx + end + 1).unwrap_or(rest.len());
+            let tag = &rest[idx..end];
+            if tag.starts_with("</") {
+                open_tags.pop();
+            } else {
+                open_tags.push(tag);
+            }
+            wrapped.push_str(tag);
+            rest = &rest[end..];
+        }
+        has_content |= !rest.is_empty();
+        wrapped.push_str(rest);
+
+        for _ in &open_tags {
+            wrapped.push_str("</span>");
+        }
+
+        lines.push(if has_content { wrapped } else { String::new() });
+    }
+
+    while lines.last().is_some_and(|line| line.is_empty()) {
+        lines.pop();
+    }
+
+    let mut numbered = String::with_capacity(code.len() + lines.len() * 40);
+    for (idx, line) in lines.iter().enumerate() {
+        let number = idx + 1;
+        let class = if highlighted_lines.iter().any(|range| range.contains(&number)) {
+            "line highlighted-line"
+        } else {
+            "line"
+        };
+        write!(numbered, r#"<span class="{class}" data-line="{number}">{line}</span>"#).expect("infallible");
+        numbered.push('\n');
+    }
+    numbered
+}
+
+/// The language of inline code, given by a `language-*` class, e.g., `language-rust`.
+fn inline_code_language(container: &Container<'_>, attributes: &Attributes<'_>) -> Option<String> {
+    if !matches!(container, Container::Other { tag } if tag == "code") {
+        return None;
+    }
+    let classes = attributes.get("class")?.to_string();
+    let language = classes
+        .split_whitespace()
+        .find_map(|class| class.strip_prefix("language-"))?;
+    Some(language.to_owned())
+}
+
+/// Write a `srcset` attribute value listing an image and its resized variants, as URLs under
+/// `base_path`.
+fn write_srcset<'b>(
+    bump: &'b Bump,
+    base_path: &str,
+    original: &Path,
+    width: u32,
+    variants: &[(u32, PathBuf)],
+) -> Result<&'b str> {
+    let mut srcset = bumpalo::collections::String::new_in(bump);
+    write!(srcset, "{} {width}w", utils::path_to_url(Some(base_path), original)?)?;
+    for (width, link) in variants {
+        write!(srcset, ",{} {width}w", utils::path_to_url(Some(base_path), link)?)?;
+    }
+    Ok(srcset.into_bump_str())
+}
+
 pub fn push_html<'s>(
     buf: &mut String,
     mut iter: impl Iterator<Item = Event<'s>>,
     images: &HashMap<String, types::Images>,
+    options: &HtmlOptions,
 ) -> Result<()> {
     let mut bump = Bump::new();
-    let mut writer = Writer::new(buf);
+    let mut writer = Writer::new(buf, options.heading_anchors);
+    let sanitize = |content: Cow<'s, str>| {
+        if options.sanitize_html {
+            Cow::Owned(sanitize::sanitize_html(&content))
+        } else {
+            content
+        }
+    };
 
     while let Some(ev) = iter.next() {
         match ev {
-            Event::Start { container, attributes } => {
+            Event::Start {
+                container,
+                mut attributes,
+            } => {
+                // External links open in a new tab, unless opted out of with `external=false`
+                if let Container::Link { destination } = &container {
+                    let external = match attributes.remove("external") {
+                        Some(value) => value.to_string() != "false",
+                        None => is_external_link(destination, options.site_host.as_deref()),
+                    };
+                    if external {
+                        if attributes.get("target").is_none() {
+                            attributes.insert("target", "_blank");
+                        }
+                        if attributes.get("rel").is_none() {
+                            attributes.insert("rel", "noopener noreferrer");
+                        }
+                    }
+                }
+
+                // Inline code with a `language-*` class is highlighted like code blocks
+                if let Some(language) = inline_code_language(&container, &attributes) {
+                    let mut code = String::new();
+                    for event in iter.by_ref() {
+                        match event {
+                            Event::Str(str) => code.push_str(&str),
+                            Event::End { .. } => break,
+                            _ => {}
+                        }
+                    }
+
+                    match highlight::highlight(&code, &language, &options.highlight_class_prefix)? {
+                        highlight::Highlighted::Plain(plaintext) => {
+                            writer.write_tag_with_attributes("code", attributes.into_iter())?;
+                            writer.write(&plaintext)?;
+                        }
+                        highlight::Highlighted::Highlighted { language, highlighted } => {
+                            attributes.insert("class", "highlight");
+                            attributes.insert("data-lang", language.to_owned());
+                            writer.write_tag_with_attributes("code", attributes.into_iter())?;
+                            writer.write(&highlighted)?;
+                        }
+                    }
+                    writer.write("</code>")?;
+                    continue;
+                }
+
                 writer.start_tag(&bump, container, attributes)?;
             }
             Event::End { container } => writer.end_tag(&bump, container)?,
@@ -778,75 +1046,121 @@ pub fn push_html<'s>(
                 };
 
                 let mut srcset: Option<&'_ str> = None;
+                let mut size: Option<(&'_ str, &'_ str)> = None;
                 let mut style: Option<&'_ str> = None;
 
                 if let Some(width) = images.original_width {
-                    let mut srcset_ = bumpalo::collections::String::new_in(&bump);
-                    write!(srcset_, "/{} {width}w", images.original.to_str().unwrap())?;
-                    if let Some(ref link) = images.x_1536 {
-                        write!(srcset_, ",/{} 1536w", link.to_str().unwrap())?;
-                    }
-                    if let Some(ref link) = images.x_768 {
-                        write!(srcset_, ",/{} 768w", link.to_str().unwrap())?;
-                    }
-                    srcset = Some(srcset_.into_bump_str());
+                    srcset = Some(write_srcset(
+                        &bump,
+                        &options.base_path,
+                        &images.original,
+                        width,
+                        &images.variants,
+                    )?);
                     style = Some(bumpalo::format!(in &bump, "max-width: calc(min(100%, {}px))", width).into_bump_str());
                 }
 
+                // The intrinsic size lets browsers reserve space before the image has loaded
+                if let (Some(width), Some(height)) = (images.original_width, images.original_height) {
+                    size = Some((
+                        bumpalo::format!(in &bump, "{width}").into_bump_str(),
+                        bumpalo::format!(in &bump, "{height}").into_bump_str(),
+                    ));
+                }
+
+                // Offer alternative image formats through a `<picture>`, the `<img>` is the fallback
+                let picture = images.original_width.filter(|_| !images.sources.is_empty());
+                if let Some(width) = picture {
+                    writer.write_on_new_line("<picture>")?;
+                    for source in &images.sources {
+                        let srcset =
+                            write_srcset(&bump, &options.base_path, &source.original, width, &source.variants)?;
+                        writer.write_tag_with_attributes(
+                            "source",
+                            [
+                                ("type".into(), source.mime_type.into()),
+                                ("srcset".into(), srcset.into()),
+                            ],
+                        )?;
+                    }
+                }
+
                 writer.write_tag_with_attributes_on_new_line(
                     "img",
                     attributes
                         .into_iter()
                         .chain([("src".into(), destination.into())])
                         .chain(srcset.map(|srcset| ("srcset".into(), srcset.into())))
+                        .chain(size.map(|(width, _)| ("width".into(), width.into())))
+                        .chain(size.map(|(_, height)| ("height".into(), height.into())))
                         .chain(style.map(|style| ("style".into(), style.into())))
-                        .chain((alt == "").then(|| ("alt".into(), alt.into()))),
-                )?
+                        .chain([("alt".into(), alt.into())]),
+                )?;
+
+                if picture.is_some() {
+                    writer.write("</picture>")?;
+                }
             }
             Event::CodeBlock {
                 language,
                 code,
-                attributes,
-            } => match highlight::highlight(&code, &language)? {
-                highlight::Highlighted::Plain(plaintext) => {
-                    writer.write_tag_with_attributes_on_new_line("pre", attributes.into_iter())?;
-                    writer.write_on_new_line("<code>")?;
-                    writer.write_on_new_line(&plaintext)?;
-                    writer.write_on_new_line("</code>\n</pre>")?;
-                }
-                highlight::Highlighted::Highlighted { language, highlighted } => {
-                    writer.write_tag_with_attributes_on_new_line(
-                        "pre",
-                        attributes.into_iter().chain([("class".into(), "highlight".into())]),
-                    )?;
-                    writer.write_tag_with_attributes_on_new_line("code", [("data-lang".into(), language.into())])?;
-                    writer.write_on_new_line(&highlighted)?;
-                    writer.write_on_new_line("</code>\n</pre>")?;
-                }
-            },
+                mut attributes,
+            } => {
+                let line_numbers = match attributes.remove("numberLines") {
+                    Some(value) => value.to_string() != "false",
+                    None => options.line_numbers || attributes.has_class("numberLines"),
+                };
+                let highlighted_lines = attributes
+                    .remove("highlight")
+                    .map(|spec| parse_line_ranges(&spec.to_string()))
+                    .unwrap_or_default();
+                let maybe_number_lines = |code: String| {
+                    if line_numbers || !highlighted_lines.is_empty() {
+                        number_lines(&code, &highlighted_lines)
+                    } else {
+                        code
+                    }
+                };
 
-            #[allow(unused_variables)]
-            Event::Math { kind, math, attributes } => {
-                writer.write_tag_with_attributes_on_new_line(
-                    "span",
-                    attributes.into_iter().chain([("class".into(), "math".into())]),
-                )?;
-                #[cfg(any(feature = "katex", feature = "latex2mathml"))]
-                {
-                    writer.write(&render_latex(&math, &kind)?)?;
+                match highlight::highlight(&code, &language, &options.highlight_class_prefix)? {
+                    highlight::Highlighted::Plain(plaintext) => {
+                        writer.write_tag_with_attributes_on_new_line("pre", attributes.into_iter())?;
+                        writer.write_on_new_line("<code>")?;
+                        writer.write_on_new_line(&maybe_number_lines(plaintext))?;
+                        writer.write_on_new_line("</code>\n</pre>")?;
+                    }
+                    highlight::Highlighted::Highlighted { language, highlighted } => {
+                        attributes.insert("class", "highlight");
+                        writer.write_tag_with_attributes_on_new_line("pre", attributes.into_iter())?;
+                        writer
+                            .write_tag_with_attributes_on_new_line("code", [("data-lang".into(), language.into())])?;
+                        writer.write_on_new_line(&maybe_number_lines(highlighted))?;
+                        writer.write_on_new_line("</code>\n</pre>")?;
+                    }
                 }
-                #[cfg(not(any(feature = "katex", feature = "latex2mathml")))]
-                {
-                    writer.with_buf(|buf| pulldown_cmark_escape::escape_html_body_text(buf, &math))?;
+            }
+
+            Event::Math {
+                kind,
+                math,
+                mut attributes,
+            } => {
+                attributes.insert("class", "math");
+                writer.write_tag_with_attributes_on_new_line("span", attributes.into_iter())?;
+                match render_latex(&math, &kind, options.math_renderer)? {
+                    Some(rendered) => writer.write(&rendered)?,
+                    None => writer.with_buf(|buf| pulldown_cmark_escape::escape_html_body_text(buf, &math))?,
                 }
                 writer.write("</span>")?;
             }
             Event::HtmlBlock { content, attributes } => {
+                let content = sanitize(content);
                 writer.write_tag_with_attributes_on_new_line("div", attributes.into_iter())?;
                 writer.write(&content)?;
                 writer.write("</div>\n")?
             }
             Event::HtmlInline { content, attributes } => {
+                let content = sanitize(content);
                 if attributes.is_empty() {
                     writer.write(&content)?;
                 } else {
@@ -870,8 +1184,11 @@ pub fn push_html<'s>(
             }
 
             Event::FootnoteReference { reference } => {
-                let num = writer.register_footnote_reference(&reference);
-                writer.write("<sup class=\"footnote-reference\">")?;
+                let (num, id) = writer.register_footnote_reference(&reference);
+                writer.write_tag_with_attributes(
+                    "sup",
+                    [("class".into(), "footnote-reference".into()), ("id".into(), id.into())],
+                )?;
                 writer.write_tag_with_attributes(
                     "a",
                     [
@@ -907,7 +1224,8 @@ pub fn push_html<'s>(
                             .find(|(_, footnote)| footnote.number == num)
                             .expect("invariant")
                             .0;
-                        log::warn!("footnote definition missing: {key}");
+                        // Missing definitions are reported per entry, see `check_footnotes`
+                        log::debug!("footnote definition missing: {key}");
                     }
 
                     writer.write_tag_with_attributes_on_new_line(
@@ -918,8 +1236,24 @@ pub fn push_html<'s>(
                             ("role".into(), "doc-footnote".into()),
                         ],
                     )?;
+                    writer.ensure_newline()?;
                 }
             }
+
+            let num: usize = footnote.number.into();
+            for reference in 1..=footnote.references {
+                let href = format!("#{}", footnote_reference_id(num, reference));
+                writer.write_tag_with_attributes(
+                    "a",
+                    [
+                        ("class".into(), "footnote-backref".into()),
+                        ("href".into(), href.into()),
+                        ("role".into(), "doc-backlink".into()),
+                    ],
+                )?;
+                writer.write("↩</a>\n")?;
+            }
+            writer.write("</li>\n")?;
         }
         buf.push_str("</ol>\n</aside>\n");
     }
@@ -927,8 +1261,63 @@ pub fn push_html<'s>(
     Ok(())
 }
 
+/// The ID of the `reference`th reference to footnote `number`, both starting at 1. Only repeated
+/// references get a suffix, e.g., `fnref-1` and `fnref-1-2`.
+fn footnote_reference_id(number: usize, reference: usize) -> String {
+    if reference == 1 {
+        format!("fnref-{number}")
+    } else {
+        format!("fnref-{number}-{reference}")
+    }
+}
+
+/// Math rendered during the current build, keyed by renderer, kind and LaTeX. Rendering math is
+/// slow, and the same formulas are often used many times.
+static RENDERED_MATH: Mutex<BTreeMap<(MathRenderer, MathKind, String), String>> = Mutex::new(BTreeMap::new());
+
+/// Forget the math rendered during the current build, such that the cache does not grow across
+/// builds.
+pub fn reset_math_cache() {
+    RENDERED_MATH.lock().unwrap().clear();
+}
+
+/// Render LaTeX math with `renderer`. Returns `None` if the renderer does not render math, or
+/// sprokkel is built without it, in which case the LaTeX should be written as text.
+#[allow(unused_mut, unused_variables)]
+fn render_latex(latex: &str, kind: &MathKind, renderer: MathRenderer) -> anyhow::Result<Option<String>> {
+    if renderer == MathRenderer::None {
+        return Ok(None);
+    }
+
+    let key = (renderer, *kind, latex.to_owned());
+    if let Some(rendered) = RENDERED_MATH.lock().unwrap().get(&key) {
+        return Ok(Some(rendered.clone()));
+    }
+
+    // The cache is not locked while rendering, such that other formulas can be rendered in the
+    // meantime
+    let mut rendered: Option<String> = None;
+    #[cfg(feature = "katex")]
+    {
+        if renderer == MathRenderer::Katex {
+            rendered = Some(render_katex(latex, kind)?);
+        }
+    }
+    #[cfg(feature = "latex2mathml")]
+    {
+        if renderer == MathRenderer::Mathml {
+            rendered = Some(render_latex2mathml(latex, kind)?);
+        }
+    }
+
+    if let Some(rendered) = &rendered {
+        RENDERED_MATH.lock().unwrap().insert(key, rendered.clone());
+    }
+    Ok(rendered)
+}
+
 #[cfg(feature = "katex")]
-fn render_latex(latex: &str, kind: &MathKind) -> anyhow::Result<String> {
+fn render_katex(latex: &str, kind: &MathKind) -> anyhow::Result<String> {
     use std::sync::OnceLock;
 
     static DISPLAY: OnceLock<katex::Opts> = OnceLock::new();
@@ -956,7 +1345,7 @@ fn render_latex(latex: &str, kind: &MathKind) -> anyhow::Result<String> {
 }
 
 #[cfg(feature = "latex2mathml")]
-fn render_latex(latex: &str, kind: &MathKind) -> anyhow::Result<String> {
+fn render_latex2mathml(latex: &str, kind: &MathKind) -> anyhow::Result<String> {
     Ok(latex2mathml::latex_to_mathml(
         latex,
         match kind {
@@ -1001,7 +1390,7 @@ fn iter_container_from_inside<'a>(iter: impl Iterator<Item = Event<'a>>) -> impl
     )
 }
 
-/// Parse title from a Djot article. The title events are removed from the iter.
+/// Parse title from an entry's markup. The title events are removed from the iter.
 pub fn parse_and_render_title(events: &mut Vec<Event<'_>>) -> anyhow::Result<Option<String>> {
     let mut title = None;
 
@@ -1040,7 +1429,7 @@ pub fn parse_and_render_title(events: &mut Vec<Event<'_>>) -> anyhow::Result<Opt
         ) {
             let title_events = iter_container_from_inside(&mut iter);
             let mut title_ = String::new();
-            push_html(&mut title_, title_events, &HashMap::new())?;
+            push_html(&mut title_, title_events, &HashMap::new(), &HtmlOptions::default())?;
             title_.truncate(title_.trim_end().len());
             title = Some(title_);
         }
@@ -1051,27 +1440,576 @@ pub fn parse_and_render_title(events: &mut Vec<Event<'_>>) -> anyhow::Result<Opt
     Ok(title)
 }
 
+/// The destination of the first image in the events, e.g., to use as an entry's cover image.
+pub fn first_image<'e>(events: &'e [Event<'_>]) -> Option<&'e str> {
+    events.iter().find_map(|event| match event {
+        Event::Image { destination, .. } => Some(destination.as_ref()),
+        _ => None,
+    })
+}
+
+/// The destinations of the images in the events, without duplicates.
+pub fn image_links(events: &[Event<'_>]) -> Vec<String> {
+    let mut links: Vec<String> = vec![];
+    for event in events {
+        if let Event::Image { destination, .. } = event {
+            if !links.iter().any(|link| *link == **destination) {
+                links.push(destination.to_string());
+            }
+        }
+    }
+    links
+}
+
+/// Whether the events contain math, e.g., to only load math stylesheets on pages that need them.
+pub fn has_math(events: &[Event<'_>]) -> bool {
+    events.iter().any(|event| matches!(event, Event::Math { .. }))
+}
+
+/// Whether the events contain code blocks, e.g., to only load highlighting stylesheets on pages
+/// that need them.
+pub fn has_code(events: &[Event<'_>]) -> bool {
+    events.iter().any(|event| matches!(event, Event::CodeBlock { .. }))
+}
+
+/// Count the words of an entry's text. Code blocks, math and raw HTML are not counted, as they are
+/// not read like prose. Words may span multiple text events, e.g., when only part of a word is
+/// emphasized.
+pub fn word_count(events: &[Event<'_>]) -> usize {
+    let mut count = 0;
+    let mut in_word = false;
+
+    for event in events {
+        match event {
+            Event::Str(text) => {
+                for char in text.chars() {
+                    if char.is_whitespace() {
+                        in_word = false;
+                    } else if !in_word {
+                        in_word = true;
+                        count += 1;
+                    }
+                }
+            }
+            // inline containers do not break words
+            Event::Start {
+                container: Container::Link { .. } | Container::Other { .. },
+                ..
+            }
+            | Event::End {
+                container: ContainerEnd::Link | ContainerEnd::Other { .. },
+            } => {}
+            _ => in_word = false,
+        }
+    }
+
+    count
+}
+
+/// Make the IDs of an entry's sections and headings unique, by suffixing duplicated IDs with a
+/// number. A heading directly inside a section gets the section's new ID. This runs on the whole
+/// entry, as the summary and the rest are rendered separately.
+pub fn deduplicate_heading_ids(events: &mut [Event<'_>]) {
+    fn unique_id(seen: &mut HashSet<String>, id: &str) -> String {
+        let mut unique = id.to_owned();
+        let mut suffix = 1;
+        while !seen.insert(unique.clone()) {
+            unique = format!("{id}-{suffix}");
+            suffix += 1;
+        }
+        if unique != id {
+            log::debug!("Heading ID {id} is duplicated, renamed to {unique}");
+        }
+        unique
+    }
+
+    let mut seen = HashSet::new();
+    let mut idx = 0;
+    while idx < events.len() {
+        match &mut events[idx] {
+            Event::Start {
+                container: Container::Section { id: Some(id) },
+                ..
+            } => {
+                let original = id.to_string();
+                let unique = unique_id(&mut seen, &original);
+                *id = Cow::Owned(unique.clone());
+
+                if let Some(Event::Start {
+                    container: Container::Heading { id: Some(id), .. },
+                    ..
+                }) = events.get_mut(idx + 1)
+                {
+                    if *id == original {
+                        *id = Cow::Owned(unique);
+                        idx += 1;
+                    }
+                }
+            }
+            Event::Start {
+                container: Container::Heading { id: Some(id), .. },
+                ..
+            } => {
+                let unique = unique_id(&mut seen, id);
+                *id = Cow::Owned(unique);
+            }
+            _ => {}
+        }
+        idx += 1;
+    }
+}
+
+/// Collect the table of contents of an entry from its headings. Headings without an ID cannot be
+/// linked to and are skipped. A heading is nested under the closest preceding heading of a higher
+/// level, even if levels in between are skipped.
+pub fn extract_toc(events: &[Event<'_>]) -> anyhow::Result<Vec<types::TocEntry>> {
+    let mut flat = vec![];
+
+    for (idx, event) in events.iter().enumerate() {
+        if let Event::Start {
+            container: Container::Heading { level, id: Some(id) },
+            attributes: _,
+        } = event
+        {
+            let title_events = iter_container_from_inside(events[idx + 1..].iter().cloned());
+            let mut title = String::new();
+            push_html(&mut title, title_events, &HashMap::new(), &HtmlOptions::default())?;
+            title.truncate(title.trim_end().len());
+
+            flat.push(types::TocEntry {
+                level: level.number(),
+                id: id.to_string(),
+                title,
+                children: vec![],
+            });
+        }
+    }
+
+    fn nest(flat: &mut std::iter::Peekable<impl Iterator<Item = types::TocEntry>>, level: u8) -> Vec<types::TocEntry> {
+        let mut entries = vec![];
+        while let Some(mut entry) = flat.next_if(|entry| entry.level > level) {
+            entry.children = nest(flat, entry.level);
+            entries.push(entry);
+        }
+        entries
+    }
+
+    Ok(nest(&mut flat.into_iter().peekable(), 0))
+}
+
+/// Render a table of contents as nested lists.
+fn render_toc(buf: &mut String, toc: &[types::TocEntry]) {
+    buf.push_str("<ul>\n");
+    for entry in toc {
+        buf.push_str("<li><a href=\"#");
+        pulldown_cmark_escape::escape_html(&mut *buf, &entry.id).expect("infallible");
+        buf.push_str("\">");
+        buf.push_str(&entry.title);
+        buf.push_str("</a>");
+        if !entry.children.is_empty() {
+            buf.push('\n');
+            render_toc(buf, &entry.children);
+        }
+        buf.push_str("</li>\n");
+    }
+    buf.push_str("</ul>\n");
+}
+
+/// Replace the contents of divs with the class `toc` by the rendered table of contents.
+pub fn replace_toc_placeholders(events: &mut Vec<Event<'_>>, toc: &[types::TocEntry]) {
+    let mut idx = 0;
+    while idx < events.len() {
+        let is_placeholder = matches!(
+            &events[idx],
+            Event::Start {
+                container: Container::Div,
+                attributes,
+            } if attributes.has_class("toc")
+        );
+        if !is_placeholder {
+            idx += 1;
+            continue;
+        }
+
+        let len = 1 + iter_container(events[idx..].iter().cloned()).count() + 1;
+        let attributes = match events.drain(idx..idx + len).next() {
+            Some(Event::Start { attributes, .. }) => attributes,
+            _ => unreachable!(),
+        };
+
+        let mut content = String::new();
+        if !toc.is_empty() {
+            render_toc(&mut content, toc);
+        }
+        events.insert(
+            idx,
+            Event::HtmlBlock {
+                content: content.into(),
+                attributes,
+            },
+        );
+        idx += 1;
+    }
+}
+
+/// Whether an event marks the end of an entry's summary: an `<!-- more -->` HTML comment or a
+/// thematic break with the class `more`.
+fn is_summary_marker(event: &Event<'_>) -> bool {
+    match event {
+        Event::HtmlBlock { content, .. } | Event::HtmlInline { content, .. } => content.trim() == "<!-- more -->",
+        Event::TagWithAttribute { tag, attributes } => tag == "hr" && attributes.has_class("more"),
+        _ => false,
+    }
+}
+
+/// Split an entry at its first summary marker. The events before the marker remain as the summary,
+/// the events after it are returned. Sections and divs the marker is in are closed in the summary
+/// and reopened, without their ID, in the rest. The marker itself is removed. If there is no
+/// marker, all events remain as the summary.
+pub fn split_summary<'s>(events: &mut Vec<Event<'s>>) -> anyhow::Result<Vec<Event<'s>>> {
+    let mut open = vec![];
+    let mut marker = None;
+    for (idx, event) in events.iter().enumerate() {
+        match event {
+            Event::Start { container, attributes } => open.push((container, attributes)),
+            Event::End { .. } => {
+                open.pop();
+            }
+            event if is_summary_marker(event) => {
+                marker = Some(idx);
+                break;
+            }
+            _ => {}
+        }
+    }
+    let Some(marker) = marker else {
+        return Ok(vec![]);
+    };
+
+    let mut close = vec![];
+    let mut reopen = vec![];
+    for (container, attributes) in open {
+        let mut attributes = attributes.clone();
+        attributes.remove("id");
+        let (container, end) = match container {
+            Container::Section { .. } => (Container::Section { id: None }, ContainerEnd::Section),
+            Container::Div => (Container::Div, ContainerEnd::Div),
+            _ => anyhow::bail!("a summary marker can only be in sections and divs"),
+        };
+        reopen.push(Event::Start { container, attributes });
+        close.push(Event::End { container: end });
+    }
+    close.reverse();
+
+    let rest = events.drain(marker..).skip(1);
+    let rest = reopen.into_iter().chain(rest).collect();
+    events.extend(close);
+    Ok(rest)
+}
+
+/// Wrap images that are alone in a paragraph and have a title in a `<figure>`, using the title as
+/// the image's `<figcaption>`.
+pub fn wrap_captioned_images(events: &mut Vec<Event<'_>>) {
+    let mut idx = 0;
+    while idx + 3 <= events.len() {
+        let is_figure = matches!(
+            &events[idx..idx + 3],
+            [
+                Event::Start {
+                    container: Container::Paragraph,
+                    ..
+                },
+                Event::Image { attributes, .. },
+                Event::End {
+                    container: ContainerEnd::Paragraph,
+                },
+            ] if attributes.get("title").is_some()
+        );
+        if !is_figure {
+            idx += 1;
+            continue;
+        }
+
+        let mut paragraph = events.drain(idx..idx + 3);
+        let (
+            Some(Event::Start { attributes, .. }),
+            Some(Event::Image {
+                destination,
+                alt,
+                attributes: mut image_attributes,
+            }),
+        ) = (paragraph.next(), paragraph.next())
+        else {
+            unreachable!()
+        };
+        drop(paragraph);
+
+        let caption = image_attributes.remove("title").expect("image has a title");
+        let figure = [
+            Event::Start {
+                container: Container::Other { tag: "figure".into() },
+                attributes,
+            },
+            Event::Image {
+                destination,
+                alt,
+                attributes: image_attributes,
+            },
+            Event::Start {
+                container: Container::Other {
+                    tag: "figcaption".into(),
+                },
+                attributes: Attributes::new(),
+            },
+            Event::Str(caption.to_string().into()),
+            Event::End {
+                container: ContainerEnd::Other {
+                    tag: "figcaption".into(),
+                },
+            },
+            Event::End {
+                container: ContainerEnd::Other { tag: "figure".into() },
+            },
+        ];
+        let len = figure.len();
+        events.splice(idx..idx, figure);
+        idx += len;
+    }
+}
+
+/// Render divs with one of the `callouts` as class as a titled `<aside>`, e.g.,
+/// `<aside class="callout callout-warning">` for a div with class `warning`. The title is the
+/// capitalized class name, unless the div has a `title` attribute.
+pub fn wrap_callouts(events: &mut Vec<Event<'_>>, callouts: &[String]) {
+    // For every open div, whether it is a callout
+    let mut divs = Vec::new();
+
+    let mut idx = 0;
+    while idx < events.len() {
+        match &mut events[idx] {
+            Event::Start {
+                container: Container::Div,
+                attributes,
+            } => {
+                let callout = attributes.get("class").and_then(|classes| {
+                    let classes = classes.to_string();
+                    classes
+                        .split_whitespace()
+                        .find(|class| callouts.iter().any(|callout| callout == class))
+                        .map(str::to_owned)
+                });
+                divs.push(callout.is_some());
+
+                if let Some(callout) = callout {
+                    let mut attributes = std::mem::replace(attributes, Attributes::new());
+                    let title = match attributes.remove("title") {
+                        Some(title) => title.to_string(),
+                        None => {
+                            let mut chars = callout.chars();
+                            chars
+                                .next()
+                                .map(|first| first.to_uppercase().chain(chars).collect())
+                                .unwrap_or_default()
+                        }
+                    };
+                    attributes.insert("class", format!("callout callout-{callout}"));
+                    attributes.insert("role", "note");
+
+                    let mut title_attributes = Attributes::new();
+                    title_attributes.insert("class", "callout-title");
+                    let callout = [
+                        Event::Start {
+                            container: Container::Other { tag: "aside".into() },
+                            attributes,
+                        },
+                        Event::Start {
+                            container: Container::Paragraph,
+                            attributes: title_attributes,
+                        },
+                        Event::Str(title.into()),
+                        Event::End {
+                            container: ContainerEnd::Paragraph,
+                        },
+                    ];
+                    events.splice(idx..=idx, callout);
+                    idx += 3;
+                }
+            }
+            Event::End {
+                container: container @ ContainerEnd::Div,
+            } => {
+                if divs.pop().unwrap_or(false) {
+                    *container = ContainerEnd::Other { tag: "aside".into() };
+                }
+            }
+            _ => {}
+        }
+        idx += 1;
+    }
+}
+
+/// Footnotes that are referenced but not defined, or defined but not referenced, by label.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct FootnoteProblems {
+    pub missing: Vec<String>,
+    pub unreferenced: Vec<String>,
+}
+
+/// Find footnotes that are referenced but not defined, or defined but not referenced.
+pub fn check_footnotes(events: &[Event<'_>]) -> FootnoteProblems {
+    let mut references = BTreeSet::new();
+    let mut definitions = BTreeSet::new();
+
+    for event in events {
+        match event {
+            Event::FootnoteReference { reference } => {
+                references.insert(reference.as_ref());
+            }
+            Event::Start {
+                container: Container::Footnote { label },
+                ..
+            } => {
+                definitions.insert(label.as_ref());
+            }
+            _ => {}
+        }
+    }
+
+    FootnoteProblems {
+        missing: references
+            .difference(&definitions)
+            .map(|label| label.to_string())
+            .collect(),
+        unreferenced: definitions
+            .difference(&references)
+            .map(|label| label.to_string())
+            .collect(),
+    }
+}
+
+/// Number the figures made by [`wrap_captioned_images`], in order, and resolve references to
+/// them. A figure is labelled by its image's ID. Links to `~fig:<label>` are rewritten to link to
+/// the figure, and get "Figure N" as their text if they have none.
+pub fn number_figures(events: &mut Vec<Event<'_>>) -> anyhow::Result<()> {
+    let mut labels: HashMap<String, usize> = HashMap::new();
+    let mut number = 0;
+
+    let mut idx = 0;
+    while idx < events.len() {
+        let is_figure = matches!(
+            &events[idx],
+            Event::Start {
+                container: Container::Other { tag },
+                ..
+            } if tag == "figure"
+        );
+        if !is_figure {
+            idx += 1;
+            continue;
+        }
+        number += 1;
+
+        // Move the image's ID to the figure, such that references link to the figure
+        let id = match events.get_mut(idx + 1) {
+            Some(Event::Image { attributes, .. }) => attributes.remove("id"),
+            _ => None,
+        };
+        if let Some(id) = id {
+            let label = id.to_string();
+            if labels.insert(label.clone(), number).is_some() {
+                anyhow::bail!("Figure label is duplicated: {label}");
+            }
+            if let Event::Start { attributes, .. } = &mut events[idx] {
+                attributes.insert("id", label);
+            }
+        }
+
+        let caption = events[idx..].iter().position(|event| {
+            matches!(
+                event,
+                Event::Start {
+                    container: Container::Other { tag },
+                    ..
+                } if tag == "figcaption"
+            )
+        });
+        if let Some(caption) = caption {
+            events.insert(
+                idx + caption + 1,
+                Event::HtmlInline {
+                    content: format!("<span class=\"figure-number\">Figure {number}:</span> ").into(),
+                    attributes: Attributes::new(),
+                },
+            );
+        }
+        idx += 1;
+    }
+
+    let mut idx = 0;
+    while idx < events.len() {
+        if let Event::Start {
+            container: Container::Link { destination },
+            ..
+        } = &mut events[idx]
+        {
+            if let Some(label) = destination.strip_prefix("~fig:") {
+                let number = *labels
+                    .get(label)
+                    .ok_or_else(|| anyhow::anyhow!("Unknown figure reference: {destination}"))?;
+                *destination = format!("#{label}").into();
+
+                if matches!(
+                    events.get(idx + 1),
+                    Some(Event::End {
+                        container: ContainerEnd::Link
+                    })
+                ) {
+                    events.insert(idx + 1, Event::Str(format!("Figure {number}").into()));
+                }
+            }
+        }
+        idx += 1;
+    }
+
+    Ok(())
+}
+
+/// Resolve an internal asset link without its `~/` prefix, e.g., `posts/foo/pic.png`, to the index
+/// of the entry the asset belongs to and the asset's path relative to that entry's asset
+/// directory. The longest canonical name the link starts with is taken.
+pub fn resolve_internal_asset<'l>(link: &'l str, entries_by_name: &HashMap<&str, usize>) -> Option<(usize, &'l str)> {
+    link.rmatch_indices('/').find_map(|(slash, _)| {
+        entries_by_name
+            .get(&link[..slash])
+            .map(|&idx| (idx, &link[slash + 1..]))
+    })
+}
+
 /// Rewrites internal links in the format `~/<canonical name>` (e.g. `posts/2024-04-23-something`)
-/// to the HTTP URL. Returns the entries this entry links to.
-pub fn rewrite_and_emit_internal_links<'entries>(
+/// to the HTTP URL. `entries_by_name` maps canonical names to indices into `entries`. Returns the
+/// indices of the entries this entry links to. Images in the format `~/<canonical name>/<path>`
+/// are only checked here, they are resolved when images are extracted.
+pub fn rewrite_and_emit_internal_links(
     events: &mut Vec<Event<'_>>,
-    entries_by_name: &HashMap<&str, &'entries types::EntryMetaAndFrontMatter<'entries>>,
-) -> anyhow::Result<Vec<&'entries types::EntryMetaAndFrontMatter<'entries>>> {
+    entries: &[types::EntryMeta],
+    entries_by_name: &HashMap<&str, usize>,
+) -> anyhow::Result<Vec<usize>> {
     let mut internal_links = vec![];
 
-    fn rewrite_link<'entries>(
+    fn rewrite_link(
         old_link: &mut Cow<'_, str>,
-        entries_by_name: &HashMap<&str, &'entries types::EntryMetaAndFrontMatter<'entries>>,
-    ) -> anyhow::Result<Option<&'entries types::EntryMetaAndFrontMatter<'entries>>> {
-        if &old_link[0..2] == "~/" {
-            let (link, anchor) = match old_link.find('#') {
-                Some(anchor_idx) => (&old_link[2..anchor_idx], &old_link[anchor_idx..]),
-                None => (&old_link[2..], ""),
+        entries: &[types::EntryMeta],
+        entries_by_name: &HashMap<&str, usize>,
+    ) -> anyhow::Result<Option<usize>> {
+        if let Some(link) = old_link.strip_prefix("~/") {
+            let (link, anchor) = match link.find('#') {
+                Some(anchor_idx) => (&link[..anchor_idx], &link[anchor_idx..]),
+                None => (link, ""),
             };
 
-            if let Some(entry) = entries_by_name.get(link) {
-                *old_link = Cow::Owned(format!("{}{}", &entry.meta.permalink, anchor));
-                return Ok(Some(entry));
+            if let Some(&idx) = entries_by_name.get(link) {
+                *old_link = Cow::Owned(format!("{}{}", &entries[idx].permalink, anchor));
+                return Ok(Some(idx));
             } else {
                 anyhow::bail!("Unknown internal link: {old_link}");
             }
@@ -1086,8 +2024,15 @@ pub fn rewrite_and_emit_internal_links<'entries>(
                 container: Container::Link { destination },
                 attributes: _,
             } => {
-                if let Some(entry) = rewrite_link(destination, entries_by_name)? {
-                    internal_links.push(entry);
+                if let Some(idx) = rewrite_link(destination, entries, entries_by_name)? {
+                    internal_links.push(idx);
+                }
+            }
+            Event::Image { destination, .. } => {
+                if let Some(link) = destination.strip_prefix("~/") {
+                    if resolve_internal_asset(link, entries_by_name).is_none() {
+                        anyhow::bail!("Unknown internal image link: {destination}");
+                    }
                 }
             }
             _ => {}
@@ -1103,6 +2048,400 @@ mod tests {
 
     use super::{AttributeValue, Attributes};
 
+    #[test]
+    fn number_lines() {
+        use super::number_lines;
+
+        assert_eq!(
+            number_lines("a\nb\n", &[]),
+            "<span class=\"line\" data-line=\"1\">a</span>\n<span class=\"line\" data-line=\"2\">b</span>\n"
+        );
+        assert_eq!(
+            number_lines("<span class=\"string\">\"a\nb\"</span>;\n\n", &[]),
+            "<span class=\"line\" data-line=\"1\"><span class=\"string\">\"a</span></span>\n\
+             <span class=\"line\" data-line=\"2\"><span class=\"string\">b\"</span>;</span>\n"
+        );
+        // Blank lines within the code are kept
+        assert_eq!(
+            number_lines("a\n\nb", &[]),
+            "<span class=\"line\" data-line=\"1\">a</span>\n<span class=\"line\" data-line=\"2\"></span>\n\
+             <span class=\"line\" data-line=\"3\">b</span>\n"
+        );
+    }
+
+    #[test]
+    fn highlighted_lines() {
+        use super::{number_lines, parse_line_ranges};
+
+        assert_eq!(parse_line_ranges("2,4-6"), [2..=2, 4..=6]);
+        assert_eq!(parse_line_ranges(" 1 , 3 - 4,x,5-,"), [1..=1, 3..=4]);
+
+        assert_eq!(
+            number_lines("a\nb\nc\n", &parse_line_ranges("2,3-10")),
+            "<span class=\"line\" data-line=\"1\">a</span>\n\
+             <span class=\"line highlighted-line\" data-line=\"2\">b</span>\n\
+             <span class=\"line highlighted-line\" data-line=\"3\">c</span>\n"
+        );
+    }
+
+    #[test]
+    fn split_summary() {
+        use std::collections::HashMap;
+
+        use super::{push_html, split_summary};
+
+        let render = |events: Vec<_>| {
+            let mut html = String::new();
+            push_html(&mut html, events.into_iter(), &HashMap::new(), &Default::default()).unwrap();
+            html
+        };
+
+        let mut events: Vec<_> = crate::djot::parse("# A\n\nTeaser\n\n{.more}\n***\n\nMore\n\n# B\n").collect();
+        let rest = split_summary(&mut events).unwrap();
+        assert_eq!(
+            render(events),
+            "<section id=\"A\">\n<h1><a href=\"#A\">A</a></h1>\n<p>Teaser</p>\n</section>\n"
+        );
+        assert_eq!(
+            render(rest),
+            "<section>\n<p>More</p>\n</section>\n<section id=\"B\">\n<h1><a href=\"#B\">B</a></h1>\n</section>\n"
+        );
+
+        let mut events: Vec<_> = crate::markdown::parse("Teaser\n\n<!-- more -->\n\nMore\n").collect();
+        let rest = split_summary(&mut events).unwrap();
+        assert_eq!(render(events), "<p>Teaser</p>\n");
+        assert_eq!(render(rest), "<p>More</p>\n");
+
+        let mut events: Vec<_> = crate::djot::parse("No marker\n").collect();
+        assert!(split_summary(&mut events).unwrap().is_empty());
+        assert_eq!(render(events), "<p>No marker</p>\n");
+
+        let mut events: Vec<_> = crate::djot::parse("> Quote\n>\n> {.more}\n> ***\n").collect();
+        assert!(split_summary(&mut events).is_err());
+    }
+
+    #[test]
+    fn word_count() {
+        use super::word_count;
+
+        let events: Vec<_> = crate::djot::parse(
+            "# A title\n\nSome *emph*asized words,\nand [a link](/foo).\n\n```rust\nlet ignored = 1;\n```\n\nEnd.\n",
+        )
+        .collect();
+        assert_eq!(word_count(&events), 9);
+        assert_eq!(word_count(&[]), 0);
+    }
+
+    #[test]
+    fn render_latex() {
+        use super::{render_latex, MathKind, MathRenderer, RENDERED_MATH};
+
+        assert_eq!(
+            render_latex("x^2", &MathKind::Inline, MathRenderer::None).unwrap(),
+            None
+        );
+
+        let renderer = MathRenderer::default();
+        let rendered = render_latex("x^2", &MathKind::Display, renderer).unwrap();
+        if let Some(rendered) = &rendered {
+            let key = (renderer, MathKind::Display, "x^2".to_owned());
+            assert_eq!(RENDERED_MATH.lock().unwrap().get(&key), Some(rendered));
+        }
+        assert_eq!(render_latex("x^2", &MathKind::Display, renderer).unwrap(), rendered);
+    }
+
+    #[test]
+    fn has_math_and_code() {
+        use super::{has_code, has_math};
+
+        let events: Vec<_> = crate::djot::parse("Euler: $`e^{i\\pi} = -1`, in `code`.\n").collect();
+        assert!(has_math(&events));
+        assert!(!has_code(&events));
+
+        let events: Vec<_> = crate::djot::parse("```rust\nfn main() {}\n```\n").collect();
+        assert!(!has_math(&events));
+        assert!(has_code(&events));
+    }
+
+    #[test]
+    fn first_image() {
+        use super::first_image;
+
+        let events: Vec<_> = crate::djot::parse("Some text.\n\n![A cat](cat.png)\n\n![A dog](dog.png)\n").collect();
+        assert_eq!(first_image(&events), Some("cat.png"));
+
+        let events: Vec<_> = crate::djot::parse("No images.\n").collect();
+        assert_eq!(first_image(&events), None);
+    }
+
+    #[test]
+    fn image_links() {
+        use super::image_links;
+
+        let events: Vec<_> = crate::djot::parse("![A cat](cat.png) ![A dog](dog.png)\n\n![A cat](cat.png)\n").collect();
+        assert_eq!(image_links(&events), ["cat.png", "dog.png"]);
+    }
+
+    #[test]
+    fn toc() {
+        use std::collections::HashMap;
+
+        use super::{extract_toc, push_html, replace_toc_placeholders};
+        use crate::types::TocEntry;
+
+        let entry = |level, id: &str, children| TocEntry {
+            level,
+            id: id.to_owned(),
+            title: id.to_owned(),
+            children,
+        };
+
+        let mut events: Vec<_> =
+            crate::djot::parse("{.toc}\n:::\nplaceholder\n:::\n\n## A\n\n#### B\n\n### C\n\n## D\n").collect();
+        let toc = extract_toc(&events).unwrap();
+        assert_eq!(
+            toc,
+            [
+                entry(2, "A", vec![entry(4, "B", vec![]), entry(3, "C", vec![])]),
+                entry(2, "D", vec![]),
+            ]
+        );
+
+        replace_toc_placeholders(&mut events, &toc);
+        let mut html = String::new();
+        push_html(&mut html, events.into_iter(), &HashMap::new(), &Default::default()).unwrap();
+        assert!(!html.contains("placeholder"));
+        assert!(html.starts_with(
+            "<div class=\"toc\"><ul>\n\
+             <li><a href=\"#A\">A</a>\n\
+             <ul>\n<li><a href=\"#B\">B</a></li>\n<li><a href=\"#C\">C</a></li>\n</ul>\n</li>\n\
+             <li><a href=\"#D\">D</a></li>\n</ul>\n</div>\n"
+        ));
+    }
+
+    #[test]
+    fn captioned_images() {
+        use std::{collections::HashMap, path::PathBuf};
+
+        use super::{push_html, wrap_captioned_images};
+        use crate::types::Images;
+
+        let images = HashMap::from(["image.png".to_owned(), "other.png".to_owned()].map(|link| {
+            let images = Images {
+                original: PathBuf::from(&link),
+                original_width: None,
+                original_height: None,
+                variants: vec![],
+                sources: vec![],
+            };
+            (link, images)
+        }));
+
+        let mut events: Vec<_> =
+            crate::markdown::parse("![alt](image.png \"A caption\")\n\nText ![alt](other.png \"Title\")\n").collect();
+        wrap_captioned_images(&mut events);
+        let mut html = String::new();
+        push_html(&mut html, events.into_iter(), &images, &Default::default()).unwrap();
+        assert_eq!(
+            html,
+            "<figure>\n<img src=\"image.png\" alt=\"alt\">\n<figcaption>A caption</figcaption></figure>\n\
+             <p>Text \n<img title=\"Title\" src=\"other.png\" alt=\"alt\"></p>\n"
+        );
+    }
+
+    #[test]
+    fn callouts() {
+        use super::{push_html, wrap_callouts};
+        use std::collections::HashMap;
+
+        let mut events: Vec<_> = crate::djot::parse(
+            "::: warning\nCareful.\n:::\n\n{title=\"Did you know?\"}\n::: tip\nA tip.\n:::\n\n::: other\nText.\n:::\n",
+        )
+        .collect();
+        wrap_callouts(&mut events, &["tip".to_owned(), "warning".to_owned()]);
+        let mut html = String::new();
+        push_html(&mut html, events.into_iter(), &HashMap::new(), &Default::default()).unwrap();
+
+        assert!(html.contains("<aside class=\"warning callout callout-warning\" role=\"note\">"));
+        assert!(html.contains("<p class=\"callout-title\">Warning</p>"));
+        assert!(html.contains("<p class=\"callout-title\">Did you know?</p>"));
+        assert!(html.contains("<div class=\"other\">"));
+        assert_eq!(html.matches("<aside").count(), 2);
+        assert_eq!(html.matches("</aside>").count(), 2);
+        assert_eq!(html.matches("</div>").count(), 1);
+    }
+
+    #[test]
+    fn check_footnotes() {
+        use super::{check_footnotes, FootnoteProblems};
+
+        let events: Vec<_> = crate::djot::parse("A[^a], b[^b] and a[^a].\n\n[^a]: A.\n\n[^c]: C.\n").collect();
+        assert_eq!(
+            check_footnotes(&events),
+            FootnoteProblems {
+                missing: vec!["b".to_owned()],
+                unreferenced: vec!["c".to_owned()],
+            }
+        );
+
+        let events: Vec<_> = crate::markdown::parse("A[^a].\n\n[^a]: A.\n").collect();
+        assert_eq!(check_footnotes(&events), FootnoteProblems::default());
+    }
+
+    #[test]
+    fn footnote_backrefs() {
+        use super::push_html;
+        use std::collections::HashMap;
+
+        let events = crate::djot::parse("A[^note] and b[^note].\n\n[^note]: The note.\n");
+        let mut html = String::new();
+        push_html(&mut html, events, &HashMap::new(), &Default::default()).unwrap();
+
+        assert!(html.contains("<sup class=\"footnote-reference\" id=\"fnref-1\">"));
+        assert!(html.contains("<sup class=\"footnote-reference\" id=\"fnref-1-2\">"));
+        assert!(html.contains("<a class=\"footnote-backref\" href=\"#fnref-1\" role=\"doc-backlink\">↩</a>"));
+        assert!(html.contains("<a class=\"footnote-backref\" href=\"#fnref-1-2\" role=\"doc-backlink\">↩</a>"));
+        assert_eq!(html.matches("<li").count(), html.matches("</li>").count());
+    }
+
+    #[test]
+    fn number_figures() {
+        use std::{collections::HashMap, path::PathBuf};
+
+        use super::{number_figures, push_html, wrap_captioned_images};
+        use crate::types::Images;
+
+        let images = HashMap::from(["a.png".to_owned(), "b.png".to_owned()].map(|link| {
+            let images = Images {
+                original: PathBuf::from(&link),
+                original_width: None,
+                original_height: None,
+                variants: vec![],
+                sources: vec![],
+            };
+            (link, images)
+        }));
+
+        let mut events: Vec<_> = crate::djot::parse(
+            "See [](~fig:b) and [the first](~fig:a).\n\n\
+             ![alt](a.png){#a title=\"First\"}\n\n\
+             ![alt](b.png){#b title=\"Second\"}\n",
+        )
+        .collect();
+        wrap_captioned_images(&mut events);
+        number_figures(&mut events).unwrap();
+        let mut html = String::new();
+        push_html(&mut html, events.into_iter(), &images, &Default::default()).unwrap();
+        assert_eq!(
+            html,
+            "<p>See \n<a href=\"#b\">Figure 2</a> and \n<a href=\"#a\">the first</a>.</p>\n\
+             <figure id=\"a\">\n<img src=\"a.png\" alt=\"alt\">\n<figcaption>\
+             <span class=\"figure-number\">Figure 1:</span> First</figcaption></figure>\n\
+             <figure id=\"b\">\n<img src=\"b.png\" alt=\"alt\">\n<figcaption>\
+             <span class=\"figure-number\">Figure 2:</span> Second</figcaption></figure>\n"
+        );
+
+        let mut events: Vec<_> = crate::djot::parse("[](~fig:missing)\n").collect();
+        assert!(number_figures(&mut events).is_err());
+    }
+
+    #[test]
+    fn inline_code() {
+        use std::collections::HashMap;
+
+        use super::push_html;
+
+        let render = |djot: &str| {
+            let mut html = String::new();
+            push_html(
+                &mut html,
+                crate::djot::parse(djot),
+                &HashMap::new(),
+                &Default::default(),
+            )
+            .unwrap();
+            html
+        };
+
+        assert_eq!(render("Use `a < b`.\n"), "<p>Use \n<code>a &lt; b</code>.</p>\n");
+
+        let html = render("Use `let x = 1;`{.language-rust}.\n");
+        assert!(html.starts_with("<p>Use <code class=\"language-rust highlight\" data-lang=\"rust\">"));
+        assert!(html.contains("<span class=\""));
+        assert!(html.ends_with("</code>.</p>\n"));
+
+        assert_eq!(
+            render("Use `a < b`{.language-text}.\n"),
+            "<p>Use <code class=\"language-text\">a &lt; b</code>.</p>\n"
+        );
+    }
+
+    #[test]
+    fn heading_anchors() {
+        use std::collections::HashMap;
+
+        use super::{push_html, HtmlOptions};
+        use crate::config::HeadingAnchors;
+
+        let render = |heading_anchors| {
+            let options = HtmlOptions {
+                heading_anchors,
+                ..Default::default()
+            };
+            let mut html = String::new();
+            push_html(&mut html, crate::djot::parse("## Heading\n"), &HashMap::new(), &options).unwrap();
+            html
+        };
+
+        assert_eq!(
+            render(HeadingAnchors::Wrap),
+            "<section id=\"Heading\">\n<h2><a href=\"#Heading\">Heading</a></h2>\n</section>\n"
+        );
+        assert_eq!(
+            render(HeadingAnchors::Prepend),
+            "<section id=\"Heading\">\n\
+             <h2><a class=\"anchor\" href=\"#Heading\" aria-hidden=\"true\">#</a>Heading</h2>\n</section>\n"
+        );
+        assert_eq!(
+            render(HeadingAnchors::None),
+            "<section id=\"Heading\">\n<h2>Heading</h2>\n</section>\n"
+        );
+    }
+
+    #[test]
+    fn external_links() {
+        use std::collections::HashMap;
+
+        use super::{push_html, HtmlOptions};
+
+        let options = HtmlOptions {
+            site_host: Some("example.com".to_owned()),
+            ..Default::default()
+        };
+        let render = |djot: &str| {
+            let mut html = String::new();
+            push_html(&mut html, crate::djot::parse(djot), &HashMap::new(), &options).unwrap();
+            html
+        };
+
+        assert_eq!(
+            render("[a](https://example.org/foo)"),
+            "<p>\n<a rel=\"noopener noreferrer\" target=\"_blank\" href=\"https://example.org/foo\">a</a></p>\n"
+        );
+        assert_eq!(
+            render("[a](https://EXAMPLE.com/foo) [b](/foo) [c](#foo)"),
+            "<p>\n<a href=\"https://EXAMPLE.com/foo\">a</a> \n<a href=\"/foo\">b</a> \n<a href=\"#foo\">c</a></p>\n"
+        );
+        assert_eq!(
+            render("[a](https://example.org/foo){external=false}"),
+            "<p>\n<a href=\"https://example.org/foo\">a</a></p>\n"
+        );
+        assert_eq!(
+            render("[a](https://example.org/foo){target=_self}"),
+            "<p>\n<a rel=\"noopener noreferrer\" target=\"_self\" href=\"https://example.org/foo\">a</a></p>\n"
+        );
+    }
+
     #[test]
     fn attr_alphabetical() {
         let mut attributes = Attributes::new();
@@ -1120,6 +2459,37 @@ mod tests {
         assert!(iter.next().is_none());
     }
 
+    #[test]
+    fn rewrite_internal_links() {
+        use std::collections::HashMap;
+
+        use super::{rewrite_and_emit_internal_links, Container, Event};
+        use crate::{types, Ctx};
+
+        let ctx = Ctx::for_test("");
+        let entries = [types::EntryMeta::for_test(&ctx, "pages/about.dj")];
+        let entries_by_name = HashMap::from([("pages/about", 0)]);
+
+        let link = |destination: &'static str| Event::Start {
+            container: Container::Link {
+                destination: destination.into(),
+            },
+            attributes: Attributes::new(),
+        };
+        let mut events = vec![link("~/pages/about#anchor"), link("https://example.com"), link("#")];
+        let links = rewrite_and_emit_internal_links(&mut events, &entries, &entries_by_name).unwrap();
+        assert_eq!(links, [0]);
+        assert!(matches!(
+            &events[0],
+            Event::Start { container: Container::Link { destination }, .. }
+                if destination == "http://localhost:8080/about#anchor"
+        ));
+
+        let mut events = vec![link("~/pages/missing")];
+        assert!(rewrite_and_emit_internal_links(&mut events, &entries, &entries_by_name).is_err());
+        assert!(rewrite_and_emit_internal_links(&mut vec![link("~/pages/about")], &[], &HashMap::new()).is_err());
+    }
+
     #[test]
     fn attr_override() {
         let mut attributes = Attributes::new();
@@ -1129,4 +2499,67 @@ mod tests {
         attributes.insert("foo", "bar");
         assert_eq!(attributes.get("foo").unwrap(), &AttributeValue::Raw("bar".into()),);
     }
+
+    #[test]
+    fn attr_class_merge() {
+        let mut attributes = Attributes::new();
+
+        attributes.insert("class", "");
+        attributes.insert("class", "foo");
+        assert_eq!(attributes.get("class").unwrap(), &AttributeValue::Raw("foo".into()),);
+        attributes.insert("class", "bar baz");
+        assert_eq!(
+            attributes.get("class").unwrap(),
+            &AttributeValue::Raw("foo bar baz".into()),
+        );
+        assert!(attributes.has_class("bar"));
+        assert_eq!(attributes.len(), 1);
+    }
+
+    #[test]
+    fn deduplicate_heading_ids() {
+        use std::collections::HashMap;
+
+        use super::{deduplicate_heading_ids, extract_toc, push_html};
+
+        let mut events: Vec<_> = crate::djot::parse("{#a}\n# A\n\n{#a}\n# B\n\n{#a-1}\n# C\n").collect();
+        deduplicate_heading_ids(&mut events);
+
+        let ids: Vec<_> = extract_toc(&events)
+            .unwrap()
+            .into_iter()
+            .map(|entry| entry.id)
+            .collect();
+        assert_eq!(ids, ["a", "a-1", "a-1-1"]);
+
+        let mut html = String::new();
+        push_html(&mut html, events.into_iter(), &HashMap::new(), &Default::default()).unwrap();
+        assert!(html.contains("<section id=\"a-1\">"));
+        assert!(html.contains("<a href=\"#a-1\">B</a>"));
+        assert!(html.contains("<section id=\"a-1-1\">"));
+        assert!(html.contains("<a href=\"#a-1-1\">C</a>"));
+    }
+
+    #[test]
+    fn resolve_internal_asset() {
+        use std::collections::HashMap;
+
+        use super::resolve_internal_asset;
+
+        let entries_by_name = HashMap::from([("posts/foo", 0), ("posts/foo/bar", 1)]);
+        assert_eq!(
+            resolve_internal_asset("posts/foo/pic.png", &entries_by_name),
+            Some((0, "pic.png"))
+        );
+        assert_eq!(
+            resolve_internal_asset("posts/foo/img/pic.png", &entries_by_name),
+            Some((0, "img/pic.png"))
+        );
+        assert_eq!(
+            resolve_internal_asset("posts/foo/bar/pic.png", &entries_by_name),
+            Some((1, "pic.png"))
+        );
+        assert_eq!(resolve_internal_asset("posts/baz/pic.png", &entries_by_name), None);
+        assert_eq!(resolve_internal_asset("posts/foo", &entries_by_name), None);
+    }
 }
@@ -1,24 +1,37 @@
 use anyhow::Context;
 use clap::Parser;
 use rayon::prelude::*;
-use std::collections::HashMap;
+use std::collections::{btree_map, BTreeMap, HashMap, HashSet};
 use std::path::{Path, PathBuf};
 use std::sync::{mpsc, Arc, Condvar, Mutex};
 use std::time::Duration;
 
 use notify_debouncer_full::{new_debouncer, notify::*, DebounceEventResult};
 
+mod alias;
+mod check;
 mod cli;
 mod config;
 mod ctx;
 mod djot;
+mod emoji;
+mod entry_cache;
+mod feed;
+mod fingerprint;
 mod front_matter;
 mod highlight;
 mod images;
+mod init;
 mod ir_markup;
 mod markdown;
 mod out;
+mod precompress;
 mod render;
+mod report;
+mod robots;
+mod sanitize;
+mod serve;
+mod sitemap;
 mod tests;
 mod types;
 mod utils;
@@ -28,6 +41,9 @@ use out::Out;
 
 use crate::types::EntrySourceKind;
 
+/// The output directory if none is configured.
+const DEFAULT_OUT_DIR: &str = "./out";
+
 #[derive(Debug)]
 struct Group {
     name: String,
@@ -49,6 +65,109 @@ impl Group {
     }
 }
 
+/// Split the per-entry results of a build step into the successful results and the indices of the
+/// failed entries. Errors are logged and failed entries are removed from `groups`. The caller is
+/// responsible for removing failed entries from other collections.
+fn retain_ok<T>(groups: &mut [Group], results: Vec<anyhow::Result<T>>) -> (Vec<T>, Vec<usize>) {
+    let mut ok = Vec::with_capacity(results.len());
+    let mut failed = vec![];
+
+    // Iterate in reverse, so removing an entry does not shift the indices of the entries still to
+    // be visited.
+    for (idx, result) in results.into_iter().enumerate().rev() {
+        match result {
+            Ok(value) => ok.push(value),
+            Err(err) => {
+                log::error!("Failed to build entry: {err:?}");
+                for group in groups.iter_mut() {
+                    group.remove_idx(idx);
+                }
+                failed.push(idx);
+            }
+        }
+    }
+
+    ok.reverse();
+    failed.reverse();
+    (ok, failed)
+}
+
+/// Order two entries of a group by date in the given order. Undated entries come after dated
+/// entries, and ties are broken by slug, so the order does not depend on file names.
+fn cmp_entries(order: config::SortOrder, a: &types::EntryMeta, b: &types::EntryMeta) -> std::cmp::Ordering {
+    use std::cmp::Ordering;
+
+    let by_date = match (a.date, b.date) {
+        (Some(a_date), Some(b_date)) => {
+            let ordering = (a_date, a.time).cmp(&(b_date, b.time));
+            match order {
+                config::SortOrder::Ascending => ordering,
+                config::SortOrder::Descending => ordering.reverse(),
+            }
+        }
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => Ordering::Equal,
+    };
+    by_date
+        .then_with(|| a.slug.cmp(&b.slug))
+        .then_with(|| a.canonical_name.cmp(&b.canonical_name))
+}
+
+/// The indices of `entries` with the entries of each group sorted by [`cmp_entries`]. Groups are
+/// contiguous, so sorting within their ranges keeps the ranges valid.
+fn sorted_entry_indices(ctx: &Ctx, groups: &[Group], entries: &[types::EntryMeta]) -> Vec<usize> {
+    let mut indices: Vec<usize> = (0..entries.len()).collect();
+    for group in groups {
+        indices[group.range.clone()].sort_by(|&a, &b| cmp_entries(ctx.entry_order(), &entries[a], &entries[b]));
+    }
+    indices
+}
+
+/// Link every entry to the entries before and after it in its group.
+fn link_siblings(groups: &[Group], entries: &mut [types::Entry]) {
+    for group in groups {
+        for idx in group.range.clone() {
+            let prev = (idx > group.range.start).then(|| entries[idx - 1].meta.permalink.clone());
+            let next = (idx + 1 < group.range.end).then(|| entries[idx + 1].meta.permalink.clone());
+            entries[idx].prev_permalink = prev;
+            entries[idx].next_permalink = next;
+        }
+    }
+}
+
+/// The output path of page `page` (zero-based) of the template at `template_path`. The first page is
+/// written to the template's own path, the other pages follow `pattern`.
+fn paginated_out_file(pattern: &str, template_path: &Path, name: &str, extension: &str, page: u32) -> PathBuf {
+    if page == 0 {
+        template_path.with_file_name(format!("{name}{extension}"))
+    } else {
+        template_path.with_file_name(
+            pattern
+                .replace("{name}", name)
+                .replace("{extension}", extension)
+                .replace("{page}", &(page + 1).to_string()),
+        )
+    }
+}
+
+/// Reorder `values` such that the value at `indices[idx]` moves to `idx`.
+fn permute<T>(values: Vec<T>, indices: &[usize]) -> Vec<T> {
+    let mut values: Vec<Option<T>> = values.into_iter().map(Some).collect();
+    indices
+        .iter()
+        .map(|&idx| values[idx].take().expect("indices are a permutation"))
+        .collect()
+}
+
+/// Parse the markup of an entry, without its front matter.
+fn parse_entry<'s>(ctx: &'s Ctx, meta: &types::EntryMeta, content: &'s str) -> Vec<ir_markup::Event<'s>> {
+    match meta.source_kind {
+        EntrySourceKind::Djot => djot::parse_with_raw_formats(content, ctx.raw_formats()).collect(),
+        EntrySourceKind::CommonMark => markdown::parse(content).collect(),
+    }
+}
+
 fn collect_entries<'a>(
     ctx: &'a Ctx,
     path_prefix: &'a Path,
@@ -56,14 +175,16 @@ fn collect_entries<'a>(
 ) -> impl Iterator<Item = anyhow::Result<types::EntryMeta>> + 'a {
     walkdir::WalkDir::new(path)
         .follow_links(true)
-        .max_depth(2)
         .sort_by_file_name()
         .into_iter()
         .filter_map(move |entry| match entry {
             Ok(entry) => {
-                let name = entry.path().to_str()?;
-                if entry.file_type().is_file() && (name.ends_with(".dj") || name.ends_with(".md")) {
-                    Some(types::EntryMeta::entry_from_path(ctx, path_prefix, entry.path()))
+                let extension = entry.path().extension()?;
+                if entry.file_type().is_file() && EntrySourceKind::from_extension(extension).is_some() {
+                    Some(
+                        types::EntryMeta::entry_from_path(ctx, path_prefix, entry.path())
+                            .with_context(|| format!("in entry {}", entry.path().display())),
+                    )
                 } else {
                     None
                 }
@@ -108,10 +229,21 @@ fn collect_entry_groups(ctx: &Ctx, path: impl AsRef<Path>) -> anyhow::Result<(Ve
     anyhow::Ok((groups, entries))
 }
 
-fn build(ctx: &Ctx, path: &Path, renderer: &render::Renderer) -> anyhow::Result<()> {
-    let out = Out::at("./out")?;
-
-    let (groups, entries) = collect_entry_groups(&ctx, path.join("entries"))?;
+fn build(
+    ctx: &Ctx,
+    path: &Path,
+    out_dir: &Path,
+    renderer: &render::Renderer,
+    image_cache: &images::ImageCache,
+    entry_cache: &entry_cache::EntryCache,
+    include_drafts: bool,
+) -> anyhow::Result<()> {
+    let mut report = report::BuildReport::start();
+    let out = Out::at(out_dir)?;
+    highlight::reset_warnings();
+    ir_markup::reset_math_cache();
+
+    let (mut groups, mut entries) = collect_entry_groups(&ctx, path.join(ctx.entries_dir()))?;
 
     log::info!("Found {} entry group(s):", groups.len());
     for group in groups.iter() {
@@ -126,72 +258,102 @@ fn build(ctx: &Ctx, path: &Path, renderer: &render::Renderer) -> anyhow::Result<
     //    now, keep track of new image transcodes. At the moment a full parsing step is required
     //    because only images actually linked in the markup are included, but that may be an
     //    unnecessary complication.
-    // 2. For every entry, parse its front matter (i.e., parse front matter TOML/YAML document +
-    //    parse the start of the markup to render the entry title).
-    // 3. Then iterate through entries, reading and parsing full file contents from fs and
-    //    rendering HTML to fs.
-    let content: Vec<String> = entries
-        .iter()
-        .map(|entry| std::fs::read_to_string(&entry.file_path))
-        .collect::<std::io::Result<_>>()?;
+    // 2. For every entry, parse its front matter TOML/YAML document, which is all that is needed to
+    //    sort and filter entries.
+    // 3. Then iterate through entries, reading and parsing full file contents from fs, rendering
+    //    titles and rewriting links, and rendering HTML to fs.
+    //
+    // Step 3 is done as one task per entry. As back-references and images need the full set of
+    // entries, the markup is scanned for links and images in a cheap first pass, and parsed again
+    // when rendering.
+    //
+    // Entries that fail to build are logged and skipped, so the other entries are still built. The
+    // build as a whole only fails in production.
+    let mut failed_entries = 0;
+    let content: Vec<String> = {
+        let content = entries
+            .iter()
+            .map(|meta| {
+                std::fs::read_to_string(&meta.file_path)
+                    .with_context(|| format!("in entry {}", meta.file_path.display()))
+            })
+            .collect();
+        let (content, failed) = retain_ok(&mut groups, content);
+        for &idx in failed.iter().rev() {
+            entries.remove(idx);
+        }
+        failed_entries += failed.len();
+        for (meta, content) in entries.iter_mut().zip(&content) {
+            meta.source_hash = entry_cache::hash(content);
+        }
+        content
+    };
 
     // Parse entry front matter
     let (content, mut front_matter): (Vec<&str>, Vec<types::FrontMatter>) = {
-        let mut content: Vec<&str> = content.iter().map(String::as_str).collect();
-
-        let front_matter: Vec<_> = entries
+        let results = entries
             .par_iter()
-            .zip(&mut content)
-            .map(|(_meta, content)| match front_matter::parse_front_matter(content) {
-                Ok((front_matter, rest)) => {
-                    *content = rest;
-                    Ok(front_matter)
-                }
-                Err(err) => Err(err),
+            .zip(&content)
+            .map(|(meta, content)| {
+                front_matter::parse_front_matter(content)
+                    .with_context(|| format!("in entry {}", meta.file_path.display()))
             })
-            .collect::<anyhow::Result<_>>()?;
+            .collect();
+        let (results, failed) = retain_ok(&mut groups, results);
+        for &idx in failed.iter().rev() {
+            entries.remove(idx);
+        }
+        failed_entries += failed.len();
 
-        (content, front_matter)
+        results
+            .into_iter()
+            .map(|(front_matter, content)| (content, front_matter))
+            .unzip()
     };
 
-    let mut parsed: Vec<Vec<ir_markup::Event<'_>>> = entries
-        .par_iter()
-        .zip(content)
-        .map(|(entry, content)| match entry.source_kind {
-            EntrySourceKind::Djot => djot::parse(content).collect(),
-            EntrySourceKind::CommonMark => markdown::parse(content).collect(),
-        })
-        .collect();
-
-    // Parse entry front matter, consuming the front matter events from `parsed`
-    entries
-        .iter()
-        .zip(&mut parsed)
-        .zip(&mut front_matter)
-        .for_each(|((meta, parsed), front_matter)| {
-            if let Ok(Some(title)) = ir_markup::parse_and_render_title(parsed) {
-                front_matter.title = title;
-            } else {
-                front_matter.title = meta.slug.clone();
+    // Dates set in front matter take precedence over dates from file names. The output paths of
+    // entries are still derived from their file names, unless a slug or permalink is set in front
+    // matter. These are applied before links are rewritten, so links resolve to the overridden
+    // URLs.
+    for (meta, front_matter) in entries.iter_mut().zip(&front_matter) {
+        meta.override_out_path(ctx, front_matter.slug.as_deref(), front_matter.out_file.as_deref());
+
+        if let Some((date, time)) = front_matter.date {
+            if meta.date.is_some() {
+                log::debug!(
+                    "Front matter date of {} overrides the date in its file name",
+                    meta.canonical_name
+                );
             }
-        });
+            meta.date = Some(date);
+            meta.time = time;
+        }
+    }
 
-    // When in production-mode, filter out non-released entries
-    let (groups, entries, mut parsed, front_matter) = if ctx.build_kind().is_production() {
-        let before = entries.len();
+    // Sort the entries within their groups, now that their dates are known
+    let (mut entries, mut content, mut front_matter) = {
+        let indices = sorted_entry_indices(ctx, &groups, &entries);
+        (
+            permute(entries, &indices),
+            permute(content, &indices),
+            permute(front_matter, &indices),
+        )
+    };
 
-        let mut groups = groups;
-        let mut entries = entries;
-        let mut parsed = parsed;
-        let mut front_matter = front_matter;
+    // Filter out draft entries, and when in production-mode, non-released entries. This only needs
+    // the front matter, so filtered entries are never parsed. Including drafts keeps all entries,
+    // for previewing.
+    if !include_drafts {
+        let before = entries.len();
 
         for idx in (0..entries.len()).rev() {
-            if front_matter[idx].released.unwrap_or(true) {
+            let front_matter_ = &front_matter[idx];
+            if !front_matter_.draft && (ctx.build_kind().is_develop() || !front_matter_.is_draft()) {
                 continue;
             }
 
             entries.remove(idx);
-            parsed.remove(idx);
+            content.remove(idx);
             front_matter.remove(idx);
 
             for group in groups.iter_mut() {
@@ -201,104 +363,335 @@ fn build(ctx: &Ctx, path: &Path, renderer: &render::Renderer) -> anyhow::Result<
 
         let after = entries.len();
         if before != after {
-            log::info!("Filtered out {} non-released entries", before - after);
+            log::info!("Filtered out {} draft or non-released entries", before - after);
         }
+    }
 
-        (groups, entries, parsed, front_matter)
-    } else {
-        (groups, entries, parsed, front_matter)
+    let entries_by_name: HashMap<&str, usize> = {
+        let mut map = HashMap::new();
+        for (idx, entry) in entries.iter().enumerate() {
+            if map.insert(&*entry.canonical_name, idx).is_some() {
+                anyhow::bail!("Entry name is duplicated: {}", entry.canonical_name);
+            }
+        }
+        map
     };
 
-    let entries_and_front_matter: Vec<types::EntryMetaAndFrontMatter> = entries
-        .iter()
-        .zip(&front_matter)
-        .map(|(meta, front_matter)| types::EntryMetaAndFrontMatter { meta, front_matter })
+    {
+        let mut out_files: HashMap<&Path, &str> = HashMap::new();
+        for entry in entries.iter() {
+            if let Some(other) = out_files.insert(&entry.out_file, &entry.canonical_name) {
+                anyhow::bail!(
+                    "Entries {} and {} have the same permalink: {}",
+                    other,
+                    entry.canonical_name,
+                    entry.permalink
+                );
+            }
+        }
+    }
+
+    report.end_phase("read");
+
+    // Scan the markup of every entry for its internal links and images, which entries need of each
+    // other before they can be rendered. Link rewriting only needs the names and permalinks of the
+    // other entries, which are known by now. The internal links are recorded as entry indices:
+    // linker => linkee, and are turned into "back-references" (as in, for each entry, "which
+    // entries link here") when rendering. The parsed markup is dropped again, so only the markup
+    // of entries currently being scanned is kept in memory.
+    //
+    // TODO: it would be nice to error on dead anchor links (headings), but collecting anchors
+    // requires a full pass of the input files. As links to anchors don't require any link
+    // rewriting, perhaps the HTML render step can output entry anchors as a side effect, and
+    // sprokkel then checks whether the links are valid.
+    let scanned: Vec<anyhow::Result<(Vec<usize>, Vec<String>)>> = entries
+        .par_iter()
+        .zip(&content)
+        .map(|(meta, content)| {
+            let mut parsed = parse_entry(ctx, meta, content);
+            // The title is removed like when rendering, so links and images in it are not counted
+            let _ = ir_markup::parse_and_render_title(&mut parsed);
+            let mut linkee_indices =
+                ir_markup::rewrite_and_emit_internal_links(&mut parsed, &entries, &entries_by_name)
+                    .with_context(|| format!("in entry {}", meta.file_path.display()))?;
+            linkee_indices.sort();
+            linkee_indices.dedup();
+            Ok((linkee_indices, ir_markup::image_links(&parsed)))
+        })
         .collect();
 
-    // Rewrite internal links and turn them into "back-references" (as in, for each entry, "which
-    // entries link here")
-    // Records entry indices: linker => linkee
-    let references: Vec<(usize, usize)> = {
-        let entries_by_name: HashMap<&str, &types::EntryMetaAndFrontMatter> = {
-            let mut map = HashMap::new();
-            for entry in entries_and_front_matter.iter() {
-                if map.insert(&*entry.meta.canonical_name, entry).is_some() {
-                    anyhow::bail!("Entry name is duplicated: {}", entry.meta.canonical_name);
-                }
+    // Failed entries are skipped when rendering
+    let mut references = vec![];
+    let mut image_links = vec![];
+    let scan_results: Vec<anyhow::Result<()>> = scanned
+        .into_iter()
+        .enumerate()
+        .map(|(linker_idx, result)| match result {
+            Ok((linkee_indices, links)) => {
+                references.extend(linkee_indices.into_iter().map(|linkee_idx| (linker_idx, linkee_idx)));
+                image_links.push(links);
+                Ok(())
             }
-            map
-        };
+            Err(err) => {
+                image_links.push(vec![]);
+                Err(err)
+            }
+        })
+        .collect();
 
-        // TODO: it would be nice to error on dead anchor links (headings), but collecting anchors
-        // requires a full pass of the input files. As links to anchors don't require any link
-        // rewriting, perhaps the HTML render step can output entry anchors as a side effect, and
-        // sprokkel then checks whether the links are valid.
-        let entries_addr = (&entries_and_front_matter[0] as *const _) as usize;
-        let references = parsed
-            .par_iter_mut()
-            .enumerate()
-            .map(|(linker_idx, parsed)| {
-                let internal_links = ir_markup::rewrite_and_emit_internal_links(parsed, &entries_by_name)?;
-
-                let mut linkee_indices = internal_links
-                    .into_iter()
-                    .map(|linkee_entry| {
-                        let linkee_addr = (linkee_entry as *const _) as usize;
-                        // calculate index of referenced entry by memory address
-                        let linkee_idx =
-                            (linkee_addr - entries_addr) / std::mem::size_of::<types::EntryMetaAndFrontMatter>();
-                        (linker_idx, linkee_idx)
-                    })
-                    .collect::<Vec<_>>();
-                linkee_indices.sort();
-                linkee_indices.dedup();
+    report.end_phase("scan");
 
-                anyhow::Ok(linkee_indices)
-            })
-            .try_reduce_with(|mut left, right| {
-                left.extend(right);
-                anyhow::Ok(left)
-            });
-        references.unwrap_or(anyhow::Ok(vec![]))?
-    };
+    let images = images::extract_images(ctx, &out, image_cache, &mut report, &entries, &image_links)?;
+    drop(image_links);
+    report.end_phase("images");
 
-    let images = images::extract_images(&out, &entries, &parsed)?;
+    // The permalinks of the entries every entry links to, which its rendered markup depends on
+    let linked_permalinks = {
+        let mut linked_permalinks: Vec<Vec<&str>> = (0..entries.len()).map(|_| vec![]).collect();
+        for &(linker, linkee) in &references {
+            linked_permalinks[linker].push(&entries[linkee].permalink);
+        }
+        linked_permalinks
+    };
 
-    // Render entry markup to HTML
-    let rendered: Vec<_> = entries
+    // Parse the markup of every entry, take its title from the markup, rewrite its internal links
+    // and render it to HTML, as one task per entry. Markup is only rendered again if its source,
+    // its images or the entries it links to changed since the previous build.
+    let rendered: Vec<anyhow::Result<types::Entry>> = entries
         .par_iter()
-        .zip(parsed)
+        .zip(&content)
+        .zip(front_matter.par_iter_mut())
         .zip(images)
-        .zip(&front_matter)
-        .map(|(((meta, parsed), images), front_matter)| {
-            let mut buf = String::new();
-            ir_markup::push_html(&mut buf, parsed.into_iter(), &images)?;
-            Ok(types::Entry {
-                meta,
-                front_matter,
-                summary: buf,
-                rest: String::new(),
-            })
-        })
-        .collect::<anyhow::Result<_>>()?;
+        .zip(scan_results)
+        .zip(&linked_permalinks)
+        .map(
+            |(((((meta, content), front_matter), images), scan_result), linked_permalinks)| {
+                scan_result?;
+                let in_entry = || format!("in entry {}", meta.file_path.display());
+
+                let mut parsed = parse_entry(ctx, meta, content);
+
+                // Consumes the title events from `parsed`
+                front_matter.title = match ir_markup::parse_and_render_title(&mut parsed) {
+                    Ok(Some(title)) => title,
+                    _ => meta.slug.clone(),
+                };
+                let front_matter: &types::FrontMatter = front_matter;
+
+                ir_markup::rewrite_and_emit_internal_links(&mut parsed, &entries, &entries_by_name)
+                    .with_context(in_entry)?;
+
+                let cover_image = match ir_markup::first_image(&parsed).and_then(|link| images.get(link)) {
+                    Some(images) => {
+                        let (path, width, height) = images.mid_size();
+                        Some(types::CoverImage {
+                            url: ctx.path_to_absolute_url(path)?,
+                            width,
+                            height,
+                        })
+                    }
+                    None => None,
+                };
+
+                let key = {
+                    let mut images: Vec<_> = images.iter().collect();
+                    images.sort_unstable_by_key(|(link, _)| *link);
+                    entry_cache::hash((meta.source_hash, images, linked_permalinks))
+                };
+                let markup = entry_cache.markup(&meta.file_path, key, || {
+                    ir_markup::deduplicate_heading_ids(&mut parsed);
+                    let toc = ir_markup::extract_toc(&parsed)?;
+                    ir_markup::replace_toc_placeholders(&mut parsed, &toc);
+                    ir_markup::wrap_captioned_images(&mut parsed);
+                    ir_markup::wrap_callouts(&mut parsed, ctx.callouts());
+                    ir_markup::number_figures(&mut parsed)?;
+                    let footnotes = ir_markup::check_footnotes(&parsed);
+                    for label in &footnotes.unreferenced {
+                        log::warn!("Footnote {label} is never referenced, {}", in_entry());
+                    }
+                    if !footnotes.missing.is_empty() {
+                        let missing = footnotes.missing.join(", ");
+                        if ctx.strict_footnotes() {
+                            anyhow::bail!("Footnote definitions missing: {missing}");
+                        }
+                        log::warn!("Footnote definitions missing: {missing}, {}", in_entry());
+                    }
+                    let word_count = ir_markup::word_count(&parsed);
+                    let has_math = ir_markup::has_math(&parsed);
+                    let has_code = ir_markup::has_code(&parsed);
+                    let rest = ir_markup::split_summary(&mut parsed)?;
+
+                    let mut summary = String::new();
+                    ir_markup::push_html(&mut summary, parsed.into_iter(), &images, ctx.html_options())?;
+                    let mut rest_html = String::new();
+                    ir_markup::push_html(&mut rest_html, rest.into_iter(), &images, ctx.html_options())?;
+                    Ok(entry_cache::Markup {
+                        summary,
+                        rest: rest_html,
+                        toc,
+                        word_count,
+                        has_math,
+                        has_code,
+                    })
+                });
+                let entry_cache::Markup {
+                    summary,
+                    rest,
+                    toc,
+                    word_count,
+                    has_math,
+                    has_code,
+                } = markup.with_context(in_entry)?;
+
+                Ok(types::Entry {
+                    meta,
+                    front_matter,
+                    summary,
+                    rest,
+                    toc,
+                    word_count,
+                    reading_time: word_count.div_ceil(ctx.words_per_minute() as usize),
+                    has_math,
+                    has_code,
+                    is_draft: front_matter.is_draft(),
+                    canonical_url: ctx.path_to_absolute_url(&meta.out_file)?,
+                    cover_image,
+                    prev_permalink: None,
+                    next_permalink: None,
+                })
+            },
+        )
+        .collect();
+    let (mut rendered, failed) = retain_ok(&mut groups, rendered);
+    report.end_phase("render markup");
+    failed_entries += failed.len();
+    link_siblings(&groups, &mut rendered);
+
+    // Turn the linker => linkee entry indices into lists of &Entry back-references and outgoing
+    // links for every entry. Entry indices are mapped to indices into `rendered`, which excludes
+    // failed entries.
+    let (references, links) = {
+        let rendered_idx: Vec<Option<usize>> = {
+            let mut failed = failed.iter().peekable();
+            let mut rendered_idx = 0;
+            (0..entries.len())
+                .map(|idx| {
+                    if failed.next_if_eq(&&idx).is_some() {
+                        None
+                    } else {
+                        rendered_idx += 1;
+                        Some(rendered_idx - 1)
+                    }
+                })
+                .collect()
+        };
 
-    // Turn the linker => linkee entry indices into a list of &Entry back-references for every
-    // entry.
-    let references = {
-        let mut references_: Vec<Vec<&types::Entry>> = (0..entries.len()).map(|_| vec![]).collect();
+        let mut references_: Vec<Vec<&types::Entry>> = (0..rendered.len()).map(|_| vec![]).collect();
+        let mut links: Vec<Vec<&types::Entry>> = (0..rendered.len()).map(|_| vec![]).collect();
 
         for (linker, linkee) in references {
-            references_[linkee].push(&rendered[linker]);
+            if let (Some(linker), Some(linkee)) = (rendered_idx[linker], rendered_idx[linkee]) {
+                references_[linkee].push(&rendered[linker]);
+                links[linker].push(&rendered[linkee]);
+            }
         }
 
-        references_
+        (references_, links)
     };
 
     let grouped_entries: HashMap<&str, &[types::Entry<'_>]> = groups
         .iter()
         .map(|Group { name, range }| (name.as_str(), &rendered[range.clone()]))
         .collect();
-    let render_context = renderer.render_context(&grouped_entries);
+    // The entries of all groups, sorted like the entries within a group
+    let all_entries: Vec<&types::Entry<'_>> = {
+        let mut all_entries: Vec<_> = rendered.iter().collect();
+        all_entries.sort_by(|a, b| cmp_entries(ctx.entry_order(), a.meta, b.meta));
+        all_entries
+    };
+    let rendered_by_name: HashMap<&str, &types::Entry<'_>> = rendered
+        .iter()
+        .map(|entry| (entry.meta.canonical_name.as_str(), entry))
+        .collect();
+
+    // Build the reverse map of tags to the entries tagged with them
+    let tags = {
+        let mut tags: BTreeMap<&str, types::Tag<'_>> = BTreeMap::new();
+        for entry in &rendered {
+            for name in &entry.front_matter.tags {
+                let tag = match tags.entry(name.as_str()) {
+                    btree_map::Entry::Occupied(tag) => tag.into_mut(),
+                    btree_map::Entry::Vacant(tag) => {
+                        let slug = utils::slugify(name);
+                        if slug.is_empty() {
+                            anyhow::bail!(
+                                "tag \"{name}\" of entry {} has an empty slug",
+                                entry.meta.canonical_name
+                            );
+                        }
+                        let out_file = PathBuf::from("tags").join(format!("{slug}.html"));
+                        tag.insert(types::Tag {
+                            permalink: ctx.path_to_url(&out_file)?,
+                            slug,
+                            out_file,
+                            entries: vec![],
+                        })
+                    }
+                };
+
+                // An entry may list the same tag more than once
+                if !tag.entries.last().is_some_and(|last| std::ptr::eq(*last, entry)) {
+                    tag.entries.push(entry);
+                }
+            }
+        }
+
+        let mut names_by_slug: HashMap<&str, &str> = HashMap::new();
+        for (name, tag) in &tags {
+            if let Some(other) = names_by_slug.insert(&tag.slug, *name) {
+                anyhow::bail!("tags \"{other}\" and \"{name}\" have the same slug \"{}\"", tag.slug);
+            }
+        }
+
+        tags
+    };
+
+    // Static assets are copied before rendering, such that templates can refer to their
+    // fingerprinted names
+    {
+        let asset_dir = path.join(ctx.assets_dir());
+        let mut manifest = fingerprint::AssetManifest::new();
+        if asset_dir.exists() {
+            out.copy_dir(&asset_dir, ".")?;
+            if ctx.fingerprint_assets() {
+                manifest = fingerprint::fingerprint_dir(&out, &asset_dir)?;
+            }
+        }
+        if renderer.set_asset_manifest(manifest) {
+            // Entry pages may refer to fingerprinted assets
+            entry_cache.clear_pages();
+        }
+    }
+
+    let render_context = &renderer.render_context(&grouped_entries, &all_entries, &rendered_by_name, &tags);
+
+    // Write the feeds of entry groups
+    for (group, feed) in ctx.feeds() {
+        let entries = match grouped_entries.get(group.as_str()) {
+            Some(entries) => entries,
+            None => {
+                log::warn!("A feed is configured for entry group \"{group}\", but that group does not exist");
+                continue;
+            }
+        };
+
+        for &format in &feed.formats {
+            let path = feed::feed_path(group, feed, format);
+            let rendered = feed::render(ctx, format, group, feed, &ctx.path_to_absolute_url(&path)?, entries);
+            out.update_file(&mut rendered.as_bytes(), path)?;
+            report.feeds += 1;
+        }
+    }
 
     // Render entries to HTML files using the template renderer, streaming results back to be
     // written to out.
@@ -306,32 +699,90 @@ fn build(ctx: &Ctx, path: &Path, renderer: &render::Renderer) -> anyhow::Result<
         let rendered = &rendered;
         rayon::scope(|s| {
             let (result_tx, result_rx) =
-                mpsc::sync_channel::<(&'_ Path, anyhow::Result<Vec<u8>>)>(rayon::current_num_threads());
+                mpsc::sync_channel::<(&'_ types::Entry<'_>, anyhow::Result<Vec<u8>>)>(rayon::current_num_threads());
 
             s.spawn(move |s| {
-                for (entry, references) in rendered.iter().zip(references) {
+                for ((entry, references), links) in rendered.iter().zip(references).zip(links) {
                     let result_tx = result_tx.clone();
                     s.spawn(move |_| {
-                        let mut write = Vec::new();
-                        let res = render_context.entry(&mut write, entry, &references);
-                        let _ = result_tx.send((&entry.meta.out_file, res.map(|_| write)));
+                        // Pages are only rendered again if the entry, the entries referring to it or
+                        // the entries it links to changed since the previous build
+                        let key = {
+                            fn names_titles_and_permalinks<'a>(
+                                entries: &[&'a types::Entry<'_>],
+                            ) -> Vec<(&'a str, &'a str, &'a str)> {
+                                entries
+                                    .iter()
+                                    .map(|entry| {
+                                        (
+                                            entry.meta.canonical_name.as_str(),
+                                            entry.front_matter.title.as_str(),
+                                            entry.meta.permalink.as_str(),
+                                        )
+                                    })
+                                    .collect()
+                            }
+                            entry_cache::hash((
+                                entry.meta.source_hash,
+                                &entry.summary,
+                                &entry.rest,
+                                names_titles_and_permalinks(&references),
+                                names_titles_and_permalinks(&links),
+                                &entry.prev_permalink,
+                                &entry.next_permalink,
+                            ))
+                        };
+                        let res = entry_cache
+                            .page(&entry.meta.file_path, key, || {
+                                let mut write = Vec::new();
+                                render_context.entry(&mut write, entry, &references, &links)?;
+                                Ok(write)
+                            })
+                            .with_context(|| format!("in entry {}", entry.meta.file_path.display()));
+                        let _ = result_tx.send((entry, res));
                     });
                 }
             });
 
-            while let Ok((path, result)) = result_rx.recv() {
-                let result = result?;
-                out.update_file(&mut &*result, path)?;
+            while let Ok((entry, result)) = result_rx.recv() {
+                match result {
+                    Ok(result) => {
+                        out.update_file(&mut &*result, &entry.meta.out_file)?;
+                        report.entries_rendered += 1;
+                    }
+                    Err(err) => {
+                        log::error!("Failed to render entry: {err:?}");
+                        failed_entries += 1;
+                    }
+                }
             }
 
             anyhow::Ok(())
         })?;
     }
 
+    // Render a page per tag, if a tag template exists
+    let render_tags = render_context.has_tag_template()?;
+    if render_tags {
+        let pages: Vec<(&Path, Vec<u8>)> = tags
+            .par_iter()
+            .map(|(name, tag)| {
+                let mut write = Vec::new();
+                render_context.tag(&mut write, name, tag)?;
+                Ok((tag.out_file.as_path(), write))
+            })
+            .collect::<anyhow::Result<_>>()?;
+
+        for (path, page) in pages {
+            out.update_file(&mut &*page, path)?;
+        }
+    }
+
     // Render all template files where no part of the template file path starts with an underscore.
-    {
-        let path = path.join("templates");
-        rayon::scope(|s| -> anyhow::Result<()> {
+    // The paths of rendered HTML pages are collected for the sitemap.
+    let mut template_pages = {
+        let path = path.join(ctx.templates_dir());
+        rayon::scope(|s| -> anyhow::Result<Vec<PathBuf>> {
             let (result_tx, result_rx) =
                 mpsc::sync_channel::<anyhow::Result<(PathBuf, String)>>(rayon::current_num_threads());
 
@@ -360,13 +811,9 @@ fn build(ctx: &Ctx, path: &Path, renderer: &render::Renderer) -> anyhow::Result<
                         let out_file = {
                             let template_path = template_path.clone();
                             let extension = extension.map(|ext| format!(".{ext}")).unwrap_or(String::new());
+                            let ctx = ctx.clone();
                             move |page| -> PathBuf {
-                                if page == 0 {
-                                    template_path.with_file_name(format!("{file_name}{extension}"))
-                                } else {
-                                    let page = page + 1;
-                                    template_path.with_file_name(format!("{file_name}-{page}{extension}"))
-                                }
+                                paginated_out_file(ctx.pagination_path(), &template_path, &file_name, &extension, page)
                             }
                         };
                         let page_permalink = {
@@ -375,7 +822,7 @@ fn build(ctx: &Ctx, path: &Path, renderer: &render::Renderer) -> anyhow::Result<
                             let ctx = ctx.clone();
                             move |page| -> String {
                                 let path = out_file(page);
-                                ctx.path_to_absolute_url(path).unwrap()
+                                ctx.path_to_url(path).unwrap()
                             }
                         };
 
@@ -396,25 +843,88 @@ fn build(ctx: &Ctx, path: &Path, renderer: &render::Renderer) -> anyhow::Result<
             }
             drop(result_tx);
 
+            let mut pages = vec![];
             while let Ok(result) = result_rx.recv() {
                 let (path, content) = result?;
-                out.update_file(&mut &*content.as_bytes(), path)?;
+                out.update_file(&mut &*content.as_bytes(), &path)?;
+                if path.extension().is_some_and(|extension| extension == "html") {
+                    pages.push(path);
+                }
             }
 
-            Ok(())
-        })?;
-    }
+            Ok(pages)
+        })?
+    };
+    report.template_pages = template_pages.len();
+    report.end_phase("render pages");
 
+    // Write a page redirecting to the entry at each of its aliases. Aliases may not take the place
+    // of other pages.
     {
-        let asset_dir = path.join("assets");
-        if asset_dir.exists() {
-            out.copy_dir(&asset_dir, ".")?;
+        let mut pages: HashSet<&Path> = rendered.iter().map(|entry| entry.meta.out_file.as_path()).collect();
+        if render_tags {
+            pages.extend(tags.values().map(|tag| tag.out_file.as_path()));
+        }
+        pages.extend(template_pages.iter().map(PathBuf::as_path));
+
+        for entry in &rendered {
+            for alias in &entry.front_matter.aliases {
+                if !pages.insert(alias) {
+                    anyhow::bail!(
+                        "Alias {} of entry {} conflicts with another page",
+                        alias.display(),
+                        entry.meta.canonical_name
+                    );
+                }
+                out.update_file(&mut alias::render(&entry.canonical_url).as_bytes(), alias)?;
+            }
+        }
+    }
+
+    if ctx.sitemap() {
+        // Pages are received in the order they finish rendering
+        template_pages.sort();
+
+        let mut urls = Vec::with_capacity(rendered.len() + template_pages.len());
+        for entry in &rendered {
+            urls.push(sitemap::Url {
+                location: ctx.path_to_absolute_url(&entry.meta.out_file)?,
+                last_modified: entry.meta.date.map(|date| (date, entry.meta.time)),
+            });
+        }
+        if render_tags {
+            for tag in tags.values() {
+                urls.push(sitemap::Url {
+                    location: ctx.path_to_absolute_url(&tag.out_file)?,
+                    last_modified: None,
+                });
+            }
+        }
+        for page in &template_pages {
+            urls.push(sitemap::Url {
+                location: ctx.path_to_absolute_url(page)?,
+                last_modified: None,
+            });
         }
+
+        out.update_file(&mut sitemap::render(&urls, ctx.utc_offset()).as_bytes(), "sitemap.xml")?;
+    }
+
+    if let Some(rules) = ctx.robots_rules() {
+        let sitemap_url = if ctx.sitemap() {
+            Some(ctx.path_to_absolute_url("sitemap.xml")?)
+        } else {
+            None
+        };
+        out.update_file(
+            &mut robots::render(rules, sitemap_url.as_deref()).as_bytes(),
+            "robots.txt",
+        )?;
     }
 
     // For every directory in ./cat, concatenate all files
     {
-        let path = path.join("cat");
+        let path = path.join(ctx.cat_dir());
         if path.exists() {
             for dir in walkdir::WalkDir::new(&path).min_depth(1).follow_links(true) {
                 let dir = dir?;
@@ -428,126 +938,397 @@ fn build(ctx: &Ctx, path: &Path, renderer: &render::Renderer) -> anyhow::Result<
         }
     }
 
+    precompress::precompress(&out, ctx.precompress())?;
+
+    out.remove_stale()?;
+
+    entry_cache.retain(&rendered.iter().map(|entry| entry.meta.file_path.as_path()).collect());
+
+    report.end_phase("write");
+    report.entries_failed = failed_entries;
+    log::info!("{report}");
+
+    if failed_entries > 0 {
+        if ctx.build_kind().is_production() {
+            anyhow::bail!("{failed_entries} entries failed to build");
+        }
+        log::warn!("{failed_entries} entries failed to build and were skipped");
+    }
+
     Ok(())
 }
 
 enum FsChange {
     Template,
+    /// Only `sprokkel.toml` changed.
+    Config,
     Other,
     None,
 }
 
-fn main() -> anyhow::Result<()> {
+/// What needs to be done when the site configuration changes while watching.
+#[derive(Debug, PartialEq)]
+enum ConfigChange {
+    None,
+    /// Build again, without reloading templates.
+    Rebuild,
+    ReloadTemplates,
+}
+
+/// Compare the site configuration `old` to `new` field by field. The template renderer keeps the
+/// configuration it was built with, so changes to fields used while rendering templates require
+/// reloading templates. All fields are destructured, such that new fields must be handled here.
+fn site_config_change(old: &config::SiteConfig, new: &config::SiteConfig) -> ConfigChange {
+    let config::SiteConfig {
+        base_url,
+        base_url_develop,
+        site,
+        links,
+        highlight,
+        markup,
+        images,
+        assets,
+        build,
+        math,
+        security,
+        feeds,
+        sitemap,
+        entry_order,
+        words_per_minute,
+        timezone,
+        dirs,
+        // The output directory is fixed while watching, and the selected environment's base URL
+        // is already copied into the base URLs
+        out_dir: _,
+        env: _,
+        selected_env,
+        extra,
+    } = new;
+
+    if *base_url != old.base_url
+        || *base_url_develop != old.base_url_develop
+        || *selected_env != old.selected_env
+        || *site != old.site
+        || *extra != old.extra
+        || *links != old.links
+        || *highlight != old.highlight
+        || *math != old.math
+        || *security != old.security
+        || markup.raw_formats != old.markup.raw_formats
+        || markup.heading_anchors != old.markup.heading_anchors
+        || assets.fingerprint != old.assets.fingerprint
+        || dirs.templates != old.dirs.templates
     {
-        use simplelog as s;
-        s::TermLogger::init(
-            s::LevelFilter::Debug,
-            s::Config::default(),
-            s::TerminalMode::Mixed,
-            s::ColorChoice::Auto,
-        )
-        .unwrap();
+        ConfigChange::ReloadTemplates
+    } else if *markup != old.markup
+        || *images != old.images
+        || *assets != old.assets
+        || *build != old.build
+        || *feeds != old.feeds
+        || *sitemap != old.sitemap
+        || *entry_order != old.entry_order
+        || *words_per_minute != old.words_per_minute
+        || *timezone != old.timezone
+        || *dirs != old.dirs
+    {
+        ConfigChange::Rebuild
+    } else {
+        ConfigChange::None
     }
+}
 
-    let args = cli::Args::parse();
+/// Read the configuration of the site at `path`, for the environment named `env` if given.
+fn read_site_config(path: &Path, env: Option<&str>) -> anyhow::Result<config::SiteConfig> {
+    let site_config_path = path.join("sprokkel.toml");
+    let mut site_config: config::SiteConfig =
+        toml::from_str(&std::fs::read_to_string(&site_config_path)?).with_context(|| "Parsing sprokkel.toml")?;
+    if let Some(env) = env {
+        site_config.select_env(env)?;
+    }
+    if let Some(pagination_path) = &site_config.links.pagination_path {
+        if !pagination_path.contains("{page}") {
+            anyhow::bail!("links.pagination-path must contain \"{{page}}\": {pagination_path}");
+        }
+    }
+    Ok(site_config)
+}
 
-    #[allow(irrefutable_let_patterns)]
-    let args = if let cli::Commands::Build(args) = args.command {
-        args
-    } else {
-        unimplemented!();
-    };
-    let build_kind = if args.develop {
-        cli::BuildKind::Develop
-    } else {
-        cli::BuildKind::Production
+/// The directory to write the site at `path` to. A directory given on the command line takes
+/// precedence over the configured directory, which is relative to the site.
+fn out_dir(cli_out_dir: Option<&Path>, path: &Path, site_config: &config::SiteConfig) -> PathBuf {
+    match (cli_out_dir, &site_config.out_dir) {
+        (Some(out_dir), _) => out_dir.to_owned(),
+        (None, Some(out_dir)) => path.join(out_dir),
+        (None, None) => PathBuf::from(DEFAULT_OUT_DIR),
+    }
+}
+
+/// Build the site every time a file in `path` changes, writing it to `out_dir`. `on_build` is
+/// called after every build. This never returns.
+fn watch(
+    build_kind: cli::BuildKind,
+    env: Option<&str>,
+    include_drafts: bool,
+    path: &Path,
+    out_dir: &Path,
+    mut on_build: impl FnMut(),
+) -> anyhow::Result<()> {
+    let cvar_pair = Arc::new((Mutex::new(FsChange::Template), Condvar::new()));
+    let cvar_pair2 = cvar_pair.clone();
+    let path_prefix = path.canonicalize()?;
+    // Changes to the output directory are ignored, as the output directory may be inside the
+    // site directory and every build would otherwise trigger another build
+    let out_prefix = {
+        std::fs::create_dir_all(out_dir)?;
+        out_dir.canonicalize()?
     };
+    // The templates directory is configurable, and is set once the site configuration is read
+    let templates_prefix = Arc::new(Mutex::new(path_prefix.join("templates")));
+    let templates_prefix2 = templates_prefix.clone();
+    let config_path = path_prefix.join("sprokkel.toml");
+    let mut debouncer = new_debouncer(Duration::from_millis(250), None, move |ev: DebounceEventResult| {
+        let (lock, cvar) = &*cvar_pair2;
+        let mut change_ = FsChange::Other;
+
+        if let Ok(evs) = ev {
+            let paths: Vec<_> = evs
+                .into_iter()
+                .flat_map(|e| e.event.paths.into_iter())
+                .filter(|path| !path.starts_with(&out_prefix))
+                .collect();
+            if paths.is_empty() {
+                return;
+            }
+
+            let templates_prefix = templates_prefix2.lock().unwrap();
+            if paths.iter().any(|path| path.starts_with(&*templates_prefix)) {
+                change_ = FsChange::Template;
+            } else if paths.iter().all(|path| *path == config_path) {
+                change_ = FsChange::Config;
+            }
+        }
 
-    let site_config_path = args.path.join("sprokkel.toml");
-
-    if args.watch {
-        let cvar_pair = Arc::new((Mutex::new(FsChange::Template), Condvar::new()));
-        let cvar_pair2 = cvar_pair.clone();
-        let path_prefix = args.path.canonicalize()?;
-        let mut debouncer = new_debouncer(Duration::from_millis(250), None, move |ev: DebounceEventResult| {
-            let (lock, cvar) = &*cvar_pair2;
-            let mut change_ = FsChange::Other;
-
-            if let Ok(evs) = ev {
-                if evs.into_iter().flat_map(|e| e.event.paths.into_iter()).any(|path| {
-                    path.strip_prefix(&path_prefix)
-                        .map(|path| path.starts_with("templates"))
-                        .unwrap_or(false)
-                }) {
-                    change_ = FsChange::Template;
+        // Changes that have not been built yet are merged, such that a change to only
+        // sprokkel.toml does not hide an earlier change to other files
+        let mut change = lock.lock().unwrap();
+        *change = match (&*change, change_) {
+            (FsChange::Template, _) | (_, FsChange::Template) => FsChange::Template,
+            (FsChange::Other, _) | (_, FsChange::Other) => FsChange::Other,
+            (_, change_) => change_,
+        };
+        cvar.notify_one();
+    })
+    .unwrap();
+
+    debouncer.watcher().watch(path, RecursiveMode::Recursive).unwrap();
+    debouncer.cache().add_root(path, RecursiveMode::Recursive);
+
+    let mut site_config: Option<config::SiteConfig> = None;
+    let mut renderer: Option<render::Renderer> = None;
+    // Processed images are kept between builds, such that only changed images are processed again
+    let image_cache = images::ImageCache::new();
+    // Likewise, only changed entries are rendered again
+    let entry_cache = entry_cache::EntryCache::new();
+
+    let mut build_watch = move |change: FsChange| -> anyhow::Result<()> {
+        let config_change = {
+            let site_config_ = read_site_config(path, env)?;
+
+            let config_change = match &site_config {
+                Some(site_config) => site_config_change(site_config, &site_config_),
+                None => ConfigChange::ReloadTemplates,
+            };
+            if let Some(site_config) = &site_config {
+                if site_config != &site_config_ {
+                    log::info!("Reloaded sprokkel.toml.");
+                }
+                if site_config.out_dir != site_config_.out_dir {
+                    log::warn!("out-dir changed, restart to write the site to the new output directory");
                 }
             }
+            site_config = Some(site_config_);
+            config_change
+        };
+        let site_config = site_config.as_ref().unwrap();
 
-            let mut change = lock.lock().unwrap();
-            *change = change_;
-            cvar.notify_one();
-        })
-        .unwrap();
+        if config_change == ConfigChange::None && matches!(change, FsChange::Config) {
+            log::info!("No changes to sprokkel.toml that affect the site, not building");
+            return Ok(());
+        }
 
-        debouncer.watcher().watch(&args.path, RecursiveMode::Recursive).unwrap();
-        debouncer.cache().add_root(&args.path, RecursiveMode::Recursive);
+        let ctx = Ctx::from_site_config(build_kind, site_config);
 
-        let mut site_config: Option<config::SiteConfig> = None;
-        let mut renderer: Option<render::Renderer> = None;
+        if config_change == ConfigChange::ReloadTemplates || matches!(change, FsChange::Template) {
+            log::info!("Reloading templates…");
+            *templates_prefix.lock().unwrap() = path_prefix.join(ctx.templates_dir());
+            renderer = Some(render::Renderer::build(&ctx, path.join(ctx.templates_dir()))?);
+            entry_cache.clear();
+        } else if config_change == ConfigChange::Rebuild {
+            // Cached entries do not track the configuration they were rendered with
+            entry_cache.clear();
+        }
 
-        let mut build_watch = move |change: FsChange| -> anyhow::Result<()> {
-            let config_changed = {
-                let site_config_: config::SiteConfig = toml::from_str(&std::fs::read_to_string(&site_config_path)?)
-                    .with_context(|| "Parsing sprokkel.toml")?;
+        log::info!("Building…");
+        let instant = std::time::Instant::now();
+        if let Err(err) = build(
+            &ctx,
+            path,
+            out_dir,
+            renderer.as_ref().unwrap(),
+            &image_cache,
+            &entry_cache,
+            include_drafts,
+        ) {
+            log::error!("{:?}", err);
+        }
+        log::info!(
+            "======== Building took {}ms ========",
+            std::time::Instant::now().duration_since(instant).as_millis()
+        );
+        on_build();
 
-                let config_changed = Some(&site_config_) != site_config.as_ref();
-                if config_changed && site_config.is_some() {
-                    log::info!("Reloaded sprokkel.toml.");
-                }
-                site_config = Some(site_config_);
-                config_changed
-            };
-            let site_config = site_config.as_ref().unwrap();
+        Ok(())
+    };
+
+    loop {
+        let (lock, cvar) = &*cvar_pair;
+        let mut change = lock.lock().unwrap();
+        while matches!(&*change, &FsChange::None) {
+            log::info!("Waiting for file change…");
+            change = cvar.wait(change).unwrap();
+        }
+        let change_ = std::mem::replace(&mut *change, FsChange::None);
+        drop(change);
+
+        if let Err(err) = build_watch(change_) {
+            log::error!("{:?}", err);
+        }
+    }
+}
+
+fn main() -> anyhow::Result<()> {
+    {
+        use simplelog as s;
+        s::TermLogger::init(
+            s::LevelFilter::Debug,
+            s::Config::default(),
+            s::TerminalMode::Mixed,
+            s::ColorChoice::Auto,
+        )
+        .unwrap();
+    }
 
-            let ctx = Ctx::from_site_config(build_kind, site_config);
+    let args = cli::Args::parse();
 
-            if config_changed || matches!(change, FsChange::Template) {
-                log::info!("Reloading templates…");
-                renderer = Some(render::Renderer::build(&ctx, args.path.join("templates"))?);
+    match args.command {
+        cli::Commands::Build(args) => {
+            let build_kind = if args.develop {
+                cli::BuildKind::Develop
+            } else {
+                cli::BuildKind::Production
+            };
+            let site_config = read_site_config(&args.path, args.env.as_deref())?;
+            let out_dir = out_dir(args.out_dir.as_deref(), &args.path, &site_config);
+            if args.clean {
+                out::clean(&out_dir)?;
             }
 
-            log::info!("Building…");
-            let instant = std::time::Instant::now();
-            if let Err(err) = build(&ctx, &args.path, renderer.as_ref().unwrap()) {
-                log::error!("{:?}", err);
+            if args.watch {
+                watch(
+                    build_kind,
+                    args.env.as_deref(),
+                    args.drafts,
+                    &args.path,
+                    &out_dir,
+                    || {},
+                )?;
+            } else {
+                let ctx = Ctx::from_site_config(build_kind, &site_config);
+                let renderer = render::Renderer::build(&ctx, args.path.join(ctx.templates_dir()))?;
+                build(
+                    &ctx,
+                    &args.path,
+                    &out_dir,
+                    &renderer,
+                    &images::ImageCache::new(),
+                    &entry_cache::EntryCache::new(),
+                    args.drafts,
+                )?;
             }
-            log::info!(
-                "======== Building took {}ms ========",
-                std::time::Instant::now().duration_since(instant).as_millis()
+        }
+        cli::Commands::Serve(args) => {
+            let build_kind = if args.develop {
+                cli::BuildKind::Develop
+            } else {
+                cli::BuildKind::Production
+            };
+            // The output directory is fixed while serving, changing it requires a restart
+            let out_dir = out_dir(
+                args.out_dir.as_deref(),
+                &args.path,
+                &read_site_config(&args.path, args.env.as_deref())?,
             );
+            if args.clean {
+                out::clean(&out_dir)?;
+            }
 
-            Ok(())
-        };
+            // Only develop builds get live reloading, production output is served as-is
+            let live_reload = build_kind.is_develop().then(|| Arc::new(serve::LiveReload::new()));
+
+            let port = args.port;
+            {
+                let live_reload = live_reload.clone();
+                let out_dir = out_dir.clone();
+                std::thread::spawn(move || {
+                    if let Err(err) = serve::serve(port, out_dir, live_reload) {
+                        log::error!("{:?}", err);
+                        std::process::exit(1);
+                    }
+                });
+            }
 
-        loop {
-            let (lock, cvar) = &*cvar_pair;
-            let mut change = lock.lock().unwrap();
-            while matches!(&*change, &FsChange::None) {
-                log::info!("Waiting for file change…");
-                change = cvar.wait(change).unwrap();
+            watch(
+                build_kind,
+                args.env.as_deref(),
+                args.drafts,
+                &args.path,
+                &out_dir,
+                move || {
+                    if let Some(live_reload) = &live_reload {
+                        live_reload.rebuilt();
+                    }
+                },
+            )?;
+        }
+        cli::Commands::Init(args) => {
+            init::init(&args.path, args.force)?;
+        }
+        cli::Commands::Clean(args) => {
+            let out_dir = match args.out_dir {
+                Some(out_dir) => out_dir,
+                None => out_dir(None, &args.path, &read_site_config(&args.path, None)?),
+            };
+            if out::clean(&out_dir)? {
+                log::info!("Removed {}", out_dir.display());
+            } else {
+                log::info!("Nothing to remove, {} does not exist", out_dir.display());
+            }
+        }
+        cli::Commands::Check(args) => {
+            let out_dir = match args.out_dir {
+                Some(out_dir) => out_dir,
+                None => out_dir(None, &args.path, &read_site_config(&args.path, None)?),
+            };
+            if !out_dir.is_dir() {
+                anyhow::bail!("{} does not exist, build the site first", out_dir.display());
             }
-            let change_ = std::mem::replace(&mut *change, FsChange::None);
-            drop(change);
 
-            if let Err(err) = build_watch(change_) {
-                log::error!("{:?}", err);
+            let dead = check::check_external_links(&out_dir, args.concurrency, Duration::from_secs(args.timeout))?;
+            if dead > 0 {
+                anyhow::bail!("{dead} external links are dead");
             }
+            log::info!("No dead external links");
         }
-    } else {
-        let site_config: config::SiteConfig = toml::from_str(&std::fs::read_to_string(&site_config_path)?)?;
-        let ctx = Ctx::from_site_config(build_kind, &site_config);
-        let renderer = render::Renderer::build(&ctx, args.path.join("templates"))?;
-        build(&ctx, &args.path, &renderer)?;
     }
 
     Ok(())
@@ -579,4 +1360,198 @@ mod test {
         group.remove_idx(0);
         assert_eq!(group.range, 0..1);
     }
+
+    #[test]
+    fn collect_djot_and_markdown_entries() {
+        use super::{collect_entry_groups, Ctx};
+        use crate::types::EntrySourceKind;
+
+        let ctx = Ctx::for_test("");
+
+        let path = std::env::temp_dir().join(format!("sprokkel-collect-entries-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir_all(path.join("pages").join("contact")).unwrap();
+        std::fs::create_dir_all(path.join("pages").join("docs").join("guide")).unwrap();
+        std::fs::write(path.join("pages").join("about.md"), "# About\n").unwrap();
+        std::fs::write(path.join("pages").join("contact").join("index.md"), "# Contact\n").unwrap();
+        std::fs::write(
+            path.join("pages").join("docs").join("guide").join("setup.dj"),
+            "# Setup\n",
+        )
+        .unwrap();
+        std::fs::write(path.join("pages").join("projects.dj"), "# Projects\n").unwrap();
+        std::fs::write(path.join("pages").join("notes.txt"), "not an entry").unwrap();
+
+        let result = collect_entry_groups(&ctx, &path);
+        std::fs::remove_dir_all(&path).unwrap();
+        let (groups, entries) = result.unwrap();
+
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].name, "pages");
+        assert_eq!(groups[0].range, 0..4);
+
+        let names: Vec<_> = entries.iter().map(|entry| entry.canonical_name.as_str()).collect();
+        assert_eq!(
+            names,
+            [
+                "pages/about",
+                "pages/contact",
+                "pages/docs/guide/setup",
+                "pages/projects"
+            ]
+        );
+        assert!(matches!(entries[0].source_kind, EntrySourceKind::CommonMark));
+        assert!(matches!(entries[1].source_kind, EntrySourceKind::CommonMark));
+        assert!(matches!(entries[2].source_kind, EntrySourceKind::Djot));
+        assert!(matches!(entries[3].source_kind, EntrySourceKind::Djot));
+    }
+
+    #[test]
+    fn link_siblings() {
+        use super::{link_siblings, Ctx, Group};
+        use crate::{front_matter, types};
+
+        let ctx = Ctx::for_test("");
+
+        let metas = ["posts/c.dj", "posts/b.dj", "posts/a.dj", "pages/about.dj"]
+            .map(|path| types::EntryMeta::for_test(&ctx, path));
+        let front_matter = front_matter::parse_front_matter("").unwrap().0;
+        let mut entries: Vec<_> = metas
+            .iter()
+            .map(|meta| types::Entry::for_test(meta, &front_matter))
+            .collect();
+
+        link_siblings(
+            &[
+                Group::new("posts".to_owned(), 0..3),
+                Group::new("pages".to_owned(), 3..4),
+            ],
+            &mut entries,
+        );
+
+        let links: Vec<_> = entries
+            .iter()
+            .map(|entry| (entry.prev_permalink.as_deref(), entry.next_permalink.as_deref()))
+            .collect();
+        assert_eq!(
+            links,
+            [
+                (None, Some("http://localhost:8080/b")),
+                (Some("http://localhost:8080/c"), Some("http://localhost:8080/a")),
+                (Some("http://localhost:8080/b"), None),
+                (None, None),
+            ]
+        );
+    }
+
+    #[test]
+    fn paginated_out_file() {
+        use super::paginated_out_file;
+        use std::path::{Path, PathBuf};
+
+        let template_path = Path::new("blog").join("index.html");
+        let default = "{name}-{page}{extension}";
+        assert_eq!(
+            paginated_out_file(default, &template_path, "index", ".html", 0),
+            template_path
+        );
+        assert_eq!(
+            paginated_out_file(default, &template_path, "index", ".html", 1),
+            PathBuf::from("blog").join("index-2.html")
+        );
+        assert_eq!(
+            paginated_out_file("page/{page}/{name}{extension}", &template_path, "index", ".html", 2),
+            PathBuf::from("blog").join("page").join("3").join("index.html")
+        );
+        assert_eq!(
+            paginated_out_file(default, Path::new("feed"), "feed", "", 1),
+            PathBuf::from("feed-2")
+        );
+    }
+
+    #[test]
+    fn sorted_entry_indices() {
+        use super::{permute, sorted_entry_indices, Ctx, Group};
+        use crate::types::EntryMeta;
+
+        let entries_with_order = |order: &str| {
+            let ctx = Ctx::for_test(&format!("entry-order = \"{order}\""));
+
+            let entries: Vec<_> = [
+                "posts/2024-04-16_b.dj",
+                "posts/2024-05-01_a.dj",
+                "posts/undated.dj",
+                "posts/2024-04-16_a.dj",
+                "pages/b.dj",
+                "pages/a.dj",
+            ]
+            .into_iter()
+            .map(|path| EntryMeta::for_test(&ctx, path))
+            .collect();
+            let groups = [
+                Group::new("posts".to_owned(), 0..4),
+                Group::new("pages".to_owned(), 4..6),
+            ];
+
+            let indices = sorted_entry_indices(&ctx, &groups, &entries);
+            permute(entries, &indices)
+                .into_iter()
+                .map(|entry| entry.canonical_name)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            entries_with_order("descending"),
+            [
+                "posts/2024-05-01_a",
+                "posts/2024-04-16_a",
+                "posts/2024-04-16_b",
+                "posts/undated",
+                "pages/a",
+                "pages/b"
+            ]
+        );
+        assert_eq!(
+            entries_with_order("ascending"),
+            [
+                "posts/2024-04-16_a",
+                "posts/2024-04-16_b",
+                "posts/2024-05-01_a",
+                "posts/undated",
+                "pages/a",
+                "pages/b"
+            ]
+        );
+    }
+
+    #[test]
+    fn site_config_change() {
+        use super::{site_config_change, ConfigChange};
+        use crate::config::SiteConfig;
+
+        let config = |extra: &str| SiteConfig::for_test(extra).unwrap();
+        let old = config("");
+
+        assert_eq!(site_config_change(&old, &config("")), ConfigChange::None);
+        assert_eq!(
+            site_config_change(&old, &config("out-dir = \"public\"")),
+            ConfigChange::None
+        );
+        assert_eq!(
+            site_config_change(&old, &config("words-per-minute = 300")),
+            ConfigChange::Rebuild
+        );
+        assert_eq!(
+            site_config_change(&old, &config("[images]\nwidths = [640]")),
+            ConfigChange::Rebuild
+        );
+        assert_eq!(
+            site_config_change(&old, &config("[site]\ntitle = \"Blog\"")),
+            ConfigChange::ReloadTemplates
+        );
+        assert_eq!(
+            site_config_change(&old, &config("[markup]\nraw-formats = [\"svg\"]")),
+            ConfigChange::ReloadTemplates
+        );
+    }
 }
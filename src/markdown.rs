@@ -1,4 +1,4 @@
-use std::borrow::Cow;
+use std::{borrow::Cow, collections::HashSet};
 
 use pulldown_cmark::{CodeBlockKind, Event, HeadingLevel, Tag, TagEnd};
 
@@ -6,6 +6,7 @@ use crate::ir_markup::{
     Alignment as IrAlignment, Attributes, Container as IrContainer, ContainerEnd as IrContainerEnd, Event as IrEvent,
     HeadingLevel as IrHeadingLevel, ListKind as IrListKind, OrderedListNumbering as IrOrderedListNumbering,
 };
+use crate::utils;
 
 /// Iterates from an Event::Start to a matching Event::End. The resulting iterator yields all
 /// events in between the start and end, skipping over the start and end itself. If the next item
@@ -53,6 +54,26 @@ fn render_to_raw_string<'a>(events: impl Iterator<Item = Event<'a>>) -> Cow<'a,
     string
 }
 
+/// Generate a heading ID from the heading's text in the same way as Djot: punctuation other than
+/// `-` and `_` is removed, and runs of whitespace are replaced by a single `-`.
+fn heading_id(text: &str) -> String {
+    let mut id = String::with_capacity(text.len());
+    for word in text.split_whitespace() {
+        let word: String = word
+            .chars()
+            .filter(|&char| !char.is_ascii_punctuation() || matches!(char, '-' | '_'))
+            .collect();
+        if word.is_empty() {
+            continue;
+        }
+        if !id.is_empty() {
+            id.push('-');
+        }
+        id.push_str(&word);
+    }
+    id
+}
+
 impl From<pulldown_cmark::HeadingLevel> for IrHeadingLevel {
     fn from(value: pulldown_cmark::HeadingLevel) -> Self {
         match value {
@@ -107,6 +128,8 @@ struct Context {
     section_stack: Vec<HeadingLevel>,
 
     heading_has_id: HeadingHasIdBitfield,
+    /// IDs of the headings so far, to keep IDs unique
+    heading_ids: HashSet<String>,
 }
 
 impl Context {
@@ -117,12 +140,14 @@ impl Context {
             table_head_or_body: TableHeadOrBody::Head,
             section_stack: Vec::with_capacity(6),
             heading_has_id: HeadingHasIdBitfield(0),
+            heading_ids: HashSet::new(),
         }
     }
 }
 
-fn markdown_to_ir<'s>(mut markdown: impl Iterator<Item = Event<'s>>) -> impl Iterator<Item = IrEvent<'s>> {
+fn markdown_to_ir<'s>(markdown: impl Iterator<Item = Event<'s>>) -> impl Iterator<Item = IrEvent<'s>> {
     let mut ctx = Context::new();
+    let mut markdown = utils::PutBack::new(markdown);
 
     // to be replaced by `gen`-blocks
     genawaiter::rc::Gen::new(|co| async move {
@@ -149,18 +174,8 @@ fn markdown_to_ir<'s>(mut markdown: impl Iterator<Item = Event<'s>>) -> impl Ite
                     attrs,
                 }) => {
                     let mut attributes = Attributes::new();
-                    if !classes.is_empty() {
-                        let class = {
-                            let class_len = classes.iter().map(|class| class.len()).sum::<usize>() + classes.len() - 1;
-                            let mut class = String::with_capacity(class_len);
-                            for class_ in classes {
-                                class.push_str(&class_);
-                            }
-                            class
-                        };
-                        if !class.is_empty() {
-                            attributes.insert("class", class);
-                        }
+                    for class in classes {
+                        attributes.insert("class", Cow::from(class));
                     }
 
                     while let Some(&open_section_level) = ctx.section_stack.last() {
@@ -176,9 +191,44 @@ fn markdown_to_ir<'s>(mut markdown: impl Iterator<Item = Event<'s>>) -> impl Ite
                         }
                     }
                     ctx.section_stack.push(level);
-                    ctx.heading_has_id.set(level, id.is_some());
 
-                    let id = id.map(Cow::from);
+                    // Headings without an explicit ID get an ID generated from their text, made
+                    // unique by a numeric suffix
+                    let id: Cow<'_, str> = match id {
+                        Some(id) => {
+                            ctx.heading_ids.insert(id.to_string());
+                            id.into()
+                        }
+                        None => {
+                            let heading: Vec<_> = iter_container_from_inside(markdown.by_ref()).collect();
+                            let mut text = String::new();
+                            for event in &heading {
+                                if let Event::Text(value) | Event::Code(value) = event {
+                                    text.push_str(value);
+                                }
+                            }
+                            markdown.put_back(
+                                heading
+                                    .into_iter()
+                                    .chain([Event::End(TagEnd::Heading(level))])
+                                    .collect(),
+                            );
+
+                            let base = match heading_id(&text) {
+                                id if id.is_empty() => "s".to_owned(),
+                                id => id,
+                            };
+                            let mut id = base.clone();
+                            let mut suffix = 1;
+                            while !ctx.heading_ids.insert(id.clone()) {
+                                id = format!("{base}-{suffix}");
+                                suffix += 1;
+                            }
+                            id.into()
+                        }
+                    };
+                    let id = Some(id);
+                    ctx.heading_has_id.set(level, id.is_some());
                     co.yield_(IrEvent::Start {
                         container: IrContainer::Section { id: id.clone() },
                         attributes: Attributes::new(),
@@ -189,7 +239,7 @@ fn markdown_to_ir<'s>(mut markdown: impl Iterator<Item = Event<'s>>) -> impl Ite
                             level: level.into(),
                             id,
                         },
-                        attributes: Attributes::new(),
+                        attributes,
                     })
                     .await;
                 }
@@ -244,10 +294,17 @@ fn markdown_to_ir<'s>(mut markdown: impl Iterator<Item = Event<'s>>) -> impl Ite
                             },
                             tight: true,
                         },
-                        None => IrContainer::List {
-                            kind: IrListKind::Unordered,
-                            tight: true,
-                        },
+                        None => {
+                            // Like in Djot, a list is a task list if its first item is a task
+                            let lookahead: Vec<_> = markdown.by_ref().take(2).collect();
+                            let task = matches!(lookahead.get(1), Some(Event::TaskListMarker(_)));
+                            markdown.put_back(lookahead);
+
+                            IrContainer::List {
+                                kind: if task { IrListKind::Task } else { IrListKind::Unordered },
+                                tight: true,
+                            }
+                        }
                     };
                     co.yield_(IrEvent::Start {
                         container,
@@ -285,7 +342,10 @@ fn markdown_to_ir<'s>(mut markdown: impl Iterator<Item = Event<'s>>) -> impl Ite
                     .await
                 }
 
-                Event::Start(Tag::MetadataBlock(_)) => unreachable!(),
+                // Front matter is parsed before the markup, metadata blocks are not content
+                Event::Start(Tag::MetadataBlock(_)) => {
+                    iter_container_from_inside(markdown.by_ref()).for_each(drop);
+                }
                 Event::End(TagEnd::MetadataBlock(_)) => unreachable!(),
 
                 Event::Start(Tag::Table(alignment)) => {
@@ -550,7 +610,9 @@ pub fn parse<'s>(input: &'s str) -> impl Iterator<Item = IrEvent<'s>> {
         | Options::ENABLE_FOOTNOTES
         | Options::ENABLE_TASKLISTS
         | Options::ENABLE_SMART_PUNCTUATION
-        | Options::ENABLE_HEADING_ATTRIBUTES;
+        | Options::ENABLE_HEADING_ATTRIBUTES
+        | Options::ENABLE_YAML_STYLE_METADATA_BLOCKS
+        | Options::ENABLE_PLUSES_DELIMITED_METADATA_BLOCKS;
     let p = Parser::new_ext(input, opts);
     markdown_to_ir(p)
 }
@@ -565,10 +627,67 @@ mod test {
     fn test(input: &str, output: &str) {
         let mut s = String::new();
         let ir = parse(input);
-        ir_markup::push_html(&mut s, ir, &HashMap::new()).unwrap();
+        ir_markup::push_html(&mut s, ir, &HashMap::new(), &Default::default()).unwrap();
         assert_eq!(s, output);
     }
 
+    #[test]
+    fn link_destination_escaped() {
+        test(
+            "[a](<foo\"bar.html>)\n",
+            "<p>\n<a href=\"foo&quot;bar.html\">a</a></p>\n",
+        );
+    }
+
+    #[test]
+    fn metadata_block() {
+        test("---\ntitle: foo\n---\nText\n", "<p>Text</p>\n");
+        test("+++\ntitle = \"foo\"\n+++\nText\n", "<p>Text</p>\n");
+    }
+
+    #[test]
+    fn heading_ids() {
+        use super::heading_id;
+
+        assert_eq!(heading_id("A heading"), "A-heading");
+        assert_eq!(heading_id("  What's  `new`? - v1.0_rc "), "Whats-new---v10_rc");
+        assert_eq!(heading_id("?!"), "");
+
+        test(
+            "# Foo\n\n## Bar { #Foo-1 }\n\n## Foo\n\n## ?\n",
+            "<section id=\"Foo\">\n<h1><a href=\"#Foo\">Foo</a></h1>\n\
+             <section id=\"Foo-1\">\n<h2><a href=\"#Foo-1\">Bar</a></h2>\n</section>\n\
+             <section id=\"Foo-2\">\n<h2><a href=\"#Foo-2\">Foo</a></h2>\n</section>\n\
+             <section id=\"s\">\n<h2><a href=\"#s\">?</a></h2>\n</section>\n</section>\n",
+        );
+    }
+
+    #[test]
+    fn heading_classes() {
+        test(
+            "## Heading { #heading .a .b }\n",
+            "<section id=\"heading\">\n<h2 class=\"a b\"><a href=\"#heading\">Heading</a></h2>\n</section>\n",
+        );
+    }
+
+    #[test]
+    fn highlight_python() {
+        let mut s = String::new();
+        let ir = parse("```python\ndef foo():\n    return 1\n```\n");
+        ir_markup::push_html(&mut s, ir, &HashMap::new(), &Default::default()).unwrap();
+        assert!(s.contains(r#"<span class="keyword">"#));
+
+        let mut s = String::new();
+        let ir = parse("```python\nprint('hi')\n```\n");
+        let options = ir_markup::HtmlOptions {
+            highlight_class_prefix: "hl-".to_owned(),
+            ..Default::default()
+        };
+        ir_markup::push_html(&mut s, ir, &HashMap::new(), &options).unwrap();
+        assert!(s.contains(r#"<span class="hl-string">"#));
+        assert!(!s.contains(r#"<span class="string">"#));
+    }
+
     #[test]
     fn bitfield() {
         use pulldown_cmark::HeadingLevel as L;
@@ -1,28 +1,102 @@
+use std::collections::HashSet;
 use std::fs::{self, File};
 use std::io::{self, Read};
 use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+use anyhow::Context;
+
+use crate::{config, utils};
+
+/// Recursively remove everything at `path`. Returns whether anything was removed.
+pub fn clean(path: impl AsRef<Path>) -> anyhow::Result<bool> {
+    match fs::remove_dir_all(path) {
+        Ok(()) => Ok(true),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(false),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// The name of the file in a `cat` directory configuring how its files are concatenated.
+pub const CAT_MANIFEST: &str = "cat.toml";
+
+/// Order the file names `names`, sorted by name, as configured by `manifest`, leaving out files
+/// that are not included or are excluded.
+fn cat_order(manifest: &config::Cat, mut names: Vec<String>) -> Vec<String> {
+    if let Some(exclude) = &manifest.exclude {
+        names.retain(|name| !exclude.iter().any(|pattern| utils::glob_match(pattern, name)));
+    }
+
+    let Some(include) = &manifest.include else {
+        return names;
+    };
+    let mut ordered = Vec::with_capacity(names.len());
+    for pattern in include {
+        let (matching, rest): (Vec<_>, Vec<_>) = names.into_iter().partition(|name| utils::glob_match(pattern, name));
+        ordered.extend(matching);
+        names = rest;
+    }
+    ordered
+}
+
+/// The length and modification time of an output file, to tell whether it changed since.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FileStamp {
+    len: u64,
+    modified: SystemTime,
+}
+
+impl FileStamp {
+    fn of(path: &Path) -> io::Result<Self> {
+        let metadata = fs::metadata(path)?;
+        Ok(FileStamp {
+            len: metadata.len(),
+            modified: metadata.modified()?,
+        })
+    }
+}
 
 pub struct Out {
     prefix: PathBuf,
+    /// The files produced by this build.
+    produced: Mutex<HashSet<PathBuf>>,
 }
 
 impl Out {
-    /// Create a new out writer at `path`.
-    ///
-    /// # Warning
-    ///
-    /// This recursively removes everything currently at `path`.
+    /// Create a new out writer at `path`. Files already at `path` are only overwritten if their
+    /// content changes, such that unchanged files keep their modification time. Files that are not
+    /// produced again are removed by [`Out::remove_stale`].
     pub fn at(path: impl AsRef<Path>) -> anyhow::Result<Self> {
         let path = path.as_ref();
 
-        let _ = std::fs::remove_dir_all(path);
         fs::create_dir_all(path)?;
 
         Ok(Out {
             prefix: path.canonicalize()?,
+            produced: Mutex::new(HashSet::new()),
         })
     }
 
+    /// Remove all files that were not produced since this out writer was created, as well as
+    /// directories that become empty.
+    pub fn remove_stale(&self) -> anyhow::Result<()> {
+        let produced = self.produced.lock().unwrap();
+
+        for entry in walkdir::WalkDir::new(&self.prefix).min_depth(1).contents_first(true) {
+            let entry = entry?;
+            if entry.file_type().is_dir() {
+                // only succeeds if the directory is empty
+                let _ = fs::remove_dir(entry.path());
+            } else if !produced.contains(entry.path()) {
+                log::debug!("Removing stale output file {}", entry.path().display());
+                fs::remove_file(entry.path())?;
+            }
+        }
+
+        Ok(())
+    }
+
     /// Copy a file by copying all bytes from `in_file` to `out_file`. This does not copy file
     /// attributes. Recursively creates `out_path` if it or its directory does not yet exist.
     pub fn copy_file(&self, in_file: impl AsRef<Path>, out_file: impl AsRef<Path>) -> anyhow::Result<()> {
@@ -33,55 +107,100 @@ impl Out {
     }
 
     /// Write a file with the given `content` to `out_file`. Recursively creates `out_path` if it or
-    /// its directory does not yet exist.
-    pub fn update_file(&self, content: &mut impl Read, out_file: impl AsRef<Path>) -> anyhow::Result<()> {
+    /// its directory does not yet exist. The file is not written if it already has that content,
+    /// such that its modification time is kept. Returns whether the file was written.
+    pub fn update_file(&self, content: &mut impl Read, out_file: impl AsRef<Path>) -> anyhow::Result<bool> {
         let out_file = self.prefix.join(out_file);
 
-        if let Some(parent) = out_file.parent() {
-            fs::create_dir_all(parent)?;
+        let mut buf = vec![];
+        content.read_to_end(&mut buf)?;
+
+        let write = fs::read(&out_file).ok().as_deref() != Some(&buf[..]);
+        if write {
+            if let Some(parent) = out_file.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::write(&out_file, buf)?;
         }
 
-        let mut fw = File::create(out_file)?;
-        io::copy(content, &mut fw)?;
+        self.produced.lock().unwrap().insert(out_file);
+        Ok(write)
+    }
 
-        Ok(())
+    /// Mark the existing file at `out_file` as produced by this build, without writing it.
+    pub fn keep_file(&self, out_file: impl AsRef<Path>) {
+        self.produced.lock().unwrap().insert(self.prefix.join(out_file));
     }
 
-    /// Concatenate all files in `in_dir` to `out_file`. Recursively creates `out_path` if it or
-    /// its directory does not yet exist. `out_file` is only created if there are files in
-    /// `in_dir`.
-    pub fn cat_dir(&self, in_dir: impl AsRef<Path>, out_file: impl AsRef<Path>) -> anyhow::Result<()> {
+    /// Get the stamp of the output file at `out_file`, to later keep it with
+    /// [`Out::keep_file_if_unchanged`].
+    pub fn stamp(&self, out_file: impl AsRef<Path>) -> anyhow::Result<FileStamp> {
         let out_file = self.prefix.join(out_file);
+        FileStamp::of(&out_file).with_context(|| format!("could not read metadata of {}", out_file.display()))
+    }
 
-        if let Some(parent) = out_file.parent() {
-            fs::create_dir_all(parent)?;
+    /// Mark the file at `out_file` as produced by this build, without writing it, if it still
+    /// exists and did not change since it was stamped. Returns whether the file was kept.
+    pub fn keep_file_if_unchanged(&self, out_file: impl AsRef<Path>, stamp: FileStamp) -> bool {
+        let out_file = self.prefix.join(out_file);
+        let unchanged = FileStamp::of(&out_file).is_ok_and(|current| current == stamp);
+        if unchanged {
+            self.produced.lock().unwrap().insert(out_file);
         }
+        unchanged
+    }
 
-        let mut fw = None;
+    /// The absolute paths of the files produced so far.
+    pub fn produced(&self) -> Vec<PathBuf> {
+        self.produced.lock().unwrap().iter().cloned().collect()
+    }
 
+    /// Concatenate the files in `in_dir` to `out_file`, in the order configured by the
+    /// [`CAT_MANIFEST`] in `in_dir`, or by name if there is none. Recursively creates `out_path` if
+    /// it or its directory does not yet exist. `out_file` is only created if there are files in
+    /// `in_dir`.
+    pub fn cat_dir(&self, in_dir: impl AsRef<Path>, out_file: impl AsRef<Path>) -> anyhow::Result<()> {
+        let in_dir = in_dir.as_ref();
+        let manifest_path = in_dir.join(CAT_MANIFEST);
+        let manifest: config::Cat = if manifest_path.is_file() {
+            toml::from_str(&fs::read_to_string(&manifest_path)?)
+                .with_context(|| format!("Parsing {}", manifest_path.display()))?
+        } else {
+            config::Cat::default()
+        };
+
+        let mut names = vec![];
         for entry in walkdir::WalkDir::new(in_dir)
             .follow_links(true)
             .sort_by_file_name()
             .max_depth(1)
         {
             let entry = entry?;
-            if !entry.file_type().is_file() {
+            if !entry.file_type().is_file() || entry.file_name() == CAT_MANIFEST {
                 continue;
             }
+            names.push(entry.file_name().to_string_lossy().into_owned());
+        }
 
-            // Create the file handle only if there are actually files inside this directory to
-            // concatenate.
-            if fw.is_none() {
-                fw = Some(File::create(&out_file)?);
-            }
-
-            let fw = fw.as_mut().unwrap();
+        // Only produce the file if there are actually files inside this directory to
+        // concatenate.
+        let names = cat_order(&manifest, names);
+        if names.is_empty() {
+            return Ok(());
+        }
 
-            let path = entry.path();
-            let mut fr = File::open(path)?;
-            io::copy(&mut fr, fw)?;
+        let mut content = Vec::new();
+        for name in names {
+            let mut fr = File::open(in_dir.join(name))?;
+            fr.read_to_end(&mut content)?;
         }
 
+        let out_file = out_file.as_ref();
+        match &manifest.extension {
+            Some(extension) => self.update_file(&mut &*content, out_file.with_extension(extension))?,
+            None => self.update_file(&mut &*content, out_file)?,
+        };
+
         Ok(())
     }
 
@@ -112,6 +231,88 @@ impl Out {
             fs::create_dir_all(parent)?;
         }
 
-        Ok(File::create(out_file)?)
+        let file = File::create(&out_file)?;
+        self.produced.lock().unwrap().insert(out_file);
+        Ok(file)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    #[test]
+    fn update_and_remove_stale() {
+        use super::Out;
+        use std::fs;
+
+        let root = std::env::temp_dir().join(format!("sprokkel-out-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+
+        let out = Out::at(&root).unwrap();
+        out.update_file(&mut &b"kept"[..], "kept.html").unwrap();
+        out.update_file(&mut &b"stale"[..], "stale/index.html").unwrap();
+        out.remove_stale().unwrap();
+        assert!(root.join("stale").join("index.html").exists());
+        let modified = fs::metadata(root.join("kept.html")).unwrap().modified().unwrap();
+
+        let out = Out::at(&root).unwrap();
+        assert!(!out.update_file(&mut &b"kept"[..], "kept.html").unwrap());
+        assert!(out.update_file(&mut &b"new"[..], "new.html").unwrap());
+        out.remove_stale().unwrap();
+        assert_eq!(
+            fs::metadata(root.join("kept.html")).unwrap().modified().unwrap(),
+            modified
+        );
+        assert_eq!(fs::read(root.join("new.html")).unwrap(), b"new");
+        assert!(!root.join("stale").exists());
+
+        let stamp = out.stamp("new.html").unwrap();
+        let out = Out::at(&root).unwrap();
+        assert!(out.keep_file_if_unchanged("new.html", stamp));
+        assert!(!out.keep_file_if_unchanged("kept.html", stamp));
+        out.remove_stale().unwrap();
+        assert!(root.join("new.html").exists());
+        assert!(!root.join("kept.html").exists());
+        out.update_file(&mut &b"changed"[..], "new.html").unwrap();
+        assert!(!out.keep_file_if_unchanged("new.html", stamp));
+
+        assert!(super::clean(&root).unwrap());
+        assert!(!root.exists());
+        assert!(!super::clean(&root).unwrap());
+    }
+
+    #[test]
+    fn cat_order() {
+        use super::cat_order;
+        use crate::config::Cat;
+
+        let names = || {
+            ["00_fonts.css", "01_highlight.css", "02_main.css", "print.css"]
+                .map(str::to_owned)
+                .to_vec()
+        };
+
+        assert_eq!(cat_order(&Cat::default(), names()), names());
+        assert_eq!(
+            cat_order(
+                &Cat {
+                    include: Some(vec!["02_*".to_owned(), "*.css".to_owned()]),
+                    exclude: Some(vec!["print.css".to_owned()]),
+                    extension: None,
+                },
+                names()
+            ),
+            ["02_main.css", "00_fonts.css", "01_highlight.css"]
+        );
+        assert_eq!(
+            cat_order(
+                &Cat {
+                    include: Some(vec!["*_main.css".to_owned()]),
+                    exclude: None,
+                    extension: None,
+                },
+                names()
+            ),
+            ["02_main.css"]
+        );
     }
 }
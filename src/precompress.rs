@@ -0,0 +1,134 @@
+//! Write compressed copies of output files, for servers serving precompressed files.
+
+use rayon::prelude::*;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use crate::{config::Compression, out::Out};
+
+/// Files smaller than this are not worth compressing.
+const MIN_SIZE: u64 = 1024;
+
+/// Extensions of the text-like files to compress.
+const EXTENSIONS: &[&str] = &["html", "css", "js", "xml", "svg", "json", "txt"];
+
+/// Write a compressed copy of every text-like file produced so far, next to the file, e.g.,
+/// `index.html.gz` for gzip. Copies that are newer than their file are kept as-is.
+pub fn precompress(out: &Out, encodings: &[Compression]) -> anyhow::Result<()> {
+    if encodings.is_empty() {
+        return Ok(());
+    }
+
+    out.produced()
+        .par_iter()
+        .filter(|path| {
+            path.extension()
+                .and_then(|extension| extension.to_str())
+                .is_some_and(|extension| EXTENSIONS.contains(&extension))
+        })
+        .try_for_each(|path| -> anyhow::Result<()> {
+            let metadata = std::fs::metadata(path)?;
+            if metadata.len() < MIN_SIZE {
+                return Ok(());
+            }
+
+            let mut content = None;
+            for &encoding in encodings {
+                let compressed_path = compressed_path(path, encoding);
+                if is_up_to_date(&compressed_path, &metadata) {
+                    out.keep_file(&compressed_path);
+                    continue;
+                }
+
+                let content = match &mut content {
+                    Some(content) => content,
+                    None => content.insert(std::fs::read(path)?),
+                };
+                out.update_file(&mut &*compress(content, encoding)?, &compressed_path)?;
+            }
+
+            Ok(())
+        })
+}
+
+fn compressed_path(path: &Path, encoding: Compression) -> PathBuf {
+    let extension = match encoding {
+        Compression::Gzip => "gz",
+        Compression::Brotli => "br",
+    };
+    let mut path = path.as_os_str().to_owned();
+    path.push(".");
+    path.push(extension);
+    path.into()
+}
+
+/// Whether the compressed copy at `compressed_path` was written after its file was last modified.
+fn is_up_to_date(compressed_path: &Path, metadata: &std::fs::Metadata) -> bool {
+    let compressed_modified = std::fs::metadata(compressed_path).and_then(|metadata| metadata.modified());
+    match (compressed_modified, metadata.modified()) {
+        (Ok(compressed_modified), Ok(modified)) => compressed_modified >= modified,
+        _ => false,
+    }
+}
+
+fn compress(content: &[u8], encoding: Compression) -> anyhow::Result<Vec<u8>> {
+    match encoding {
+        Compression::Gzip => {
+            let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::best());
+            encoder.write_all(content)?;
+            Ok(encoder.finish()?)
+        }
+        Compression::Brotli => {
+            let mut compressed = Vec::new();
+            {
+                let mut encoder = brotli::CompressorWriter::new(&mut compressed, 4096, 11, 22);
+                encoder.write_all(content)?;
+            }
+            Ok(compressed)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    #[test]
+    fn compressed_path() {
+        use super::compressed_path;
+        use crate::config::Compression;
+        use std::path::Path;
+
+        assert_eq!(
+            compressed_path(Path::new("out/index.html"), Compression::Gzip),
+            Path::new("out/index.html.gz")
+        );
+        assert_eq!(
+            compressed_path(Path::new("out/css/style.css"), Compression::Brotli),
+            Path::new("out/css/style.css.br")
+        );
+    }
+
+    #[test]
+    fn compress() {
+        use super::compress;
+        use crate::config::Compression;
+        use std::io::Read;
+
+        let content = "<p>Hello, world!</p>\n".repeat(100);
+
+        let compressed = compress(content.as_bytes(), Compression::Gzip).unwrap();
+        assert!(compressed.len() < content.len());
+        let mut decompressed = String::new();
+        flate2::read::GzDecoder::new(&*compressed)
+            .read_to_string(&mut decompressed)
+            .unwrap();
+        assert_eq!(decompressed, content);
+
+        let compressed = compress(content.as_bytes(), Compression::Brotli).unwrap();
+        assert!(compressed.len() < content.len());
+        let mut decompressed = String::new();
+        brotli::Decompressor::new(&*compressed, 4096)
+            .read_to_string(&mut decompressed)
+            .unwrap();
+        assert_eq!(decompressed, content);
+    }
+}
@@ -1,11 +1,17 @@
 use minijinja::{context, value::ViaDeserialize, Environment};
 use std::{
     cell::RefCell,
-    collections::HashMap,
+    collections::{BTreeMap, HashMap},
+    fmt::Write,
     path::{Path, PathBuf},
+    sync::{Arc, RwLock},
 };
 
+use crate::djot;
+use crate::fingerprint::AssetManifest;
+use crate::ir_markup;
 use crate::types;
+use crate::utils;
 use crate::Ctx;
 
 thread_local! {
@@ -19,6 +25,8 @@ thread_local! {
 }
 
 struct Paginator {
+    /// The sequence paginated over, if pagination was set up with a sequence rather than a number.
+    items: Option<minijinja::Value>,
     item_count: usize,
     per_page: u32,
     current_page: u32,
@@ -27,18 +35,19 @@ struct Paginator {
 }
 
 impl Paginator {
-    pub fn new(ctx: &Ctx, per_page: u32, item_count: usize) -> Self {
+    pub fn new(per_page: u32, items: Option<minijinja::Value>, item_count: usize) -> Self {
         PAGE_PERMALINK.with_borrow(|page_permalink| {
             let page_permalink = page_permalink.as_ref().unwrap();
 
-            let last_page = (item_count / per_page as usize) as u32;
+            let last_page = (item_count.div_ceil(per_page as usize).max(1) - 1) as u32;
             Paginator {
+                items,
                 item_count,
                 per_page,
                 current_page: 0,
                 last_page,
                 page_permalinks: (0u32..=last_page)
-                    .map(|page| ctx.path_to_absolute_url((*page_permalink)(page)).unwrap())
+                    .map(|page| (*page_permalink)(page))
                     .collect(),
             }
         })
@@ -51,49 +60,75 @@ impl Paginator {
     }
 }
 
+/// The pages in a window of `size` pages around `current_page`, e.g., for numbered pagers. The
+/// window is shifted to stay within the pages, and is smaller when there are fewer pages.
+fn page_window(current_page: u32, last_page: u32, size: u32) -> Vec<u32> {
+    let page_count = last_page + 1;
+    let size = size.min(page_count);
+    let start = current_page
+        .saturating_sub(size.saturating_sub(1) / 2)
+        .min(page_count - size);
+    (start..start + size).collect()
+}
+
 fn pagination_reset() {
     PAGINATOR.with_borrow_mut(|pagination| pagination.take());
 }
 
 /// Template pagination function that can be added to a `minijinja::Environment`. This takes the
 /// total number of items (either as a sequence or as a number) and the number of items to be
-/// displayed per page.
+/// displayed per page. When given a sequence, the items of the current page are returned as well.
 ///
 /// The first call per template render sets up the paginator. Subsequent calls ignore the arguments
 /// and return the same result.
-fn gen_paginate(
-    ctx: Ctx,
-) -> impl Fn(&minijinja::Value, u32) -> Result<minijinja::Value, minijinja::Error> {
+fn gen_paginate() -> impl Fn(&minijinja::Value, u32) -> Result<minijinja::Value, minijinja::Error> {
     move |items, per_page| {
         PAGINATOR.with_borrow_mut(|paginator| {
             if paginator.is_none() {
-                let item_count = if items.is_number() {
-                    usize::try_from(items.clone()).ok()
+                let (items, item_count) = if items.is_number() {
+                    (None, usize::try_from(items.clone()).ok())
                 } else if let Some(seq) = items.as_seq() {
-                    Some(seq.item_count())
+                    (Some(items.clone()), Some(seq.item_count()))
                 } else {
-                    None
+                    (None, None)
                 };
                 let item_count = item_count.ok_or(minijinja::Error::new(
                     minijinja::ErrorKind::InvalidOperation,
                     "`items` argument is neither a number nor a sequence",
                 ))?;
-
-                *paginator = Some(Paginator::new(&ctx, per_page, item_count));
+                if per_page == 0 {
+                    return Err(minijinja::Error::new(
+                        minijinja::ErrorKind::InvalidOperation,
+                        "`per_page` argument must be at least 1",
+                    ));
+                }
+
+                *paginator = Some(Paginator::new(per_page, items, item_count));
             }
             let paginator = paginator.as_ref().unwrap();
 
             let page_start = paginator.current_page as usize * paginator.per_page as usize;
             let page_end = (page_start + paginator.per_page as usize).min(paginator.item_count);
 
+            let items = paginator.items.as_ref().and_then(|items| items.as_seq()).map(|seq| {
+                (page_start..page_end)
+                    .filter_map(|idx| seq.get_item(idx))
+                    .collect::<Vec<_>>()
+            });
+
             let is_first_page = paginator.current_page == 0;
             let is_last_page = paginator.current_page == paginator.last_page;
 
+            let (current_page, last_page) = (paginator.current_page, paginator.last_page);
+            let page_window =
+                minijinja::Value::from_function(move |size: u32| page_window(current_page, last_page, size));
+
             Ok(minijinja::context! {
                 item_count => paginator.item_count,
                 page_count => paginator.last_page + 1,
                 current_page => paginator.current_page,
                 indices => (page_start..page_end).collect::<Vec<_>>(),
+                items => items,
                 is_first_page => is_first_page,
                 is_last_page => is_last_page,
                 previous => if is_first_page {
@@ -106,6 +141,9 @@ fn gen_paginate(
                 } else {
                     Some(paginator.page_permalinks[(paginator.current_page+1) as usize].clone())
                 },
+                first_page => paginator.page_permalinks[0],
+                last_page => paginator.page_permalinks[paginator.last_page as usize],
+                page_window => page_window,
                 page_permalinks => paginator.page_permalinks,
             })
         })
@@ -115,27 +153,165 @@ fn gen_paginate(
 pub struct Renderer {
     ctx: Ctx,
     t: Environment<'static>,
+    /// Fingerprinted names of static assets, looked up by the `fingerprint` filter.
+    asset_manifest: Arc<RwLock<AssetManifest>>,
 }
 
 #[derive(Clone, Copy, serde::Serialize)]
 struct TemplateCtx<'ctx> {
     base_url: &'ctx str,
+    env: &'ctx str,
+    site: &'ctx types::SiteMeta,
     entries: &'ctx HashMap<&'ctx str, &'ctx [types::Entry<'ctx>]>,
+    /// The entries of all groups, sorted like the entries within a group.
+    all_entries: &'ctx [&'ctx types::Entry<'ctx>],
+    tags: &'ctx BTreeMap<&'ctx str, types::Tag<'ctx>>,
 }
 
-#[derive(Clone, Copy)]
 pub struct RenderCtx<'ctx> {
     renderer: &'ctx Renderer,
+    get_entry: minijinja::Value,
     ctx: TemplateCtx<'ctx>,
 }
 
-/// Minijinja filter to add leading zeros to a numeric value.
+/// Template function to look up an entry by its canonical name, e.g.,
+/// `get_entry("posts/2024-04-26-foo")`. Unknown names result in `undefined`.
+///
+/// Functions added to the environment must be `'static`, so the entries are serialized up front.
+fn gen_get_entry(entries_by_name: &HashMap<&str, &types::Entry<'_>>) -> minijinja::Value {
+    let entries: HashMap<String, minijinja::Value> = entries_by_name
+        .iter()
+        .map(|(name, entry)| (name.to_string(), minijinja::Value::from_serialize(entry)))
+        .collect();
+
+    minijinja::Value::from_function(move |name: &str| entries.get(name).cloned().unwrap_or(minijinja::Value::UNDEFINED))
+}
+
+/// Minijinja filter to add leading zeros to a numeric value, up to the given number of digits. The
+/// sign of negative values is not counted as a digit.
 fn leading_zeros(val: minijinja::Value, leading_zeros: u8) -> Result<String, minijinja::Error> {
     let num: i64 = val.try_into()?;
-    let length = num.ilog10() + 1;
+    let sign = if num < 0 { "-" } else { "" };
+    let num = num.unsigned_abs();
+    let length = num.checked_ilog10().unwrap_or(0) + 1;
     let zeros = "0".repeat(leading_zeros.saturating_sub(length as u8) as usize);
 
-    Ok(format!("{zeros}{num}"))
+    Ok(format!("{sign}{zeros}{num}"))
+}
+
+/// Minijinja filter to group a sequence by an attribute, e.g., `entries | group_by("date.year")`.
+/// Returns a `{ key, items }` map per distinct value of the attribute, in order of first
+/// appearance. The order of the items is preserved. Items without the attribute are grouped under
+/// `none`.
+fn group_by(items: Vec<minijinja::Value>, attr: &str) -> Result<Vec<minijinja::Value>, minijinja::Error> {
+    let mut groups: Vec<(minijinja::Value, Vec<minijinja::Value>)> = vec![];
+    for item in items {
+        let key = attr.split('.').try_fold(item.clone(), |value, name| {
+            if value.is_undefined() || value.is_none() {
+                Ok(minijinja::Value::from(()))
+            } else {
+                value.get_attr(name)
+            }
+        })?;
+        let key = if key.is_undefined() {
+            minijinja::Value::from(())
+        } else {
+            key
+        };
+
+        match groups.iter_mut().find(|(group_key, _)| *group_key == key) {
+            Some((_, group)) => group.push(item),
+            None => groups.push((key, vec![item])),
+        }
+    }
+
+    Ok(groups.into_iter().map(|(key, items)| context! { key, items }).collect())
+}
+
+const MONTHS: [&str; 12] = [
+    "January",
+    "February",
+    "March",
+    "April",
+    "May",
+    "June",
+    "July",
+    "August",
+    "September",
+    "October",
+    "November",
+    "December",
+];
+const WEEKDAYS: [&str; 7] = [
+    "Sunday",
+    "Monday",
+    "Tuesday",
+    "Wednesday",
+    "Thursday",
+    "Friday",
+    "Saturday",
+];
+
+/// Minijinja filter to format a date or time with a strftime-like format string. Supported are
+/// `%Y`, `%m`, `%d`, `%e` (day without padding), `%B` and `%b` (full and abbreviated month name),
+/// `%A` and `%a` (full and abbreviated weekday name), `%H`, `%M`, `%S` and `%%`.
+fn date(value: minijinja::Value, format: &str) -> Result<String, minijinja::Error> {
+    let field = |name: &str| value.get_attr(name).ok().and_then(|field| u16::try_from(field).ok());
+    let date = match (field("year"), field("month"), field("day")) {
+        (Some(year), Some(month), Some(day)) => Some(types::Date::new(year, month as u8, day as u8)),
+        _ => None,
+    };
+    let time = match (field("hour"), field("minute"), field("second")) {
+        (Some(hour), Some(minute), Some(second)) => Some(types::Time::new(hour as u8, minute as u8, second as u8)),
+        _ => None,
+    };
+    let error = |message: String| minijinja::Error::new(minijinja::ErrorKind::InvalidOperation, message);
+    if date.is_none() && time.is_none() {
+        return Err(error(format!("`date` filter expects a date or a time, got: {value}")));
+    }
+
+    let month_name = |date: types::Date| {
+        MONTHS
+            .get((date.month as usize).wrapping_sub(1))
+            .ok_or_else(|| error(format!("invalid month: {}", date.month)))
+    };
+
+    let mut formatted = String::with_capacity(format.len());
+    let mut chars = format.chars();
+    while let Some(char) = chars.next() {
+        if char != '%' {
+            formatted.push(char);
+            continue;
+        }
+
+        let written = match (chars.next(), date, time) {
+            (Some('Y'), Some(date), _) => write!(formatted, "{:04}", date.year),
+            (Some('m'), Some(date), _) => write!(formatted, "{:02}", date.month),
+            (Some('d'), Some(date), _) => write!(formatted, "{:02}", date.day),
+            (Some('e'), Some(date), _) => write!(formatted, "{}", date.day),
+            (Some('B'), Some(date), _) => write!(formatted, "{}", month_name(date)?),
+            (Some('b'), Some(date), _) => write!(formatted, "{}", &month_name(date)?[..3]),
+            (Some('A'), Some(date), _) => {
+                write!(formatted, "{}", WEEKDAYS[date.weekday() as usize])
+            }
+            (Some('a'), Some(date), _) => {
+                write!(formatted, "{}", &WEEKDAYS[date.weekday() as usize][..3])
+            }
+            (Some('H'), _, Some(time)) => write!(formatted, "{:02}", time.hour),
+            (Some('M'), _, Some(time)) => write!(formatted, "{:02}", time.minute),
+            (Some('S'), _, Some(time)) => write!(formatted, "{:02}", time.second),
+            (Some('%'), _, _) => write!(formatted, "%"),
+            (Some(specifier), _, _) => {
+                return Err(error(format!(
+                    "`date` filter format `%{specifier}` is unsupported or not applicable to {value}"
+                )))
+            }
+            (None, _, _) => return Err(error("`date` filter format ends with `%`".to_owned())),
+        };
+        written.expect("infallible");
+    }
+
+    Ok(formatted)
 }
 
 impl Renderer {
@@ -143,15 +319,18 @@ impl Renderer {
         let mut t = Environment::new();
         t.set_undefined_behavior(minijinja::UndefinedBehavior::Chainable);
 
-        t.add_function("paginate", gen_paginate(ctx.clone()));
+        t.add_function("paginate", gen_paginate());
         t.add_filter("leading_zeros", leading_zeros);
+        t.add_filter("date", date);
+        t.add_filter("slugify", utils::slugify);
+        t.add_filter("group_by", group_by);
 
         {
             let ctx = ctx.clone();
             t.add_filter(
                 "path_to_url",
                 move |path: ViaDeserialize<PathBuf>| -> Result<String, minijinja::Error> {
-                    let url = ctx.path_to_absolute_url(&*path).map_err(|_| {
+                    let url = ctx.path_to_url(&*path).map_err(|_| {
                         minijinja::Error::new(
                             minijinja::ErrorKind::InvalidOperation,
                             format!("path is not valid unicode: {:?}", &path.0),
@@ -163,48 +342,166 @@ impl Renderer {
             );
         }
 
+        {
+            let ctx = ctx.clone();
+            t.add_filter("absolute_url", move |path: &str| -> Result<String, minijinja::Error> {
+                if utils::url_host(path).is_some() {
+                    return Ok(path.to_owned());
+                }
+                ctx.path_to_absolute_url(path.trim_start_matches('/')).map_err(|err| {
+                    minijinja::Error::new(
+                        minijinja::ErrorKind::InvalidOperation,
+                        format!("cannot make an absolute URL of {path:?}: {err}"),
+                    )
+                })
+            });
+        }
+
+        {
+            let ctx = ctx.clone();
+            t.add_filter("prepend_base_path", move |path: &str| ctx.prepend_base_path(path));
+        }
+
+        {
+            let ctx = ctx.clone();
+            t.add_filter(
+                "render_djot",
+                move |markup: &str| -> Result<minijinja::Value, minijinja::Error> {
+                    let mut html = String::new();
+                    let events = djot::parse_with_raw_formats(markup, ctx.raw_formats());
+                    ir_markup::push_html(&mut html, events, &HashMap::new(), ctx.html_options()).map_err(|err| {
+                        minijinja::Error::new(
+                            minijinja::ErrorKind::InvalidOperation,
+                            format!("cannot render Djot: {err}"),
+                        )
+                    })?;
+                    Ok(minijinja::Value::from_safe_string(html))
+                },
+            );
+        }
+
+        let asset_manifest = Arc::new(RwLock::new(AssetManifest::new()));
+        {
+            let ctx = ctx.clone();
+            let asset_manifest = asset_manifest.clone();
+            t.add_filter("fingerprint", move |path: &str| -> Result<String, minijinja::Error> {
+                if !ctx.fingerprint_assets() {
+                    return Ok(path.to_owned());
+                }
+                let asset_path = path.trim_start_matches('/');
+                match asset_manifest.read().unwrap().get(asset_path) {
+                    Some(fingerprinted) => Ok(format!("{}{fingerprinted}", &path[..path.len() - asset_path.len()])),
+                    None => Err(minijinja::Error::new(
+                        minijinja::ErrorKind::InvalidOperation,
+                        format!("cannot fingerprint {path:?}: no such asset"),
+                    )),
+                }
+            });
+        }
+
         t.set_loader(minijinja::path_loader(template_path));
 
         Ok(Renderer {
             ctx: ctx.clone(),
             t,
+            asset_manifest,
         })
     }
 
+    /// Set the fingerprinted names of static assets. Returns whether they changed.
+    pub fn set_asset_manifest(&self, manifest: AssetManifest) -> bool {
+        let mut asset_manifest = self.asset_manifest.write().unwrap();
+        let changed = *asset_manifest != manifest;
+        *asset_manifest = manifest;
+        changed
+    }
+
     pub fn render_context<'ctx>(
         &'ctx self,
         entries: &'ctx HashMap<&'ctx str, &'ctx [types::Entry<'ctx>]>,
+        all_entries: &'ctx [&'ctx types::Entry<'ctx>],
+        entries_by_name: &HashMap<&str, &types::Entry<'_>>,
+        tags: &'ctx BTreeMap<&'ctx str, types::Tag<'ctx>>,
     ) -> RenderCtx<'ctx> {
         RenderCtx {
             renderer: self,
+            get_entry: gen_get_entry(entries_by_name),
             ctx: TemplateCtx {
                 base_url: &self.ctx.base_url(),
+                env: self.ctx.env(),
+                site: self.ctx.site(),
                 entries,
+                all_entries,
+                tags,
             },
         }
     }
 }
 
 impl RenderCtx<'_> {
+    /// The context available to all templates.
+    fn template_context(&self) -> minijinja::Value {
+        context! {
+            get_entry => self.get_entry.clone(),
+            ..minijinja::Value::from_serialize(self.ctx),
+        }
+    }
+
     pub fn entry(
         &self,
         write: impl std::io::Write,
         entry: &types::Entry,
         referring_entries: &[&types::Entry<'_>],
+        linked_entries: &[&types::Entry<'_>],
     ) -> anyhow::Result<()> {
-        let template = self
-            .renderer
-            .t
-            .get_template(&format!("_{}.html", entry.meta.group))
-            .or_else(|_| self.renderer.t.get_template("_entry.html"))?;
+        let template = match &entry.front_matter.template {
+            Some(name) => self.renderer.t.get_template(&format!("_{name}.html")).map_err(|err| {
+                anyhow::anyhow!(
+                    "entry {} uses template `_{name}.html`: {err}",
+                    entry.meta.canonical_name
+                )
+            })?,
+            None => self
+                .renderer
+                .t
+                .get_template(&format!("_{}.html", entry.meta.group))
+                .or_else(|_| self.renderer.t.get_template("_entry.html"))?,
+        };
 
         let ctx = context! {
                 referring_entries => referring_entries,
+                linked_entries => linked_entries,
                 entry => entry,
         };
         template.render_to_write(
             context! {
-                ..ctx, ..minijinja::Value::from_serialize(&self.ctx)
+                ..ctx, ..self.template_context()
+            },
+            write,
+        )?;
+
+        Ok(())
+    }
+
+    /// Whether a `_tag.html` template exists to render tag pages with.
+    pub fn has_tag_template(&self) -> anyhow::Result<bool> {
+        match self.renderer.t.get_template("_tag.html") {
+            Ok(_) => Ok(true),
+            Err(err) if err.kind() == minijinja::ErrorKind::TemplateNotFound => Ok(false),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    pub fn tag(&self, write: impl std::io::Write, name: &str, tag: &types::Tag<'_>) -> anyhow::Result<()> {
+        let template = self.renderer.t.get_template("_tag.html")?;
+
+        let ctx = context! {
+                tag_name => name,
+                tag => tag,
+        };
+        template.render_to_write(
+            context! {
+                ..ctx, ..self.template_context()
             },
             write,
         )?;
@@ -226,9 +523,7 @@ impl RenderCtx<'_> {
                 template_path.as_ref()
             ))?)?;
 
-        let content = template.render(context! {
-            ..minijinja::Value::from_serialize(self.ctx),
-        });
+        let content = template.render(self.template_context());
 
         let mut paginate = PAGINATOR.with_borrow_mut(|paginator| {
             paginator
@@ -249,9 +544,7 @@ impl RenderCtx<'_> {
 
             while paginate {
                 page += 1;
-                let content = template.render(context! {
-                    ..minijinja::Value::from_serialize(self.ctx),
-                });
+                let content = template.render(self.template_context());
                 pages.push(
                     content
                         .map(|content| (page, content))
@@ -271,3 +564,175 @@ impl RenderCtx<'_> {
         Ok(pages.into_iter())
     }
 }
+
+#[cfg(test)]
+mod test {
+    #[test]
+    fn absolute_url() {
+        use super::Renderer;
+        use crate::Ctx;
+
+        let ctx = Ctx::for_test("base-url = \"https://example.com\"");
+        let renderer = Renderer::build(&ctx, "templates").unwrap();
+        let render = |template: &str| renderer.t.render_str(template, ()).unwrap();
+
+        assert_eq!(
+            render("{{ '/css/style.css' | absolute_url }}"),
+            "https://example.com/css/style.css"
+        );
+        assert_eq!(
+            render("{{ 'css/style.css' | absolute_url }}"),
+            "https://example.com/css/style.css"
+        );
+        assert_eq!(
+            render("{{ '/blog/index.html' | absolute_url }}"),
+            "https://example.com/blog"
+        );
+        assert_eq!(render("{{ '/' | absolute_url }}"), "https://example.com");
+        assert_eq!(
+            render("{{ 'https://example.org/a' | absolute_url }}"),
+            "https://example.org/a"
+        );
+    }
+
+    #[test]
+    fn paginate() {
+        use super::{gen_paginate, pagination_reset, PAGE_PERMALINK};
+        use minijinja::Value;
+
+        PAGE_PERMALINK.with_borrow_mut(|page_permalink| {
+            *page_permalink = Some(Box::new(|page| format!("/page-{page}")));
+        });
+        let paginate = gen_paginate();
+
+        let page = paginate(&Value::from(vec!["a", "b", "c"]), 2).unwrap();
+        assert_eq!(page.get_attr("items").unwrap(), Value::from(vec!["a", "b"]));
+        assert_eq!(page.get_attr("indices").unwrap(), Value::from(vec![0, 1]));
+        assert_eq!(page.get_attr("first_page").unwrap(), Value::from("/page-0"));
+        assert_eq!(page.get_attr("last_page").unwrap(), Value::from("/page-1"));
+        pagination_reset();
+
+        let page = paginate(&Value::from(3), 2).unwrap();
+        assert!(page.get_attr("items").unwrap().is_none());
+        assert_eq!(page.get_attr("item_count").unwrap(), Value::from(3));
+        pagination_reset();
+
+        let page = paginate(&Value::from(4), 2).unwrap();
+        assert_eq!(page.get_attr("page_count").unwrap(), Value::from(2));
+        assert_eq!(page.get_attr("last_page").unwrap(), Value::from("/page-1"));
+        pagination_reset();
+
+        let page = paginate(&Value::from(0), 2).unwrap();
+        assert_eq!(page.get_attr("page_count").unwrap(), Value::from(1));
+        pagination_reset();
+
+        assert!(paginate(&Value::from(3), 0).is_err());
+        pagination_reset();
+    }
+
+    #[test]
+    fn page_window() {
+        use super::page_window;
+
+        assert_eq!(page_window(0, 9, 5), [0, 1, 2, 3, 4]);
+        assert_eq!(page_window(5, 9, 5), [3, 4, 5, 6, 7]);
+        assert_eq!(page_window(5, 9, 4), [4, 5, 6, 7]);
+        assert_eq!(page_window(9, 9, 5), [5, 6, 7, 8, 9]);
+        assert_eq!(page_window(1, 2, 5), [0, 1, 2]);
+        assert_eq!(page_window(0, 0, 0), [] as [u32; 0]);
+    }
+
+    #[test]
+    fn render_djot() {
+        use super::Renderer;
+        use crate::Ctx;
+
+        let ctx = Ctx::for_test("base-url = \"https://example.com\"");
+        let renderer = Renderer::build(&ctx, "templates").unwrap();
+        let render = |template: &str, description: &str| {
+            renderer
+                .t
+                .render_str(template, minijinja::context! { description })
+                .unwrap()
+        };
+
+        let html = render(
+            "{{ description | render_djot }}",
+            "A [link](https://example.com) & _more_",
+        );
+        assert!(html.contains(r#"<a href="https://example.com">link</a>"#));
+        assert!(html.contains("&amp; \n<em>more</em>"));
+        assert!(render("{{ description }}", "_more_").contains("_more_"));
+    }
+
+    #[test]
+    fn leading_zeros() {
+        use super::leading_zeros;
+        use minijinja::Value;
+
+        assert_eq!(leading_zeros(Value::from(0), 2).unwrap(), "00");
+        assert_eq!(leading_zeros(Value::from(5), 2).unwrap(), "05");
+        assert_eq!(leading_zeros(Value::from(99), 2).unwrap(), "99");
+        assert_eq!(leading_zeros(Value::from(123), 2).unwrap(), "123");
+        assert_eq!(leading_zeros(Value::from(-5), 3).unwrap(), "-005");
+        assert_eq!(leading_zeros(Value::from(-42), 0).unwrap(), "-42");
+    }
+
+    #[test]
+    fn group_by() {
+        use super::group_by;
+        use minijinja::{context, Value};
+
+        let entry = |title: &str, year: Option<u16>| {
+            context! {
+                title,
+                date => year.map(|year| context! { year, month => 1, day => 1 }),
+            }
+        };
+        let entries = vec![
+            entry("c", Some(2024)),
+            entry("b", Some(2024)),
+            entry("a", Some(2023)),
+            entry("undated", None),
+        ];
+
+        let groups = group_by(entries.clone(), "date.year").unwrap();
+        assert_eq!(groups.len(), 3);
+        assert_eq!(groups[0].get_attr("key").unwrap(), Value::from(2024));
+        assert_eq!(groups[0].get_attr("items").unwrap(), Value::from(entries[..2].to_vec()));
+        assert_eq!(groups[1].get_attr("key").unwrap(), Value::from(2023));
+        assert_eq!(
+            groups[1].get_attr("items").unwrap(),
+            Value::from(vec![entries[2].clone()])
+        );
+        assert!(groups[2].get_attr("key").unwrap().is_none());
+
+        let groups = group_by(entries.clone(), "title").unwrap();
+        assert_eq!(groups.len(), 4);
+        assert_eq!(groups[3].get_attr("key").unwrap(), Value::from("undated"));
+    }
+
+    #[test]
+    fn date() {
+        use super::date;
+        use crate::types::{Date, Time};
+        use minijinja::Value;
+
+        let date_value = Value::from_serialize(Date::new(2024, 4, 26));
+        let time_value = Value::from_serialize(Time::new(9, 40, 2));
+
+        assert_eq!(date(date_value.clone(), "%B %e, %Y").unwrap(), "April 26, 2024");
+        assert_eq!(
+            date(date_value.clone(), "%a %d %b %Y (%m) 100%%").unwrap(),
+            "Fri 26 Apr 2024 (04) 100%"
+        );
+        assert_eq!(date(date_value.clone(), "%A").unwrap(), "Friday");
+        assert_eq!(date(time_value.clone(), "%H:%M:%S").unwrap(), "09:40:02");
+
+        assert!(date(date_value.clone(), "%H").is_err());
+        assert!(date(time_value, "%Y").is_err());
+        assert!(date(date_value, "%").is_err());
+        assert!(date(Value::from("2024-04-26"), "%Y").is_err());
+        assert!(date(Value::UNDEFINED, "%Y").is_err());
+    }
+}
@@ -0,0 +1,99 @@
+//! Summarize a build: what was built, and how long each phase of the build took.
+
+use std::fmt;
+use std::time::{Duration, Instant};
+
+/// Counts and phase timings of a build.
+pub struct BuildReport {
+    pub entries_rendered: usize,
+    pub entries_failed: usize,
+    pub images_processed: usize,
+    /// Images that were not processed again, as they did not change since the previous build.
+    pub images_cached: usize,
+    pub template_pages: usize,
+    pub feeds: usize,
+    phases: Vec<(&'static str, Duration)>,
+    phase_start: Instant,
+}
+
+impl BuildReport {
+    /// Start a report, and the timer of the first phase.
+    pub fn start() -> Self {
+        BuildReport {
+            entries_rendered: 0,
+            entries_failed: 0,
+            images_processed: 0,
+            images_cached: 0,
+            template_pages: 0,
+            feeds: 0,
+            phases: vec![],
+            phase_start: Instant::now(),
+        }
+    }
+
+    /// End the current phase, naming it `name`, and start the next.
+    pub fn end_phase(&mut self, name: &'static str) {
+        let now = Instant::now();
+        self.phases.push((name, now.duration_since(self.phase_start)));
+        self.phase_start = now;
+    }
+}
+
+impl fmt::Display for BuildReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let counts = [
+            ("entries rendered", self.entries_rendered),
+            ("entries failed", self.entries_failed),
+            ("images processed", self.images_processed),
+            ("images cached", self.images_cached),
+            ("template pages", self.template_pages),
+            ("feeds", self.feeds),
+        ];
+        let width = counts
+            .iter()
+            .map(|(name, _)| name.len())
+            .chain(self.phases.iter().map(|(name, _)| name.len()))
+            .max()
+            .unwrap_or(0);
+
+        write!(f, "Build summary:")?;
+        for (name, count) in counts {
+            write!(f, "\n  {name:<width$}  {count:>6}")?;
+        }
+        for (name, duration) in &self.phases {
+            write!(f, "\n  {name:<width$}  {:>4}ms", duration.as_millis())?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    #[test]
+    fn display() {
+        use super::BuildReport;
+        use std::time::Duration;
+
+        let mut report = BuildReport::start();
+        report.entries_rendered = 12;
+        report.images_cached = 3;
+        report.feeds = 1;
+        report.phases = vec![
+            ("parse", Duration::from_millis(5)),
+            ("images", Duration::from_millis(1234)),
+        ];
+
+        assert_eq!(
+            report.to_string(),
+            "Build summary:
+  entries rendered      12
+  entries failed         0
+  images processed       0
+  images cached          3
+  template pages         0
+  feeds                  1
+  parse                5ms
+  images            1234ms"
+        );
+    }
+}
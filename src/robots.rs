@@ -0,0 +1,34 @@
+//! Generate a `robots.txt` for crawlers.
+
+/// Rules used when none are configured, allowing all crawlers everywhere.
+pub const DEFAULT_RULES: &str = "User-agent: *\nDisallow:\n";
+
+/// Render a `robots.txt` with the given rules, followed by a reference to the sitemap at the
+/// absolute URL `sitemap_url`, if any.
+pub fn render(rules: &str, sitemap_url: Option<&str>) -> String {
+    let mut buf = rules.trim_end().to_owned();
+    buf.push('\n');
+    if let Some(sitemap_url) = sitemap_url {
+        buf.push_str("\nSitemap: ");
+        buf.push_str(sitemap_url);
+        buf.push('\n');
+    }
+    buf
+}
+
+#[cfg(test)]
+mod test {
+    #[test]
+    fn render() {
+        use super::{render, DEFAULT_RULES};
+
+        assert_eq!(
+            render(DEFAULT_RULES, Some("https://example.com/sitemap.xml")),
+            "User-agent: *\nDisallow:\n\nSitemap: https://example.com/sitemap.xml\n"
+        );
+        assert_eq!(
+            render("User-agent: *\nDisallow: /drafts/\n\n", None),
+            "User-agent: *\nDisallow: /drafts/\n"
+        );
+    }
+}
@@ -0,0 +1,368 @@
+//! Sanitize untrusted raw HTML, keeping only safe tags and attributes.
+//!
+//! Raw HTML in entries is often a fragment rather than a complete element, e.g., an opening tag in
+//! one raw inline and the closing tag in another. The sanitizer therefore works tag by tag, without
+//! balancing tags.
+
+/// Tags that are kept. Other tags are removed, keeping their content.
+const ALLOWED_TAGS: &[&str] = &[
+    "a",
+    "abbr",
+    "audio",
+    "b",
+    "bdi",
+    "bdo",
+    "blockquote",
+    "br",
+    "caption",
+    "cite",
+    "code",
+    "col",
+    "colgroup",
+    "dd",
+    "del",
+    "details",
+    "dfn",
+    "div",
+    "dl",
+    "dt",
+    "em",
+    "figcaption",
+    "figure",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "hr",
+    "i",
+    "img",
+    "ins",
+    "kbd",
+    "li",
+    "mark",
+    "ol",
+    "p",
+    "picture",
+    "pre",
+    "q",
+    "rp",
+    "rt",
+    "ruby",
+    "s",
+    "samp",
+    "small",
+    "source",
+    "span",
+    "strong",
+    "sub",
+    "summary",
+    "sup",
+    "table",
+    "tbody",
+    "td",
+    "tfoot",
+    "th",
+    "thead",
+    "time",
+    "tr",
+    "track",
+    "u",
+    "ul",
+    "var",
+    "video",
+    "wbr",
+    // SVG
+    "circle",
+    "clippath",
+    "defs",
+    "desc",
+    "ellipse",
+    "g",
+    "line",
+    "lineargradient",
+    "mask",
+    "path",
+    "polygon",
+    "polyline",
+    "radialgradient",
+    "rect",
+    "stop",
+    "svg",
+    "symbol",
+    "text",
+    "title",
+    "tspan",
+    "use",
+];
+
+/// Tags that are removed along with their content.
+const REMOVED_WITH_CONTENT: &[&str] = &[
+    "applet", "iframe", "noscript", "object", "script", "style", "template", "textarea",
+];
+
+/// Attributes whose values are URLs. They are removed if their URL has a scheme other than those
+/// in [`ALLOWED_SCHEMES`].
+const URL_ATTRIBUTES: &[&str] = &[
+    "action",
+    "background",
+    "cite",
+    "data",
+    "formaction",
+    "href",
+    "longdesc",
+    "poster",
+    "src",
+    "srcset",
+    "xlink:href",
+];
+
+const ALLOWED_SCHEMES: &[&str] = &["http", "https", "mailto", "tel"];
+
+/// Decode the character references of `value` that can be used to hide a URL's scheme. Like
+/// browsers, numeric references do not need a terminating `;`.
+fn decode_references(value: &str) -> String {
+    let mut decoded = String::with_capacity(value.len());
+    let mut rest = value;
+    while let Some(idx) = rest.find('&') {
+        decoded.push_str(&rest[..idx]);
+        rest = &rest[idx + 1..];
+
+        if let Some(number) = rest.strip_prefix('#') {
+            let (radix, digits) = match number.strip_prefix(['x', 'X']) {
+                Some(hex) => (16, hex),
+                None => (10, number),
+            };
+            let end = digits.find(|c: char| !c.is_digit(radix)).unwrap_or(digits.len());
+            if let Some(char) = u32::from_str_radix(&digits[..end], radix).ok().and_then(char::from_u32) {
+                decoded.push(char);
+                let after = &digits[end..];
+                rest = after.strip_prefix(';').unwrap_or(after);
+                continue;
+            }
+        }
+        let named = [("colon;", ':'), ("Tab;", '\t'), ("NewLine;", '\n')]
+            .into_iter()
+            .find(|(name, _)| rest.starts_with(name));
+        match named {
+            Some((name, char)) => {
+                decoded.push(char);
+                rest = &rest[name.len()..];
+            }
+            None => decoded.push('&'),
+        }
+    }
+    decoded.push_str(rest);
+    decoded
+}
+
+/// Whether `url` is relative or has an allowed scheme. Browsers ignore whitespace and control
+/// characters in schemes, so they are ignored here as well.
+fn is_safe_url(url: &str) -> bool {
+    let url: String = decode_references(url)
+        .chars()
+        .filter(|c| !c.is_ascii_whitespace() && !c.is_control())
+        .collect();
+    match url.find([':', '/', '?', '#']) {
+        Some(idx) if url[idx..].starts_with(':') => {
+            let scheme = &url[..idx];
+            ALLOWED_SCHEMES
+                .iter()
+                .any(|allowed| scheme.eq_ignore_ascii_case(allowed))
+        }
+        _ => true,
+    }
+}
+
+fn is_safe_attribute(name: &str, value: &str) -> bool {
+    if name.starts_with("on") || name == "style" || name == "srcdoc" {
+        return false;
+    }
+    if name == "srcset" {
+        return value
+            .split(',')
+            .all(|candidate| is_safe_url(candidate.split_whitespace().next().unwrap_or("")));
+    }
+    !URL_ATTRIBUTES.contains(&name) || is_safe_url(value)
+}
+
+/// Parse the attributes of a tag starting at `rest`, up to and including the closing `>`. Returns
+/// the attributes as `(name, value)`, whether the tag is self-closing, and the remaining input. If
+/// the tag is not closed, `None` is returned.
+fn parse_attributes(mut rest: &str) -> Option<(Vec<(String, Option<&str>)>, bool, &str)> {
+    let mut attributes = vec![];
+    loop {
+        rest = rest.trim_start();
+        if let Some(after) = rest.strip_prefix('>') {
+            return Some((attributes, false, after));
+        }
+        if let Some(after) = rest.strip_prefix("/>") {
+            return Some((attributes, true, after));
+        }
+        if rest.is_empty() {
+            return None;
+        }
+
+        let name_end = rest
+            .find(|c: char| c.is_ascii_whitespace() || c == '=' || c == '>' || c == '/')
+            .unwrap_or(rest.len())
+            // A stray `/` or `=` is skipped
+            .max(1);
+        let name = rest[..name_end].to_ascii_lowercase();
+        rest = rest[name_end..].trim_start();
+
+        let value = match rest.strip_prefix('=') {
+            Some(after) => {
+                let after = after.trim_start();
+                match after.chars().next() {
+                    Some(quote @ ('"' | '\'')) => {
+                        let end = after[1..].find(quote)?;
+                        rest = &after[1 + end + 1..];
+                        Some(&after[1..1 + end])
+                    }
+                    _ => {
+                        let end = after
+                            .find(|c: char| c.is_ascii_whitespace() || c == '>')
+                            .unwrap_or(after.len());
+                        rest = &after[end..];
+                        Some(&after[..end])
+                    }
+                }
+            }
+            None => None,
+        };
+        // Attributes with unusual names are dropped, rather than risking writing invalid markup
+        if name.starts_with(|c: char| c.is_ascii_alphabetic())
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | ':' | '.'))
+        {
+            attributes.push((name, value));
+        }
+    }
+}
+
+/// Sanitize the HTML fragment `html`. Comments, disallowed tags, event handler and style
+/// attributes, and URLs with schemes other than `http`, `https`, `mailto` and `tel` are removed.
+pub fn sanitize_html(html: &str) -> String {
+    let mut sanitized = String::with_capacity(html.len());
+
+    let mut rest = html;
+    while let Some(idx) = rest.find('<') {
+        sanitized.push_str(&rest[..idx]);
+        rest = &rest[idx..];
+
+        // Comments, doctypes and processing instructions
+        if let Some(after) = rest.strip_prefix("<!--") {
+            rest = after.find("-->").map_or("", |end| &after[end + "-->".len()..]);
+            continue;
+        }
+        if rest.starts_with("<!") || rest.starts_with("<?") {
+            rest = rest.find('>').map_or("", |end| &rest[end + 1..]);
+            continue;
+        }
+
+        let (closing, after) = match rest[1..].strip_prefix('/') {
+            Some(after) => (true, after),
+            None => (false, &rest[1..]),
+        };
+        let name_end = after
+            .find(|c: char| !c.is_ascii_alphanumeric() && c != '-' && c != ':')
+            .unwrap_or(after.len());
+        if !after.starts_with(|c: char| c.is_ascii_alphabetic()) {
+            // Not a tag
+            sanitized.push_str("&lt;");
+            rest = &rest[1..];
+            continue;
+        }
+        let name = after[..name_end].to_ascii_lowercase();
+
+        let Some((attributes, self_closing, after)) = parse_attributes(&after[name_end..]) else {
+            // An unclosed tag is dropped, along with everything after it
+            rest = "";
+            break;
+        };
+        rest = after;
+
+        if REMOVED_WITH_CONTENT.contains(&name.as_str()) {
+            if !closing {
+                let closing_tag = format!("</{name}");
+                rest = match rest.to_ascii_lowercase().find(&closing_tag) {
+                    Some(end) => {
+                        let after = &rest[end..];
+                        after.find('>').map_or("", |end| &after[end + 1..])
+                    }
+                    None => "",
+                };
+            }
+            continue;
+        }
+        if !ALLOWED_TAGS.contains(&name.as_str()) {
+            continue;
+        }
+
+        sanitized.push('<');
+        if closing {
+            sanitized.push('/');
+        }
+        sanitized.push_str(&name);
+        if !closing {
+            for (attribute, value) in attributes {
+                if !is_safe_attribute(&attribute, value.unwrap_or("")) {
+                    continue;
+                }
+                sanitized.push(' ');
+                sanitized.push_str(&attribute);
+                if let Some(value) = value {
+                    sanitized.push_str("=\"");
+                    sanitized.push_str(&value.replace('"', "&quot;").replace('<', "&lt;"));
+                    sanitized.push('"');
+                }
+            }
+        }
+        if self_closing {
+            sanitized.push_str(" /");
+        }
+        sanitized.push('>');
+    }
+    sanitized.push_str(rest);
+
+    sanitized
+}
+
+#[cfg(test)]
+mod test {
+    #[test]
+    fn sanitize_html() {
+        use super::sanitize_html;
+
+        assert_eq!(
+            sanitize_html(r#"<p class="note">Hello <b>world</b>!</p>"#),
+            r#"<p class="note">Hello <b>world</b>!</p>"#
+        );
+        assert_eq!(
+            sanitize_html("<div>a<script>alert('<b>')</script>b</div>"),
+            "<div>ab</div>"
+        );
+        assert_eq!(
+            sanitize_html(r#"<img src="cat.png" onerror="alert(1)" alt='A "cat"'>"#),
+            r#"<img src="cat.png" alt="A &quot;cat&quot;">"#
+        );
+        assert_eq!(
+            sanitize_html(r#"<a href="java&#x09;script&colon;alert(1)">a</a> <a HREF=https://example.com>b</a>"#),
+            r#"<a>a</a> <a href="https://example.com">b</a>"#
+        );
+        assert_eq!(
+            sanitize_html(r#"<a href="javascript&#58alert(1)" / title="x">a</a><a href="/about#team">b</a>"#),
+            r#"<a title="x">a</a><a href="/about#team">b</a>"#
+        );
+        assert_eq!(
+            sanitize_html("<form action=/x><input name=q></form><!-- comment -->1 < 2<br/>"),
+            "1 &lt; 2<br />"
+        );
+        assert_eq!(sanitize_html("<span>open"), "<span>open");
+        assert_eq!(sanitize_html("</span>"), "</span>");
+        assert_eq!(sanitize_html("a <b unclosed"), "a ");
+    }
+}
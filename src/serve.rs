@@ -0,0 +1,298 @@
+//! A minimal HTTP server to preview the built site locally.
+
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::Duration;
+
+/// The path of the server-sent events endpoint signalling live reloads.
+const LIVE_RELOAD_PATH: &str = "/__sprokkel/live-reload";
+
+const LIVE_RELOAD_SCRIPT: &str =
+    r#"<script>new EventSource("/__sprokkel/live-reload").onmessage = () => location.reload();</script>"#;
+
+/// Tracks site rebuilds, such that connected browsers can be told to reload.
+pub struct LiveReload {
+    build_id: Mutex<u64>,
+    rebuilt: Condvar,
+}
+
+impl LiveReload {
+    pub fn new() -> Self {
+        LiveReload {
+            build_id: Mutex::new(0),
+            rebuilt: Condvar::new(),
+        }
+    }
+
+    /// Signal that a rebuild has completed.
+    pub fn rebuilt(&self) {
+        let mut build_id = self.build_id.lock().unwrap();
+        *build_id += 1;
+        self.rebuilt.notify_all();
+    }
+
+    fn current(&self) -> u64 {
+        *self.build_id.lock().unwrap()
+    }
+
+    /// Wait until the build id differs from `build_id`, or until the timeout passes. Returns the
+    /// current build id.
+    fn wait(&self, build_id: u64, timeout: Duration) -> u64 {
+        let current = self.build_id.lock().unwrap();
+        let (current, _) = self
+            .rebuilt
+            .wait_timeout_while(current, timeout, |current| *current == build_id)
+            .unwrap();
+        *current
+    }
+}
+
+/// Serve the files in `root` over HTTP on `port`. If `live_reload` is given, HTML pages are served
+/// with a script that reloads the page when the site is rebuilt. This never returns unless binding
+/// to the port fails.
+pub fn serve(port: u16, root: impl AsRef<Path>, live_reload: Option<Arc<LiveReload>>) -> anyhow::Result<()> {
+    let root = root.as_ref().to_owned();
+    let listener = TcpListener::bind(("127.0.0.1", port))?;
+    log::info!("Serving site at http://localhost:{port}");
+
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(err) => {
+                log::warn!("Failed to accept connection: {err}");
+                continue;
+            }
+        };
+
+        let root = root.clone();
+        let live_reload = live_reload.clone();
+        std::thread::spawn(move || {
+            if let Err(err) = handle_connection(&root, live_reload.as_deref(), stream) {
+                log::debug!("Error while handling request: {err:?}");
+            }
+        });
+    }
+
+    Ok(())
+}
+
+fn handle_connection(root: &Path, live_reload: Option<&LiveReload>, mut stream: TcpStream) -> anyhow::Result<()> {
+    let mut reader = BufReader::new(&stream);
+
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+
+    // Skip the request headers
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 || header.trim_end().is_empty() {
+            break;
+        }
+    }
+
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().unwrap_or("");
+    let target = parts.next().unwrap_or("");
+
+    if !matches!(method, "GET" | "HEAD") {
+        return respond(
+            &mut stream,
+            "405 Method Not Allowed",
+            "text/plain",
+            b"Method not allowed",
+            true,
+        );
+    }
+    let send_body = method == "GET";
+
+    if let Some(live_reload) = live_reload {
+        if target == LIVE_RELOAD_PATH {
+            return stream_live_reload(live_reload, &mut stream);
+        }
+    }
+
+    let file = resolve_path(root, target).and_then(|path| Some((std::fs::read(&path).ok()?, path)));
+    match file {
+        Some((content, path)) => {
+            let content_type = content_type(&path);
+            let content = if live_reload.is_some() && content_type.starts_with("text/html") {
+                inject_live_reload_script(content)
+            } else {
+                content
+            };
+            respond(&mut stream, "200 OK", content_type, &content, send_body)
+        }
+        None => respond(&mut stream, "404 Not Found", "text/plain", b"Not found", send_body),
+    }
+}
+
+/// Keep the connection open as a server-sent event stream, sending an event every time the site is
+/// rebuilt. Returns once the client disconnects.
+fn stream_live_reload(live_reload: &LiveReload, stream: &mut TcpStream) -> anyhow::Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nCache-Control: no-cache\r\nConnection: keep-alive\r\n\r\n"
+    )?;
+    stream.flush()?;
+
+    let mut build_id = live_reload.current();
+    loop {
+        let current = live_reload.wait(build_id, Duration::from_secs(30));
+        if current == build_id {
+            // Keep-alive comment, this errors once the client has gone away
+            stream.write_all(b": ping\n\n")?;
+        } else {
+            build_id = current;
+            stream.write_all(b"data: reload\n\n")?;
+        }
+        stream.flush()?;
+    }
+}
+
+/// Insert the live reload script before the closing body tag, or at the end of the document if
+/// there is none.
+fn inject_live_reload_script(content: Vec<u8>) -> Vec<u8> {
+    let idx = content
+        .windows(b"</body>".len())
+        .rposition(|window| window.eq_ignore_ascii_case(b"</body>"))
+        .unwrap_or(content.len());
+
+    let mut injected = Vec::with_capacity(content.len() + LIVE_RELOAD_SCRIPT.len());
+    injected.extend_from_slice(&content[..idx]);
+    injected.extend_from_slice(LIVE_RELOAD_SCRIPT.as_bytes());
+    injected.extend_from_slice(&content[idx..]);
+    injected
+}
+
+fn respond(
+    stream: &mut TcpStream,
+    status: &str,
+    content_type: &str,
+    content: &[u8],
+    send_body: bool,
+) -> anyhow::Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 {status}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        content.len()
+    )?;
+    if send_body {
+        stream.write_all(content)?;
+    }
+    stream.flush()?;
+
+    Ok(())
+}
+
+/// Resolve a request target to a file in `root`. Directories resolve to their `index.html`, such
+/// that both `/foo/` and `/foo` (as produced when trimming `index.html` from URLs) resolve to
+/// `foo/index.html`.
+fn resolve_path(root: &Path, target: &str) -> Option<PathBuf> {
+    let target = target.split(['?', '#']).next().unwrap_or("");
+    let target = percent_decode(target)?;
+
+    let mut path = root.to_owned();
+    for component in Path::new(target.trim_start_matches('/')).components() {
+        match component {
+            Component::Normal(component) => path.push(component),
+            Component::CurDir => {}
+            _ => return None,
+        }
+    }
+
+    if path.is_dir() {
+        path.push("index.html");
+    }
+
+    path.is_file().then_some(path)
+}
+
+fn percent_decode(value: &str) -> Option<String> {
+    let mut bytes = Vec::with_capacity(value.len());
+    let mut iter = value.bytes();
+
+    while let Some(byte) = iter.next() {
+        if byte == b'%' {
+            let high = (iter.next()? as char).to_digit(16)?;
+            let low = (iter.next()? as char).to_digit(16)?;
+            bytes.push((high * 16 + low) as u8);
+        } else {
+            bytes.push(byte);
+        }
+    }
+
+    String::from_utf8(bytes).ok()
+}
+
+fn content_type(path: &Path) -> &'static str {
+    match path.extension().and_then(|extension| extension.to_str()) {
+        Some("html" | "htm") => "text/html; charset=utf-8",
+        Some("css") => "text/css; charset=utf-8",
+        Some("js") => "text/javascript; charset=utf-8",
+        Some("json") => "application/json",
+        Some("xml") => "application/xml",
+        Some("txt") => "text/plain; charset=utf-8",
+        Some("svg") => "image/svg+xml",
+        Some("png") => "image/png",
+        Some("jpg" | "jpeg") => "image/jpeg",
+        Some("gif") => "image/gif",
+        Some("webp") => "image/webp",
+        Some("avif") => "image/avif",
+        Some("ico") => "image/x-icon",
+        Some("woff") => "font/woff",
+        Some("woff2") => "font/woff2",
+        Some("pdf") => "application/pdf",
+        _ => "application/octet-stream",
+    }
+}
+
+#[cfg(test)]
+mod test {
+    #[test]
+    fn percent_decode() {
+        use super::percent_decode;
+
+        assert_eq!(percent_decode("/foo/bar").unwrap(), "/foo/bar");
+        assert_eq!(percent_decode("/foo%20bar").unwrap(), "/foo bar");
+        assert_eq!(percent_decode("/caf%C3%A9").unwrap(), "/café");
+        assert!(percent_decode("/foo%2").is_none());
+        assert!(percent_decode("/foo%zz").is_none());
+    }
+
+    #[test]
+    fn inject_live_reload_script() {
+        use super::{inject_live_reload_script, LIVE_RELOAD_SCRIPT};
+
+        assert_eq!(
+            &*inject_live_reload_script(b"<html><body><p>Hi</p></body></html>".to_vec()),
+            format!("<html><body><p>Hi</p>{LIVE_RELOAD_SCRIPT}</body></html>").as_bytes()
+        );
+        assert_eq!(
+            &*inject_live_reload_script(b"<p>Hi</p>".to_vec()),
+            format!("<p>Hi</p>{LIVE_RELOAD_SCRIPT}").as_bytes()
+        );
+    }
+
+    #[test]
+    fn resolve_path() {
+        use super::resolve_path;
+
+        let root = std::env::temp_dir().join(format!("sprokkel-serve-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(root.join("foo")).unwrap();
+        std::fs::write(root.join("index.html"), "").unwrap();
+        std::fs::write(root.join("foo").join("index.html"), "").unwrap();
+        std::fs::write(root.join("style.css"), "").unwrap();
+
+        assert_eq!(resolve_path(&root, "/"), Some(root.join("index.html")));
+        assert_eq!(resolve_path(&root, "/foo/"), Some(root.join("foo").join("index.html")));
+        assert_eq!(resolve_path(&root, "/foo"), Some(root.join("foo").join("index.html")));
+        assert_eq!(resolve_path(&root, "/style.css?v=1"), Some(root.join("style.css")));
+        assert_eq!(resolve_path(&root, "/missing"), None);
+        assert_eq!(resolve_path(&root, "/foo/../style.css"), None);
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
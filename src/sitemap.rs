@@ -0,0 +1,71 @@
+//! Generate a sitemap of the pages of the site.
+
+use crate::{types, utils::escape_xml};
+
+/// A page to list in the sitemap.
+pub struct Url {
+    /// Absolute URL of the page.
+    pub location: String,
+    pub last_modified: Option<(types::Date, Option<types::Time>)>,
+}
+
+/// Render a sitemap following the sitemaps.org protocol. Times without a UTC offset are taken to
+/// be at `default_offset`.
+pub fn render(urls: &[Url], default_offset: types::UtcOffset) -> String {
+    let mut buf = String::new();
+    buf.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    buf.push_str("<urlset xmlns=\"http://www.sitemaps.org/schemas/sitemap/0.9\">\n");
+
+    for url in urls {
+        buf.push_str("<url>\n<loc>");
+        escape_xml(&mut buf, &url.location);
+        buf.push_str("</loc>\n");
+        if let Some((date, time)) = url.last_modified {
+            buf.push_str("<lastmod>");
+            buf.push_str(&date.rfc3339(time, default_offset));
+            buf.push_str("</lastmod>\n");
+        }
+        buf.push_str("</url>\n");
+    }
+
+    buf.push_str("</urlset>\n");
+    buf
+}
+
+#[cfg(test)]
+mod test {
+    #[test]
+    fn render() {
+        use super::Url;
+        use crate::types::{Date, Time, UtcOffset};
+
+        let sitemap = super::render(
+            &[
+                Url {
+                    location: "https://example.com".to_owned(),
+                    last_modified: None,
+                },
+                Url {
+                    location: "https://example.com/posts/fish&chips".to_owned(),
+                    last_modified: Some((Date::new(2024, 4, 16), Some(Time::new(9, 40, 32)))),
+                },
+            ],
+            UtcOffset(60),
+        );
+
+        assert_eq!(
+            sitemap,
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<urlset xmlns="http://www.sitemaps.org/schemas/sitemap/0.9">
+<url>
+<loc>https://example.com</loc>
+</url>
+<url>
+<loc>https://example.com/posts/fish&amp;chips</loc>
+<lastmod>2024-04-16T09:40:32+01:00</lastmod>
+</url>
+</urlset>
+"#
+        );
+    }
+}
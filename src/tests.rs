@@ -10,10 +10,10 @@ mod djot_markdown_equal {
         let markdown = markdown::parse(markdown);
 
         let mut dhtml = String::new();
-        ir_markup::push_html(&mut dhtml, djot, &HashMap::new()).unwrap();
+        ir_markup::push_html(&mut dhtml, djot, &HashMap::new(), &Default::default()).unwrap();
 
         let mut mhtml = String::new();
-        ir_markup::push_html(&mut mhtml, markdown, &HashMap::new()).unwrap();
+        ir_markup::push_html(&mut mhtml, markdown, &HashMap::new(), &Default::default()).unwrap();
 
         assert_eq!(dhtml, mhtml);
     }
@@ -39,6 +39,20 @@ mod djot_markdown_equal {
         );
     }
 
+    #[test]
+    fn task_list() {
+        djot_markdown_equal(
+            r#"
+- [ ] unchecked
+- [x] checked
+"#,
+            r#"
+- [ ] unchecked
+- [x] checked
+"#,
+        );
+    }
+
     #[test]
     fn doc() {
         djot_markdown_equal(
@@ -68,7 +82,7 @@ Some features:
 ...
 "#,
             r#"
-# A document { #A-document }
+# A document
 
 With a paragraph
 [containing a link](https://example.com)
@@ -88,10 +102,50 @@ Some features:
 1. "smart punctuation"
 1. 'other smart punctuation'
 
-## And a subheading { #And-a-subheading }
+## And a subheading
 
 ...
 "#,
         );
     }
 }
+
+mod title {
+    use std::collections::HashMap;
+
+    use crate::{djot, ir_markup, markdown};
+
+    fn title<'s>(events: impl Iterator<Item = ir_markup::Event<'s>>) -> (Option<String>, String) {
+        let mut events: Vec<_> = events.collect();
+        let title = ir_markup::parse_and_render_title(&mut events).unwrap();
+
+        let mut rest = String::new();
+        ir_markup::push_html(&mut rest, events.into_iter(), &HashMap::new(), &Default::default()).unwrap();
+
+        (title, rest)
+    }
+
+    #[test]
+    fn djot_and_markdown_titles() {
+        let (djot_title, djot_rest) = title(djot::parse("# The title\n\nA paragraph\n"));
+        let (markdown_title, markdown_rest) = title(markdown::parse("# The title\n\nA paragraph\n"));
+
+        assert_eq!(djot_title.as_deref(), Some("The title"));
+        assert_eq!(markdown_title.as_deref(), Some("The title"));
+
+        assert_eq!(
+            djot_rest,
+            "<section id=\"The-title\">\n<p>A paragraph</p>\n</section>\n"
+        );
+        assert_eq!(markdown_rest, djot_rest);
+    }
+
+    #[test]
+    fn no_title() {
+        let (djot_title, _) = title(djot::parse("A paragraph\n"));
+        let (markdown_title, _) = title(markdown::parse("A paragraph\n"));
+
+        assert!(djot_title.is_none());
+        assert!(markdown_title.is_none());
+    }
+}
@@ -7,7 +7,7 @@ use std::{
 
 use crate::{utils, Ctx};
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, serde::Serialize)]
 pub struct Date {
     pub year: u16,
     pub month: u8,
@@ -18,27 +18,160 @@ impl Date {
     pub fn new(year: u16, month: u8, day: u8) -> Self {
         Date { year, month, day }
     }
+
+    /// Format this date and an optional time as an RFC 3339 date-time, e.g.
+    /// `2024-04-16T09:40:32Z`. `default_offset` is used if the time has no UTC offset.
+    pub fn rfc3339(self, time: Option<Time>, default_offset: UtcOffset) -> String {
+        let time = time.unwrap_or(Time::new(0, 0, 0));
+        format!(
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}{}",
+            self.year,
+            self.month,
+            self.day,
+            time.hour,
+            time.minute,
+            time.second,
+            time.offset.unwrap_or(default_offset).rfc3339(),
+        )
+    }
+
+    /// The UTC date and time at `duration` after the Unix epoch.
+    pub fn from_unix_time(duration: std::time::Duration) -> (Self, Time) {
+        let seconds = duration.as_secs();
+        let time = Time::new(
+            (seconds / 3600 % 24) as u8,
+            (seconds / 60 % 60) as u8,
+            (seconds % 60) as u8,
+        )
+        .with_offset(UtcOffset(0));
+
+        // Howard Hinnant's `civil_from_days`
+        let days = (seconds / 86400) as i64 + 719468;
+        let era = days.div_euclid(146097);
+        let day_of_era = days.rem_euclid(146097);
+        let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let shifted_month = (5 * day_of_year + 2) / 153;
+        let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+        let month = if shifted_month < 10 {
+            shifted_month + 3
+        } else {
+            shifted_month - 9
+        };
+        let year = year_of_era + era * 400 + (month <= 2) as i64;
+
+        (Date::new(year as u16, month as u8, day as u8), time)
+    }
+
+    /// Day of the week, where 0 is Sunday.
+    pub fn weekday(self) -> u8 {
+        // Sakamoto's method
+        const OFFSETS: [i64; 12] = [0, 3, 2, 5, 0, 3, 5, 1, 4, 6, 2, 4];
+        let year = self.year as i64 - (self.month < 3) as i64;
+        let month_offset = OFFSETS[(self.month.clamp(1, 12) - 1) as usize];
+        (year + year.div_euclid(4) - year.div_euclid(100) + year.div_euclid(400) + month_offset + self.day as i64)
+            .rem_euclid(7) as u8
+    }
 }
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, serde::Serialize)]
 pub struct Time {
     pub hour: u8,
     pub minute: u8,
     pub second: u8,
+    /// The offset from UTC, if given. Times are compared without taking the offset into account.
+    pub offset: Option<UtcOffset>,
 }
 
 impl Time {
     pub fn new(hour: u8, minute: u8, second: u8) -> Self {
-        Time { hour, minute, second }
+        Time {
+            hour,
+            minute,
+            second,
+            offset: None,
+        }
+    }
+
+    pub fn with_offset(self, offset: UtcOffset) -> Self {
+        Time {
+            offset: Some(offset),
+            ..self
+        }
+    }
+}
+
+/// An offset from UTC in minutes, e.g., `+02:00`. Parsed from `Z` or `±HH:MM`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, serde::Serialize, serde::Deserialize)]
+#[serde(try_from = "String")]
+pub struct UtcOffset(pub i16);
+
+impl UtcOffset {
+    /// Format as in RFC 3339, e.g., `Z` or `+02:00`.
+    pub fn rfc3339(self) -> String {
+        if self.0 == 0 {
+            return "Z".to_owned();
+        }
+        let sign = if self.0 < 0 { '-' } else { '+' };
+        let minutes = self.0.unsigned_abs();
+        format!("{sign}{:02}:{:02}", minutes / 60, minutes % 60)
+    }
+
+    /// Format as in RFC 822, e.g., `+0200`.
+    pub fn rfc822(self) -> String {
+        let sign = if self.0 < 0 { '-' } else { '+' };
+        let minutes = self.0.unsigned_abs();
+        format!("{sign}{:02}{:02}", minutes / 60, minutes % 60)
+    }
+}
+
+impl std::str::FromStr for UtcOffset {
+    type Err = ();
+
+    fn from_str(value: &str) -> Result<Self, ()> {
+        if matches!(value, "Z" | "z") {
+            return Ok(UtcOffset(0));
+        }
+
+        let sign = match value.get(..1) {
+            Some("+") => 1,
+            Some("-") => -1,
+            _ => return Err(()),
+        };
+        let (hours, minutes) = value[1..].split_once(':').ok_or(())?;
+        if hours.len() != 2 || minutes.len() != 2 {
+            return Err(());
+        }
+        let hours: i16 = hours.parse().map_err(|_| ())?;
+        let minutes: i16 = minutes.parse().map_err(|_| ())?;
+        if hours > 23 || minutes > 59 {
+            return Err(());
+        }
+
+        Ok(UtcOffset(sign * (hours * 60 + minutes)))
+    }
+}
+
+impl TryFrom<String> for UtcOffset {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, String> {
+        value
+            .parse()
+            .map_err(|_| format!("expected a UTC offset such as \"Z\" or \"+02:00\", got {value:?}"))
     }
 }
 
-fn parse_date_time(value: &str) -> Result<(Date, Option<Time>), ()> {
+/// Parse a date in the format `yyyy-mm-dd`, optionally followed by a time as `Thhmmss`, which may
+/// be followed by a UTC offset as `Z` or `±HH:MM`.
+pub fn parse_date_time(value: &str) -> Result<(Date, Option<Time>), ()> {
     // formats:
     // 2024-04-26
     // or
     // 2024-04-26T123456
-    if !(value.len() == 10 || value.len() == 17) || !value.is_char_boundary(10) {
+    // or
+    // 2024-04-26T123456Z or 2024-04-26T123456+02:00
+    if !(value.len() == 10 || value.len() >= 17) || !value.is_char_boundary(10) {
         return Err(());
     }
 
@@ -50,17 +183,22 @@ fn parse_date_time(value: &str) -> Result<(Date, Option<Time>), ()> {
 
     let date = Date::new(year, month, day);
 
-    if value.len() == 17 {
+    if value.len() >= 17 {
         if !matches!(&value[10..11], "T" | "t") {
             return Err(());
         }
 
-        let time: u32 = value[11..].parse().map_err(|_| ())?;
+        let time: u32 = value.get(11..17).ok_or(())?.parse().map_err(|_| ())?;
         let hour = time / 1_00_00;
         let minute = (time - hour * 1_00_00) / 1_00;
         let second = time - hour * 1_00_00 - minute * 1_00;
+        let mut time = Time::new(hour as u8, minute as u8, second as u8);
+
+        if value.len() > 17 {
+            time = time.with_offset(value.get(17..).ok_or(())?.parse()?);
+        }
 
-        Ok((date, Some(Time::new(hour as u8, minute as u8, second as u8))))
+        Ok((date, Some(time)))
     } else {
         Ok((date, None))
     }
@@ -79,25 +217,78 @@ fn file_name_into_date_and_slug(file_name: &str) -> (Option<(Date, Option<Time>)
     }
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug, Hash)]
 pub struct Images {
     pub original: PathBuf,
     pub original_width: Option<u32>,
-    pub x_1536: Option<PathBuf>,
-    pub x_768: Option<PathBuf>,
+    pub original_height: Option<u32>,
+    /// Resized variants of the original as `(width, path)`, from largest to smallest.
+    pub variants: Vec<(u32, PathBuf)>,
+    /// The image encoded in alternative formats, in order of preference.
+    pub sources: Vec<ImageSource>,
 }
 
+impl Images {
+    /// A mid-size resized variant of the image as `(path, width, height)`, or the original if it
+    /// was not resized.
+    pub fn mid_size(&self) -> (&Path, Option<u32>, Option<u32>) {
+        match self.variants.get(self.variants.len() / 2) {
+            Some((width, path)) => {
+                let height = self
+                    .original_width
+                    .zip(self.original_height)
+                    .map(|(original_width, original_height)| {
+                        let (width, original_width) = (u64::from(*width), u64::from(original_width));
+                        ((u64::from(original_height) * width + original_width / 2) / original_width) as u32
+                    });
+                (path, Some(*width), height)
+            }
+            None => (&self.original, self.original_width, self.original_height),
+        }
+    }
+}
+
+/// An image representing an entry, e.g., for OpenGraph tags or card-style entry listings.
 #[derive(Debug, serde::Serialize)]
+pub struct CoverImage {
+    /// Absolute URL of the image.
+    pub url: String,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+}
+
+/// An image encoded in an alternative format, such as WebP.
+#[derive(Clone, Debug, Hash)]
+pub struct ImageSource {
+    pub mime_type: &'static str,
+    pub original: PathBuf,
+    /// Resized variants as `(width, path)`, from largest to smallest.
+    pub variants: Vec<(u32, PathBuf)>,
+}
+
+#[derive(Clone, Copy, Debug, serde::Serialize)]
 pub enum EntrySourceKind {
     Djot,
     CommonMark,
 }
 
+impl EntrySourceKind {
+    /// All file name extensions recognized as entry markup, with their source kinds.
+    pub const EXTENSIONS: &'static [(&'static str, Self)] =
+        &[("dj", EntrySourceKind::Djot), ("md", EntrySourceKind::CommonMark)];
+
+    /// Get the source kind of an entry from its file name extension.
+    pub fn from_extension(extension: &std::ffi::OsStr) -> Option<Self> {
+        Self::EXTENSIONS
+            .iter()
+            .find(|(known, _)| extension == *known)
+            .map(|&(_, source_kind)| source_kind)
+    }
+}
+
 #[derive(Debug, serde::Serialize)]
 pub struct EntryMeta {
     /// Date is set for entries whose filenames' start with a date in the format `yyyy-mm-dd`
-    #[serde(skip)]
-    pub sort_key: String,
     pub date: Option<Date>,
     pub time: Option<Time>,
     pub group: String,
@@ -128,24 +319,66 @@ pub struct EntryMeta {
     pub asset_url: String,
     /// e.g., `2024/foo-bar.html`
     pub permalink: String,
-}
-
-#[derive(Debug, serde::Serialize)]
-pub struct EntryMetaAndFrontMatter<'m> {
-    #[serde(flatten)]
-    pub meta: &'m EntryMeta,
-    #[serde(flatten)]
-    pub front_matter: &'m FrontMatter,
+    /// Hash of the entry's source file, set once the file is read.
+    #[serde(skip)]
+    pub source_hash: u64,
 }
 
 #[derive(Debug, serde::Serialize)]
 pub struct FrontMatter {
     pub title: String,
+    /// Entries are released unless set otherwise.
+    #[serde(serialize_with = "serialize_released")]
     pub released: Option<bool>,
+    /// Drafts are excluded from builds, regardless of `released`.
+    pub draft: bool,
+    /// Description set in front matter, preferred over the summary in feeds.
+    pub description: Option<String>,
+    /// Date and time set in front matter, overriding those from the file name.
+    #[serde(skip)]
+    pub date: Option<(Date, Option<Time>)>,
+    /// Slug set in front matter, overriding the slug from the file name.
+    #[serde(skip)]
+    pub slug: Option<String>,
+    /// Output file of the permalink set in front matter, overriding the output path derived from
+    /// the file name.
+    #[serde(skip)]
+    pub out_file: Option<PathBuf>,
+    /// Output files of the aliases set in front matter, which redirect to the entry.
+    #[serde(skip)]
+    pub aliases: Vec<PathBuf>,
+    /// Template set in front matter, e.g., `special` for `_special.html`, overriding the group's
+    /// template.
+    #[serde(skip)]
+    pub template: Option<String>,
+    pub tags: Vec<String>,
     #[serde(rename(serialize = "front_matter"))]
     pub extra: HashMap<String, minijinja::value::Value>,
 }
 
+fn serialize_released<S: serde::Serializer>(released: &Option<bool>, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_bool(released.unwrap_or(true))
+}
+
+impl FrontMatter {
+    /// Whether the entry is left out of production builds, as a draft or as a non-released entry.
+    pub fn is_draft(&self) -> bool {
+        self.draft || !self.released.unwrap_or(true)
+    }
+}
+
+/// A heading in an entry's table of contents.
+#[derive(Debug, PartialEq, Eq, serde::Serialize)]
+pub struct TocEntry {
+    /// The heading level, from 1 to 6.
+    pub level: u8,
+    pub id: String,
+    /// The heading's content, rendered to HTML.
+    pub title: String,
+    /// Headings of a lower level following this heading.
+    pub children: Vec<TocEntry>,
+}
+
 #[derive(Debug, serde::Serialize)]
 pub struct Entry<'m> {
     #[serde(flatten)]
@@ -154,14 +387,70 @@ pub struct Entry<'m> {
     pub front_matter: &'m FrontMatter,
     pub summary: String,
     pub rest: String,
+    pub toc: Vec<TocEntry>,
+    /// Number of words in the entry's text, excluding code blocks.
+    pub word_count: usize,
+    /// Estimated reading time in minutes.
+    pub reading_time: usize,
+    /// Whether the entry contains math, e.g., to only load math stylesheets when needed.
+    pub has_math: bool,
+    /// Whether the entry contains code blocks, e.g., to only load highlighting stylesheets when
+    /// needed.
+    pub has_code: bool,
+    /// Whether the entry is left out of production builds, e.g., for a badge in develop builds.
+    pub is_draft: bool,
+    /// Absolute URL of the entry, regardless of the configured link style, e.g., for a canonical
+    /// link.
+    pub canonical_url: String,
+    /// The first image in the entry, at a mid-size variant if it was resized.
+    pub cover_image: Option<CoverImage>,
+    /// Permalink of the entry before this one in its group's order.
+    pub prev_permalink: Option<String>,
+    /// Permalink of the entry after this one in its group's order.
+    pub next_permalink: Option<String>,
+}
+
+#[cfg(test)]
+impl<'m> Entry<'m> {
+    /// An entry without content in tests.
+    pub fn for_test(meta: &'m EntryMeta, front_matter: &'m FrontMatter) -> Self {
+        Entry {
+            meta,
+            front_matter,
+            summary: String::new(),
+            rest: String::new(),
+            toc: vec![],
+            word_count: 0,
+            reading_time: 0,
+            has_math: false,
+            has_code: false,
+            is_draft: false,
+            canonical_url: String::new(),
+            cover_image: None,
+            prev_permalink: None,
+            next_permalink: None,
+        }
+    }
 }
 
 impl EntryMeta {
+    /// The entry at `path` relative to an `entries` directory in tests.
+    #[cfg(test)]
+    pub fn for_test(ctx: &Ctx, path: &str) -> Self {
+        EntryMeta::entry_from_path(ctx, Path::new("entries"), &Path::new("entries").join(path)).unwrap()
+    }
+
     pub fn entry_from_path(ctx: &Ctx, path_prefix: &Path, path: &Path) -> anyhow::Result<Self> {
-        let source_kind = match path.extension().map(std::ffi::OsStr::as_encoded_bytes) {
-            Some(b"dj") => EntrySourceKind::Djot,
-            Some(b"md") => EntrySourceKind::CommonMark,
-            _ => anyhow::bail!("Expected entry filename extension to be .dj or .md"),
+        let source_kind = match path.extension().and_then(EntrySourceKind::from_extension) {
+            Some(source_kind) => source_kind,
+            None => anyhow::bail!(
+                "Expected entry filename extension to be one of: .{}",
+                EntrySourceKind::EXTENSIONS
+                    .iter()
+                    .map(|(extension, _)| *extension)
+                    .collect::<Vec<_>>()
+                    .join(", .")
+            ),
         };
 
         let mut path_without_prefix = path.strip_prefix(path_prefix)?.iter();
@@ -172,18 +461,37 @@ impl EntryMeta {
             .ok_or(anyhow!("path is not Unicode"))?
             .to_owned();
 
+        // The directories between the group directory and the entry. For entries that are in their
+        // own directory, that directory is not included, and its name is the entry's name.
+        let mut sub_dirs = path_without_prefix
+            .map(|component| {
+                component
+                    .to_str()
+                    .ok_or(anyhow!("expected UTF-8 path"))
+                    .map(str::to_owned)
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
         let file_name = {
-            let name = path_without_prefix
-                .next()
+            let name = sub_dirs
+                .pop()
                 .ok_or(anyhow!("expected path to have at least two components"))?;
-
-            Path::new(name)
+            let stem = Path::new(&name)
                 .file_stem()
                 .ok_or(anyhow!("path has no file name"))?
                 .to_str()
                 .ok_or(anyhow!("expected UTF-8 file name"))?
-                .to_owned()
+                .to_owned();
+
+            match sub_dirs.pop() {
+                Some(dir) if stem == "index" => dir,
+                Some(dir) => {
+                    sub_dirs.push(dir);
+                    stem
+                }
+                None => stem,
+            }
         };
+        let out_prefix: PathBuf = sub_dirs.iter().collect();
 
         let parent_dir = path.parent().ok_or(anyhow!("expected file with parent dir"))?;
 
@@ -191,7 +499,7 @@ impl EntryMeta {
         // directly in the group directory, strip the file suffix.
         let canonical_name = {
             let path = path.strip_prefix(path_prefix).unwrap_or(path);
-            let path: Cow<'_, Path> = if path.ends_with("index.dj") {
+            let path: Cow<'_, Path> = if path.file_stem() == Some(std::ffi::OsStr::new("index")) {
                 Cow::Borrowed(path.parent().unwrap())
             } else {
                 Cow::Owned(path.with_extension(""))
@@ -202,10 +510,16 @@ impl EntryMeta {
         let (dt, slug) = file_name_into_date_and_slug(&file_name);
         if let Some(dt) = dt {
             let (date, time) = dt;
-            let out_file = PathBuf::from(format!("{}", date.year)).join(slug).join("index.html");
-            let out_asset_dir = PathBuf::from(format!("{}", date.year)).join(slug);
+            // Entries nested in directories named after their date, e.g., `2024/04`, already have
+            // the year in their output path
+            let year = date.year.to_string();
+            let out_asset_dir = if sub_dirs.contains(&year) {
+                out_prefix.join(slug)
+            } else {
+                out_prefix.join(year).join(slug)
+            };
+            let out_file = out_asset_dir.join("index.html");
             Ok(EntryMeta {
-                sort_key: file_name.to_owned(),
                 group,
                 date: Some(date),
                 time,
@@ -214,20 +528,20 @@ impl EntryMeta {
                 file_path: path.to_owned(),
                 asset_dir: parent_dir.to_owned(),
                 canonical_name,
-                permalink: ctx.path_to_absolute_url(&out_file).expect("valid path"),
-                asset_url: ctx.path_to_absolute_url(&out_asset_dir).expect("valid path"),
+                permalink: ctx.path_to_url(&out_file).expect("valid path"),
+                asset_url: ctx.path_to_url(&out_asset_dir).expect("valid path"),
                 out_file,
                 out_asset_dir,
+                source_hash: 0,
             })
         } else {
             let out_file = if slug == "index" {
-                PathBuf::from(format!("{slug}.html"))
+                out_prefix.join(format!("{slug}.html"))
             } else {
-                PathBuf::from(slug).join("index.html")
+                out_prefix.join(slug).join("index.html")
             };
-            let out_asset_dir = PathBuf::from(slug);
+            let out_asset_dir = out_prefix.join(slug);
             Ok(EntryMeta {
-                sort_key: file_name.to_owned(),
                 group,
                 date: None,
                 time: None,
@@ -236,13 +550,79 @@ impl EntryMeta {
                 file_path: path.to_owned(),
                 asset_dir: parent_dir.to_owned(),
                 canonical_name,
-                permalink: ctx.path_to_absolute_url(&out_file).expect("valid path"),
-                asset_url: ctx.path_to_absolute_url(&out_asset_dir).expect("valid path"),
+                permalink: ctx.path_to_url(&out_file).expect("valid path"),
+                asset_url: ctx.path_to_url(&out_asset_dir).expect("valid path"),
                 out_file,
                 out_asset_dir,
+                source_hash: 0,
             })
         }
     }
+
+    /// Override the entry's output path with a slug or permalink output file set in front matter.
+    /// The permalink takes precedence.
+    pub fn override_out_path(&mut self, ctx: &Ctx, slug: Option<&str>, out_file: Option<&Path>) {
+        if let Some(out_file) = out_file {
+            self.out_file = out_file.to_owned();
+            self.out_asset_dir = match out_file.parent() {
+                Some(parent) if out_file.ends_with("index.html") && parent != Path::new("") => parent.to_owned(),
+                _ => out_file.with_extension(""),
+            };
+        } else if let Some(slug) = slug {
+            self.slug = slug.to_owned();
+            self.out_asset_dir = self.out_asset_dir.with_file_name(slug);
+            self.out_file = self.out_asset_dir.join("index.html");
+        } else {
+            return;
+        }
+
+        self.permalink = ctx.path_to_url(&self.out_file).expect("valid path");
+        self.asset_url = ctx.path_to_url(&self.out_asset_dir).expect("valid path");
+    }
+}
+
+/// Map a root-relative permalink to the output file it is served from. Permalinks ending in `.html`
+/// map to that file, other permalinks map to an `index.html` in a directory. E.g., both `/about/`
+/// and `/about` map to `about/index.html`.
+pub fn permalink_to_out_file(permalink: &str) -> anyhow::Result<PathBuf> {
+    let path = permalink
+        .strip_prefix('/')
+        .ok_or(anyhow!("permalink must start with a slash, e.g., \"/about/\""))?;
+
+    let mut out_file = PathBuf::new();
+    for component in path.split('/').filter(|component| !component.is_empty()) {
+        if component == "." || component == ".." {
+            anyhow::bail!("permalink must not contain `.` or `..`: {permalink}");
+        }
+        out_file.push(component);
+    }
+
+    if out_file.extension() != Some(std::ffi::OsStr::new("html")) {
+        out_file.push("index.html");
+    }
+    Ok(out_file)
+}
+
+/// Site-level metadata. Fields that are not configured are empty.
+#[derive(Debug, serde::Serialize)]
+pub struct SiteMeta {
+    pub title: String,
+    pub description: String,
+    pub author: String,
+    /// Arbitrary site-wide variables from the site config's `[extra]` table.
+    pub extra: HashMap<String, minijinja::value::Value>,
+}
+
+/// The entries tagged with a tag.
+#[derive(Debug, serde::Serialize)]
+pub struct Tag<'e> {
+    /// The URL-safe version of the tag name.
+    pub slug: String,
+    /// e.g., `tags/some-tag.html`
+    #[serde(skip)]
+    pub out_file: PathBuf,
+    pub permalink: String,
+    pub entries: Vec<&'e Entry<'e>>,
 }
 
 #[cfg(test)]
@@ -263,9 +643,119 @@ mod test {
         assert_eq!(file_name_into_date_and_slug(""), (None, ""));
     }
 
+    #[test]
+    fn entry_from_path() {
+        use super::EntryMeta;
+        use crate::Ctx;
+        use std::path::Path;
+
+        let ctx = Ctx::for_test("");
+        let entry = |path: &str| EntryMeta::for_test(&ctx, path);
+
+        let meta = entry("posts/2024-04-16_foo.dj");
+        assert_eq!(meta.group, "posts");
+        assert_eq!(meta.canonical_name, "posts/2024-04-16_foo");
+        assert_eq!(meta.out_file, Path::new("2024/foo/index.html"));
+
+        let meta = entry("posts/2024-04-16_foo/index.dj");
+        assert_eq!(meta.canonical_name, "posts/2024-04-16_foo");
+        assert_eq!(meta.out_file, Path::new("2024/foo/index.html"));
+        assert_eq!(meta.out_asset_dir, Path::new("2024/foo"));
+
+        let meta = entry("pages/index.md");
+        assert_eq!(meta.canonical_name, "pages");
+        assert_eq!(meta.out_file, Path::new("index.html"));
+
+        let meta = entry("posts/2024/04/foo.dj");
+        assert_eq!(meta.group, "posts");
+        assert_eq!(meta.slug, "foo");
+        assert_eq!(meta.canonical_name, "posts/2024/04/foo");
+        assert_eq!(meta.out_file, Path::new("2024/04/foo/index.html"));
+
+        let meta = entry("posts/2024/04/bar/index.dj");
+        assert_eq!(meta.canonical_name, "posts/2024/04/bar");
+        assert_eq!(meta.out_file, Path::new("2024/04/bar/index.html"));
+        assert_eq!(meta.asset_dir, Path::new("entries/posts/2024/04/bar"));
+
+        let meta = entry("posts/2024/04/2024-04-16_baz.dj");
+        assert_eq!(meta.slug, "baz");
+        assert_eq!(meta.out_file, Path::new("2024/04/baz/index.html"));
+
+        let meta = entry("posts/travel/2024-04-16_qux/index.dj");
+        assert_eq!(meta.out_file, Path::new("travel/2024/qux/index.html"));
+
+        let meta = entry("docs/guide/index.dj");
+        assert_eq!(meta.canonical_name, "docs/guide");
+        assert_eq!(meta.out_file, Path::new("guide/index.html"));
+
+        let mut meta = entry("posts/2024-04-16_foo.dj");
+        meta.override_out_path(&ctx, Some("bar"), None);
+        assert_eq!(meta.slug, "bar");
+        assert_eq!(meta.out_file, Path::new("2024/bar/index.html"));
+        assert_eq!(meta.permalink, "http://localhost:8080/2024/bar");
+        meta.override_out_path(&ctx, Some("baz"), Some(Path::new("about/index.html")));
+        assert_eq!(meta.out_file, Path::new("about/index.html"));
+        assert_eq!(meta.out_asset_dir, Path::new("about"));
+    }
+
+    #[test]
+    fn permalink_to_out_file() {
+        use super::permalink_to_out_file;
+        use std::path::Path;
+
+        assert_eq!(permalink_to_out_file("/about/").unwrap(), Path::new("about/index.html"));
+        assert_eq!(permalink_to_out_file("/about").unwrap(), Path::new("about/index.html"));
+        assert_eq!(permalink_to_out_file("/a/b.html").unwrap(), Path::new("a/b.html"));
+        assert_eq!(permalink_to_out_file("/").unwrap(), Path::new("index.html"));
+        assert!(permalink_to_out_file("about/").is_err());
+        assert!(permalink_to_out_file("/a/../b").is_err());
+    }
+
+    #[test]
+    fn rfc3339() {
+        use super::{Date, Time, UtcOffset};
+
+        assert_eq!(
+            Date::new(2024, 4, 16).rfc3339(None, UtcOffset(0)),
+            "2024-04-16T00:00:00Z"
+        );
+        assert_eq!(
+            Date::new(2000, 1, 1).rfc3339(Some(Time::new(9, 40, 32)), UtcOffset(0)),
+            "2000-01-01T09:40:32Z"
+        );
+        assert_eq!(
+            Date::new(2024, 4, 16).rfc3339(None, UtcOffset(120)),
+            "2024-04-16T00:00:00+02:00"
+        );
+        assert_eq!(
+            Date::new(2000, 1, 1).rfc3339(Some(Time::new(9, 40, 32).with_offset(UtcOffset(-330))), UtcOffset(120)),
+            "2000-01-01T09:40:32-05:30"
+        );
+    }
+
+    #[test]
+    fn from_unix_time() {
+        use super::{Date, Time, UtcOffset};
+        use std::time::Duration;
+
+        let utc = |hour, minute, second| Time::new(hour, minute, second).with_offset(UtcOffset(0));
+        assert_eq!(
+            Date::from_unix_time(Duration::ZERO),
+            (Date::new(1970, 1, 1), utc(0, 0, 0))
+        );
+        assert_eq!(
+            Date::from_unix_time(Duration::from_secs(951_782_400 + 3_661)),
+            (Date::new(2000, 2, 29), utc(1, 1, 1))
+        );
+        assert_eq!(
+            Date::from_unix_time(Duration::from_secs(1_713_260_432)),
+            (Date::new(2024, 4, 16), utc(9, 40, 32))
+        );
+    }
+
     #[test]
     fn parse_date_time() {
-        use super::{parse_date_time, Date, Time};
+        use super::{parse_date_time, Date, Time, UtcOffset};
 
         let (date, time) = parse_date_time("2024-04-16").unwrap();
         assert!(time.is_none());
@@ -293,12 +783,45 @@ mod test {
                 hour: 9,
                 minute: 40,
                 second: 32,
+                offset: None,
             })
         );
 
+        let (_, time) = parse_date_time("2024-04-16T094032Z").unwrap();
+        assert_eq!(time, Some(Time::new(9, 40, 32).with_offset(UtcOffset(0))));
+        let (_, time) = parse_date_time("2024-04-16T094032+02:00").unwrap();
+        assert_eq!(time, Some(Time::new(9, 40, 32).with_offset(UtcOffset(120))));
+        let (_, time) = parse_date_time("2024-04-16T094032-05:30").unwrap();
+        assert_eq!(time, Some(Time::new(9, 40, 32).with_offset(UtcOffset(-330))));
+        assert!(parse_date_time("2024-04-16T094032+2:00").is_err());
+        assert!(parse_date_time("2024-04-16T094032+24:00").is_err());
+        assert!(parse_date_time("2024-04-16Z").is_err());
+
         assert!(parse_date_time("2024-04-16T0940320").is_err());
         assert!(parse_date_time("2024-04-16T").is_err());
         assert!(parse_date_time("202-04-16").is_err());
         assert!(parse_date_time("20240416").is_err());
     }
+
+    #[test]
+    fn mid_size() {
+        use super::Images;
+        use std::path::{Path, PathBuf};
+
+        let mut images = Images {
+            original: PathBuf::from("cat.jpg"),
+            original_width: Some(2000),
+            original_height: Some(1001),
+            variants: vec![],
+            sources: vec![],
+        };
+        assert_eq!(images.mid_size(), (Path::new("cat.jpg"), Some(2000), Some(1001)));
+
+        images.variants = vec![
+            (1536, PathBuf::from("cat-1536.jpg")),
+            (768, PathBuf::from("cat-768.jpg")),
+            (384, PathBuf::from("cat-384.jpg")),
+        ];
+        assert_eq!(images.mid_size(), (Path::new("cat-768.jpg"), Some(768), Some(384)));
+    }
 }